@@ -0,0 +1,146 @@
+//! Backing off from a server that is failing
+
+use rand::RngExt;
+use std::time::Duration;
+
+/// Exponential backoff with jitter
+///
+/// Each consecutive failure doubles the delay, starting at `initial` and capped at `max`. The
+/// actual delay is picked randomly from the upper half of that, so that several clients
+/// waiting on the same outage don't all come back at once.
+#[derive(Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            failures: 0,
+        }
+    }
+
+    /// Records a failure and returns how long to wait before trying again
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(self.failures))
+            .min(self.max);
+        self.failures = self.failures.saturating_add(1);
+        let delay = rand::rng().random_range(delay / 2..=delay);
+        Duration::from_millis(delay.as_millis() as u64)
+    }
+
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+/// Keeps track of consecutive failed polls to tell when the server has become unreachable
+///
+/// The outage is only reported once it has lasted for `threshold` polls, and the recovery is
+/// only reported for an outage that was reported.
+#[derive(Debug)]
+pub struct Outage {
+    threshold: u32,
+    failures: u32,
+}
+
+impl Outage {
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            failures: 0,
+        }
+    }
+
+    /// Records a failed poll, returning whether the outage should be reported now
+    pub fn record_failure(&mut self) -> bool {
+        self.failures = self.failures.saturating_add(1);
+        self.failures == self.threshold
+    }
+
+    /// Records a successful poll, returning whether the recovery should be reported
+    pub fn record_success(&mut self) -> bool {
+        let recovered = self.failures >= self.threshold;
+        self.failures = 0;
+        recovered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The delays of consecutive failures, each of which is picked from the upper half
+    fn delays(backoff: &mut Backoff, count: usize) -> Vec<Duration> {
+        (0..count).map(|_| backoff.next_delay()).collect()
+    }
+
+    fn assert_within(delays: &[Duration], expected: &[u64]) {
+        for (delay, expected) in delays.iter().zip(expected) {
+            let expected = Duration::from_secs(*expected);
+            assert!(
+                expected / 2 <= *delay && *delay <= expected,
+                "{:?} isn't within the upper half of {:?}",
+                delay,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn delays_double_up_to_the_cap() {
+        let mut backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(60));
+        assert_within(&delays(&mut backoff, 6), &[10, 20, 40, 60, 60, 60]);
+    }
+
+    #[test]
+    fn many_failures_dont_overflow() {
+        let mut backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(60));
+        backoff.failures = u32::MAX;
+        assert_within(&delays(&mut backoff, 2), &[60, 60]);
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(60));
+        delays(&mut backoff, 4);
+        backoff.reset();
+        assert_within(&delays(&mut backoff, 2), &[10, 20]);
+    }
+
+    #[test]
+    fn outages_are_reported_once_past_the_threshold() {
+        let mut outage = Outage::new(3);
+        let reported: Vec<bool> = (0..5).map(|_| outage.record_failure()).collect();
+        assert_eq!(reported, [false, false, true, false, false]);
+        assert!(outage.record_success());
+        // Recovered already, so another success isn't a recovery
+        assert!(!outage.record_success());
+    }
+
+    #[test]
+    fn short_outages_arent_reported() {
+        let mut outage = Outage::new(3);
+        outage.record_failure();
+        outage.record_failure();
+        assert!(!outage.record_success());
+        // The count starts over after a success
+        outage.record_failure();
+        outage.record_failure();
+        assert!(outage.record_failure());
+        assert!(outage.record_success());
+    }
+
+    #[test]
+    fn a_zero_threshold_reports_the_first_failure() {
+        let mut outage = Outage::new(0);
+        assert!(outage.record_failure());
+        assert!(outage.record_success());
+    }
+}
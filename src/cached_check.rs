@@ -0,0 +1,61 @@
+//! Answers about the desktop that are remembered for a while, as the daemon asks for them
+//! several times a second while waiting for the next poll and asking may mean a D-Bus call or
+//! running a command
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A check whose answer is relied on for `ttl` before it is made again
+pub struct CachedCheck {
+    ttl: Duration,
+    check: fn() -> bool,
+    /// When the check was last made and what it answered then
+    last: Mutex<Option<(Instant, bool)>>,
+}
+
+impl CachedCheck {
+    pub const fn new(ttl: Duration, check: fn() -> bool) -> Self {
+        Self {
+            ttl,
+            check,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// The last answer if it is recent enough, otherwise a new one
+    pub fn get(&self) -> bool {
+        let mut last = self.last.lock().unwrap();
+        match *last {
+            Some((checked_at, answer)) if checked_at.elapsed() < self.ttl => answer,
+            _ => {
+                let answer = (self.check)();
+                *last = Some((Instant::now(), answer));
+                answer
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn answers_are_reused_until_they_expire() {
+        static CHECKS: AtomicUsize = AtomicUsize::new(0);
+        fn check() -> bool {
+            CHECKS.fetch_add(1, Ordering::SeqCst).is_multiple_of(2)
+        }
+
+        let cached = CachedCheck::new(Duration::from_secs(60), check);
+        assert!(cached.get());
+        assert!(cached.get());
+        assert_eq!(CHECKS.load(Ordering::SeqCst), 1);
+
+        let uncached = CachedCheck::new(Duration::ZERO, check);
+        assert!(!uncached.get());
+        assert!(uncached.get());
+        assert_eq!(CHECKS.load(Ordering::SeqCst), 3);
+    }
+}
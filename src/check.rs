@@ -0,0 +1,199 @@
+//! The `check` subcommand, which looks for mistakes in the config before the notifier is left
+//! running with it
+//!
+//! Mistakes in the config file itself, such as unknown options or invalid patterns, already
+//! stop the settings from loading. What is left to check is whether the credentials work and
+//! whether the feeds and categories that filters and rules name exist on the server.
+
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::config::Settings;
+use crate::filter::Matcher;
+use crate::source;
+
+/// Checks each server, printing the problems found and failing if there are any
+pub fn run(servers: &[Settings], config_path: Option<&Path>) -> Result<()> {
+    let contents = config_path.and_then(|path| fs::read_to_string(path).ok());
+    let mut problems = 0;
+    for settings in servers {
+        let name = settings.name.as_deref().unwrap_or(&settings.server);
+        for problem in check_server(settings, contents.as_deref()) {
+            println!("{}: {}", name, problem);
+            problems += 1;
+        }
+    }
+    match problems {
+        0 => {
+            println!("No problems found");
+            Ok(())
+        }
+        1 => Err(anyhow!("Found 1 problem")),
+        _ => Err(anyhow!("Found {} problems", problems)),
+    }
+}
+
+fn check_server(settings: &Settings, contents: Option<&str>) -> Vec<String> {
+    let client = match source::from_settings(settings) {
+        Ok(client) => client,
+        Err(e) => return vec![format!("{:#}", e)],
+    };
+    if let Err(e) = client.me() {
+        return match e.is_auth() {
+            true => vec!["The server rejected the credentials".to_string()],
+            false => vec![format!("Failed to reach the server: {}", e)],
+        };
+    }
+
+    let mut problems = vec![];
+    match client.feeds() {
+        Ok(Some(feeds)) => {
+            for (option, matcher) in matchers(settings, Kind::Feed) {
+                if !feeds
+                    .iter()
+                    .any(|feed| matcher.matches(feed.id, &feed.title))
+                {
+                    problems.push(unmatched(&option, Kind::Feed, matcher, contents));
+                }
+            }
+        }
+        Ok(None) => {}
+        Err(e) => problems.push(format!("Failed to list the feeds: {}", e)),
+    }
+    match client.categories() {
+        Ok(Some(categories)) => {
+            for (option, matcher) in matchers(settings, Kind::Category) {
+                if !categories
+                    .iter()
+                    .any(|category| matcher.matches(category.id, &category.title))
+                {
+                    problems.push(unmatched(&option, Kind::Category, matcher, contents));
+                }
+            }
+        }
+        Ok(None) => {}
+        Err(e) => problems.push(format!("Failed to list the categories: {}", e)),
+    }
+    problems
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Feed,
+    Category,
+}
+
+/// The matchers for feeds or categories throughout the settings, along with the option they
+/// were given in
+fn matchers(settings: &Settings, kind: Kind) -> Vec<(String, &Matcher)> {
+    let (include, exclude, rules) = match kind {
+        Kind::Feed => ("include_feeds", "exclude_feeds", "feeds"),
+        Kind::Category => ("include_categories", "exclude_categories", "categories"),
+    };
+    let pick = |feeds, categories| match kind {
+        Kind::Feed => feeds,
+        Kind::Category => categories,
+    };
+
+    let filter = &settings.filter;
+    let mut lists: Vec<(String, &[Matcher])> = vec![
+        (
+            include.to_string(),
+            pick(&filter.include_feeds, &filter.include_categories),
+        ),
+        (
+            exclude.to_string(),
+            pick(&filter.exclude_feeds, &filter.exclude_categories),
+        ),
+    ];
+    for (notifier, filter) in &settings.notifier_filters {
+        let notifier = format!("{:?}", notifier).to_lowercase();
+        let filter = &filter.entries;
+        lists.push((
+            format!("notifier_filters.{}.{}", notifier, include),
+            pick(&filter.include_feeds, &filter.include_categories),
+        ));
+        lists.push((
+            format!("notifier_filters.{}.{}", notifier, exclude),
+            pick(&filter.exclude_feeds, &filter.exclude_categories),
+        ));
+    }
+    for rule in &settings.open_rules {
+        lists.push((
+            format!("feed_open.{}", rules),
+            pick(&rule.feeds, &rule.categories),
+        ));
+    }
+    for rule in &settings.urgency_rules {
+        lists.push((
+            format!("feed_urgency.{}", rules),
+            pick(&rule.feeds, &rule.categories),
+        ));
+    }
+    for rule in &settings.priority_rules {
+        lists.push((
+            format!("feed_priority.{}", rules),
+            pick(&rule.feeds, &rule.categories),
+        ));
+    }
+    for rule in &settings.poll_rules {
+        lists.push((
+            format!("feed_poll_intervals.{}", rules),
+            pick(&rule.feeds, &rule.categories),
+        ));
+    }
+    for template in &settings.feed_templates {
+        lists.push((
+            format!("feed_templates.{}", rules),
+            pick(&template.feeds, &template.categories),
+        ));
+    }
+    lists
+        .into_iter()
+        .flat_map(|(option, list)| list.iter().map(move |matcher| (option.clone(), matcher)))
+        .collect()
+}
+
+fn unmatched(option: &str, kind: Kind, matcher: &Matcher, contents: Option<&str>) -> String {
+    let (matcher_text, line) = match matcher {
+        Matcher::Id(id) => (format!("id {}", id), contents.and_then(|c| find_id(c, *id))),
+        Matcher::Title(pattern) => (
+            format!("{:?}", pattern.as_str()),
+            contents.and_then(|c| find_pattern(c, pattern.as_str())),
+        ),
+    };
+    let what = match kind {
+        Kind::Feed => "feed",
+        Kind::Category => "category",
+    };
+    let problem = format!("{} matches no {}: {}", option, what, matcher_text);
+    match line {
+        Some(line) => format!("line {}: {}", line, problem),
+        None => problem,
+    }
+}
+
+/// The line of the config file where a title pattern is given, if it was given there rather
+/// than on the command line
+fn find_pattern(contents: &str, pattern: &str) -> Option<usize> {
+    let quoted = [format!("\"{}\"", pattern), format!("'{}'", pattern)];
+    find_line(contents, |line| {
+        quoted.iter().any(|quoted| line.contains(quoted.as_str()))
+    })
+}
+
+/// The line of the config file where an id is given in a list of feeds or categories
+fn find_id(contents: &str, id: u32) -> Option<usize> {
+    let id = id.to_string();
+    find_line(contents, |line| {
+        (line.contains("feeds") || line.contains("categories"))
+            && line
+                .split(|c: char| !c.is_ascii_digit())
+                .any(|number| number == id)
+    })
+}
+
+fn find_line(contents: &str, matches: impl Fn(&str) -> bool) -> Option<usize> {
+    contents.lines().position(matches).map(|index| index + 1)
+}
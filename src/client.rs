@@ -0,0 +1,145 @@
+//! The HTTP clients shared by all requests to the Miniflux server and to notification services
+
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::*;
+use log::warn;
+use reqwest::blocking::{Client, ClientBuilder};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use reqwest::{Certificate, Identity, NoProxy, Proxy};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::{Auth, Settings};
+
+/// The User-Agent sent unless another one is configured
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// How long a connection sits idle before TCP checks that the other end is still there, which
+/// has nothing to do with how long idle connections are kept for reuse
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Builds the client used for every request to the server.
+///
+/// The client is created once and reused, so that its connection pool can keep connections
+/// to the server open between polls instead of doing a new TCP and TLS handshake each time.
+/// Every request is authenticated with the configured credentials.
+pub fn build(settings: &Settings) -> Result<Client> {
+    let mut headers = HeaderMap::new();
+    let (name, value) = auth_header(&settings.auth)?;
+    headers.insert(name, value);
+    Ok(server_builder(settings)?.default_headers(headers).build()?)
+}
+
+/// The headers the client from [`build`] sends with every request, for sending requests
+/// without it
+pub fn server_headers(settings: &Settings) -> Result<HeaderMap> {
+    let mut headers = extra_headers(&settings.headers)?;
+    headers.insert(USER_AGENT, HeaderValue::from_str(&settings.user_agent)?);
+    let (name, value) = auth_header(&settings.auth)?;
+    headers.insert(name, value);
+    Ok(headers)
+}
+
+/// Builds a client for requests to servers that are logged in to rather than sent the
+/// credentials with every request
+pub fn build_login(settings: &Settings) -> Result<Client> {
+    Ok(server_builder(settings)?.build()?)
+}
+
+/// Builds a client for requests to other services, which must not carry the credentials for
+/// the Miniflux server
+pub fn build_external(settings: &Settings) -> Result<Client> {
+    Ok(builder(settings)?.build()?)
+}
+
+/// A builder for clients talking to the server, which may have a self-signed certificate or
+/// sit behind a reverse proxy asking for a client certificate
+fn server_builder(settings: &Settings) -> Result<ClientBuilder> {
+    let mut builder = builder(settings)?.default_headers(extra_headers(&settings.headers)?);
+    match (&settings.client_cert, &settings.client_key) {
+        (Some(cert), Some(key)) => builder = builder.identity(client_identity(cert, key)?),
+        (None, None) => {}
+        _ => {
+            return Err(anyhow!(
+                "--client-cert and --client-key have to be given together"
+            ))
+        }
+    }
+    if settings.insecure {
+        warn!(
+            "Not verifying the TLS certificate of {}, anyone on the network can read and change \
+             the requests to it",
+            settings.server
+        );
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder)
+}
+
+fn builder(settings: &Settings) -> Result<ClientBuilder> {
+    let mut builder = Client::builder()
+        .user_agent(settings.user_agent.as_str())
+        .timeout(settings.timeout)
+        .connect_timeout(settings.connect_timeout)
+        .pool_idle_timeout(settings.keep_alive)
+        .tcp_keepalive(TCP_KEEPALIVE);
+    // Without one, the proxy is taken from HTTPS_PROXY, ALL_PROXY and so on
+    if let Some(proxy) = &settings.proxy {
+        let proxy = Proxy::all(proxy)
+            .with_context(|| format!("Invalid proxy {}", proxy))?
+            .no_proxy(NoProxy::from_env());
+        builder = builder.proxy(proxy);
+    }
+    if let Some(path) = &settings.ca_cert {
+        let pem = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let cert = Certificate::from_pem(&pem)
+            .with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    Ok(builder)
+}
+
+/// Reads the client certificate and its PKCS#8 key from PEM files
+fn client_identity(cert: &Path, key: &Path) -> Result<Identity> {
+    let read =
+        |path: &Path| fs::read(path).with_context(|| format!("Failed to read {}", path.display()));
+    let (cert, key) = (read(cert)?, read(key)?);
+    #[cfg(feature = "native-tls")]
+    let identity = Identity::from_pkcs8_pem(&cert, &key);
+    #[cfg(not(feature = "native-tls"))]
+    let identity = Identity::from_pem(&[cert, key].concat());
+    identity.context("Invalid client certificate or key")
+}
+
+/// The configured extra headers, which are treated as secrets as they often carry tokens
+fn extra_headers(headers: &[(String, String)]) -> Result<HeaderMap> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = HeaderName::try_from(name.as_str())
+                .with_context(|| format!("Invalid header name {}", name))?;
+            let mut value = HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value for header {}", name))?;
+            value.set_sensitive(true);
+            Ok((name, value))
+        })
+        .collect()
+}
+
+fn auth_header(auth: &Auth) -> Result<(&'static str, HeaderValue)> {
+    let (name, value) = match auth {
+        Auth::ApiKey(api_key) => ("X-Auth-Token", api_key.clone()),
+        Auth::Basic { username, password } => (
+            "Authorization",
+            format!(
+                "Basic {}",
+                BASE64_STANDARD.encode(format!("{}:{}", username, password))
+            ),
+        ),
+    };
+    let mut value = HeaderValue::from_str(&value)?;
+    value.set_sensitive(true);
+    Ok((name, value))
+}
@@ -0,0 +1,51 @@
+//! Copying text to the clipboard through whichever clipboard tool is installed
+
+use anyhow::{anyhow, Context, Result};
+use std::env;
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+/// The tools tried in order on Wayland, each given the text on stdin
+const WAYLAND_TOOLS: &[&[&str]] = &[&["wl-copy"]];
+
+/// The tools tried in order otherwise
+const OTHER_TOOLS: &[&[&str]] = &[
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+    &["pbcopy"],
+];
+
+/// Puts `text` on the clipboard, using wl-copy on Wayland and xclip, xsel or pbcopy otherwise
+pub fn copy(text: &str) -> Result<()> {
+    let wayland: &[&[&str]] = match env::var_os("WAYLAND_DISPLAY") {
+        Some(_) => WAYLAND_TOOLS,
+        None => &[],
+    };
+    for tool in wayland.iter().chain(OTHER_TOOLS) {
+        match run(tool, text) {
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            result => return result.with_context(|| format!("Failed to run {}", tool[0])),
+        }
+    }
+    Err(anyhow!(
+        "No clipboard tool found, install wl-clipboard, xclip or xsel"
+    ))
+}
+
+fn run(tool: &[&str], text: &str) -> std::io::Result<()> {
+    let mut child = Command::new(tool[0])
+        .args(&tool[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    // The tools keep serving the clipboard from a process of their own once they have read
+    // the text, so this doesn't block for long
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("exited with {}", status)));
+    }
+    Ok(())
+}
@@ -0,0 +1,1418 @@
+//! The settings of the notifier, gathered from the command line, the environment and the
+//! config file
+//!
+//! Options given on the command line or through the environment take precedence over the
+//! config file, which in turn takes precedence over the built-in defaults.
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml::de::{DeTable, DeValue};
+use toml::Spanned;
+
+use crate::client::DEFAULT_USER_AGENT;
+use crate::control::{self, CtlArgs};
+use crate::filter::{EntryFilter, Matcher, NotifierFilter};
+use crate::history::HistoryArgs;
+use crate::logging::LogFormat;
+use crate::mark_all_read::MarkAllReadArgs;
+use crate::notify::desktop::{FallbackBackend, NotificationTimeout, UrgencyRule};
+use crate::notify::{NotifierKind, OpenRule, PriorityRule, SmtpSecurity};
+use crate::rules::MuteRule;
+use crate::schedule::QuietHours;
+use crate::scoped_poll::PollRule;
+use crate::service::InstallServiceArgs;
+use crate::source::SourceKind;
+use crate::status_bar::StatusFormat;
+use crate::template::FeedTemplate;
+
+/// The shortest allowed wait period between checks, to avoid hammering the server
+pub const MIN_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Parser, Debug, Clone)]
+pub struct Args {
+    /// What to do instead of watching the server
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// The fully qualified URL to the Miniflux server
+    server: Option<String>,
+
+    /// The wait period between each check in minutes [default: 60]
+    wait_period: Option<u64>,
+
+    /// The config file to read [default: ~/.config/miniflux-notifier/config.toml]
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// How log messages are written to stderr
+    #[clap(long, value_enum, default_value_t)]
+    pub log_format: LogFormat,
+
+    /// The most detailed messages to log (error, warn, info, debug or trace), taking
+    /// precedence over RUST_LOG [default: info]
+    #[clap(long)]
+    pub log_level: Option<log::LevelFilter>,
+
+    /// The wait period between each check (e.g. "30s" or "5m"), takes precedence over the
+    /// wait period in minutes
+    #[clap(long, value_parser = parse_poll_interval)]
+    poll_interval: Option<Duration>,
+
+    /// The wait period between each check while the computer is running on battery, as
+    /// reported by UPower, if longer than the usual one
+    #[clap(long, value_parser = parse_poll_interval)]
+    battery_poll_interval: Option<Duration>,
+
+    /// Up to how long to randomly add to each wait period and to wait before the first check,
+    /// so that several notifiers polling the same server don't all do so at once
+    #[clap(long, value_parser = humantime::parse_duration)]
+    poll_jitter: Option<Duration>,
+
+    /// How long to wait before retrying after the server failed to respond, doubling with
+    /// each further failure [default: 10s]
+    #[clap(long, value_parser = humantime::parse_duration)]
+    backoff_initial: Option<Duration>,
+
+    /// The longest to wait before retrying after the server failed to respond [default: 30m]
+    #[clap(long, value_parser = humantime::parse_duration)]
+    backoff_max: Option<Duration>,
+
+    /// Send a notification once this many polls in a row have failed, and another one when
+    /// the server can be reached again
+    #[clap(long)]
+    outage_threshold: Option<u32>,
+
+    /// Don't poll while NetworkManager reports no connection, and poll right away once there
+    /// is one again
+    #[clap(long)]
+    pause_when_offline: bool,
+
+    /// The API the server speaks [default: miniflux]
+    #[clap(long, value_enum)]
+    source: Option<SourceKind>,
+
+    /// The API key used to authenticate with the Miniflux server
+    #[clap(long, env)]
+    miniflux_api_key: Option<String>,
+
+    /// Read the API key from this file instead, which keeps it out of the process list
+    #[clap(long)]
+    api_key_file: Option<PathBuf>,
+
+    /// Read the API key from the OS keyring instead, stored under the service
+    /// "miniflux-notifier" with the server URL as the user name (requires the "keyring"
+    /// feature)
+    #[clap(long)]
+    keyring: bool,
+
+    /// The user to authenticate as with HTTP Basic authentication, for servers without API
+    /// keys
+    #[clap(long, env = "MINIFLUX_USERNAME")]
+    username: Option<String>,
+
+    /// The password of the user
+    #[clap(long, env = "MINIFLUX_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+
+    /// Read the password of the user from this file instead
+    #[clap(long)]
+    password_file: Option<PathBuf>,
+
+    /// Only fetch the entries published after the newest entry seen so far, instead of
+    /// fetching all unread entries and comparing them with the previous poll
+    #[clap(long)]
+    incremental: bool,
+
+    /// Poll the server a single time and exit, for running from cron or a systemd timer. The
+    /// state file is what tells which entries are new, the first run only records them.
+    /// Notifications can't be acted on once the notifier has exited.
+    #[clap(long)]
+    once: bool,
+
+    /// Print what would be done about each new entry and which rules it matched, instead of
+    /// sending notifications. The state file is read but not written.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// How many entries to request per page [default: 100]
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    page_size: Option<u32>,
+
+    /// The path of the entries endpoint, for deployments that expose the API under a
+    /// different route [default: /v1/entries]
+    #[clap(long, value_parser = parse_api_path)]
+    entries_path: Option<String>,
+
+    /// The path of the healthcheck endpoint [default: /healthcheck]
+    #[clap(long, value_parser = parse_api_path)]
+    healthcheck_path: Option<String>,
+
+    /// The Unix socket Miniflux listens on, to send the requests to instead of the server
+    /// URL, which is still used for links to Miniflux
+    #[clap(long)]
+    unix_socket: Option<PathBuf>,
+
+    /// How long a request to the server may take before it is given up on [default: 30s]
+    #[clap(long, value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// How long connecting to the server may take before it is given up on, which can be
+    /// shorter than --timeout to notice an unreachable server quickly [default: 10s]
+    #[clap(long, value_parser = humantime::parse_duration)]
+    connect_timeout: Option<Duration>,
+
+    /// How long to keep idle connections to the server open for reuse by the next poll
+    /// [default: 90s]
+    #[clap(long, value_parser = humantime::parse_duration)]
+    keep_alive: Option<Duration>,
+
+    /// The User-Agent header sent with every request [default: miniflux-notifier/<version>]
+    #[clap(long)]
+    user_agent: Option<String>,
+
+    /// The proxy to make requests through, e.g. "http://proxy.example.com:3128" or
+    /// "socks5h://localhost:1080" for an SSH tunnel [default: from HTTPS_PROXY or ALL_PROXY]
+    #[clap(long)]
+    proxy: Option<String>,
+
+    /// An extra header sent with every request to the server, e.g. "Remote-User: me" or
+    /// "Cookie: session=..." for a server behind an authenticating proxy (repeatable)
+    #[clap(long = "header", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+
+    /// A PEM file with the certificate of a private CA to trust, e.g. for a server whose
+    /// certificate it signed
+    #[clap(long)]
+    ca_cert: Option<PathBuf>,
+
+    /// Don't verify the TLS certificate of the server, for self-signed ones on a home network.
+    /// DANGEROUS: anyone on the network can then read and change the requests, API key included
+    #[clap(long)]
+    insecure: bool,
+
+    /// A PEM file with the client certificate to present to the server, for reverse proxies
+    /// requiring mutual TLS
+    #[clap(long)]
+    client_cert: Option<PathBuf>,
+
+    /// A PEM file with the PKCS#8 private key of the client certificate
+    #[clap(long)]
+    client_key: Option<PathBuf>,
+
+    /// How often to check whether Miniflux fails to fetch any feeds, warning about them with
+    /// a notification (e.g. "1h")
+    #[clap(long, value_parser = humantime::parse_duration)]
+    feed_errors_interval: Option<Duration>,
+
+    /// How many times in a row fetching a feed has to fail before it is warned about
+    /// [default: 1]
+    #[clap(long)]
+    feed_error_threshold: Option<u32>,
+
+    /// Don't notify about entries whose URL has already been notified about, even if
+    /// Miniflux has given the entry a new id since (e.g. after a feed was re-added)
+    #[clap(long)]
+    dedup_urls: bool,
+
+    /// Don't notify about entries whose title is nearly the same as that of an entry already
+    /// notified about, e.g. the same article reposted by several feeds
+    #[clap(long)]
+    dedup_titles: bool,
+
+    /// Only count entries as duplicates of ones notified about this recently (e.g. "2d")
+    /// [default: as long as they are remembered]
+    #[clap(long, value_parser = humantime::parse_duration)]
+    dedup_window: Option<Duration>,
+
+    /// The maximum number of notified URLs to remember [default: 1000]
+    #[clap(long)]
+    seen_limit: Option<usize>,
+
+    /// How long to remember a notified URL for (e.g. "30d" or "12h") [default: 30d]
+    #[clap(long, value_parser = humantime::parse_duration)]
+    seen_max_age: Option<Duration>,
+
+    /// The file state is persisted to between runs
+    /// [default: ~/.local/state/miniflux-notifier/state.json]
+    #[clap(long)]
+    state_file: Option<PathBuf>,
+
+    /// How often changes to the state file are written to disk [default: 5m]
+    #[clap(long, value_parser = humantime::parse_duration)]
+    state_flush_interval: Option<Duration>,
+
+    /// Serve metrics for Prometheus at /metrics on this address (e.g. "127.0.0.1:9180")
+    #[clap(long)]
+    metrics_listen: Option<String>,
+
+    /// Where to listen for commands such as pausing or muting the notifier, which are sent
+    /// with the ctl subcommand [default: $XDG_RUNTIME_DIR/miniflux-notifier.sock]
+    #[clap(long)]
+    control_socket: Option<PathBuf>,
+
+    /// Don't listen for commands on the control socket
+    #[clap(long)]
+    no_control: bool,
+
+    /// Export traces of polls and requests to the server to this OTLP/HTTP endpoint of an
+    /// OpenTelemetry collector (e.g. "http://localhost:4318"). Requires the "otel" feature.
+    #[clap(long)]
+    otlp_endpoint: Option<String>,
+
+    /// Write each new entry to stdout as a line of JSON
+    #[clap(long)]
+    emit_jsonl: bool,
+
+    /// Write the number of unread entries to stdout after each poll, for a status bar to show.
+    /// Not available with --incremental.
+    #[clap(long, value_enum)]
+    status_format: Option<StatusFormat>,
+
+    /// Show a tray icon with the number of unread entries and a menu to pause polling, mute,
+    /// poll right away and open Miniflux. Not available with --incremental.
+    #[clap(long)]
+    tray: bool,
+
+    /// Show the number of unread entries as a badge on the launcher of this desktop file in
+    /// docks such as Dash to Dock and Plank, e.g. "firefox.desktop". Not available with
+    /// --incremental.
+    #[clap(long, value_name = "DESKTOP_FILE")]
+    launcher_badge: Option<String>,
+
+    /// Don't send desktop notifications for new entries
+    #[clap(long)]
+    no_notify: bool,
+
+    /// Don't show the icons of feeds in desktop notifications, which are otherwise fetched
+    /// once and cached in ~/.cache/miniflux-notifier/icons
+    #[clap(long)]
+    no_feed_icons: bool,
+
+    /// The number of desktop notifications that have to fail in a row before the
+    /// notification service is considered unavailable [default: 3]
+    #[clap(long)]
+    notify_failure_threshold: Option<u32>,
+
+    /// How often to check whether an unavailable notification service is back [default: 5m]
+    #[clap(long, value_parser = humantime::parse_duration)]
+    notify_retry_interval: Option<Duration>,
+
+    /// The most notifications to handle actions for at the same time [default: 32]
+    #[clap(long)]
+    max_pending_actions: Option<usize>,
+
+    /// Show every entry in a desktop notification of its own, instead of replacing the
+    /// notification about the previous entry from the same feed
+    #[clap(long)]
+    no_replace_notifications: bool,
+
+    /// Also mark the entry as read when it is opened from its notification
+    #[clap(long)]
+    mark_read_on_open: bool,
+
+    /// Mark entries as read once their notification has gone this long (e.g. "30m") without
+    /// any of its actions being used, for keeping Miniflux at zero unread entries
+    #[clap(long, value_parser = humantime::parse_duration)]
+    mark_read_after: Option<Duration>,
+
+    /// Open entries in Miniflux rather than on their original site when following their
+    /// notification
+    #[clap(long)]
+    open_in_miniflux: bool,
+
+    /// The shell command to open entries with from their desktop notification, with {url}
+    /// standing for the URL (e.g. "firefox --private-window {url}") [default: the default
+    /// browser]
+    #[clap(long)]
+    open_command: Option<String>,
+
+    /// How long to wait before notifying again about an entry snoozed from its desktop
+    /// notification [default: 15m]
+    #[clap(long, value_parser = humantime::parse_duration)]
+    snooze_delay: Option<Duration>,
+
+    /// How long desktop notifications stay on screen, "never" to keep them until they are
+    /// closed or acted on [default: "default", leaving it to the notification service]
+    #[clap(long)]
+    notification_timeout: Option<NotificationTimeout>,
+
+    /// Keep desktop notifications in the notification center after one of their actions is
+    /// used, for notification services supporting it
+    #[clap(long)]
+    resident_notifications: bool,
+
+    /// The bundle identifier of the app desktop notifications are shown as coming from on
+    /// macOS, e.g. "com.apple.Terminal" [default: the app called "Miniflux Notifier" if
+    /// installed, Finder otherwise]
+    #[clap(long)]
+    macos_bundle_id: Option<String>,
+
+    /// Where to send notifications (repeatable) [default: desktop]
+    #[clap(long = "notifier", value_enum)]
+    notifiers: Vec<NotifierKind>,
+
+    /// The ntfy server to publish notifications to [default: https://ntfy.sh]
+    #[clap(long)]
+    ntfy_server: Option<String>,
+
+    /// The ntfy topic to publish notifications to, required by the ntfy notifier
+    #[clap(long)]
+    ntfy_topic: Option<String>,
+
+    /// The priority of ntfy notifications, from 1 (min) to 5 (max) [default: 3]
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=5))]
+    ntfy_priority: Option<u8>,
+
+    /// The access token for publishing to a protected ntfy topic
+    #[clap(long, env = "NTFY_TOKEN", hide_env_values = true)]
+    ntfy_token: Option<String>,
+
+    /// The URL of the Gotify server to send notifications to, required by the gotify notifier
+    #[clap(long)]
+    gotify_server: Option<String>,
+
+    /// The token of the Gotify application to send notifications as, required by the gotify
+    /// notifier
+    #[clap(long, env = "GOTIFY_TOKEN", hide_env_values = true)]
+    gotify_token: Option<String>,
+
+    /// The priority of Gotify notifications [default: 5]
+    #[clap(long)]
+    gotify_priority: Option<u32>,
+
+    /// The token of the Telegram bot to send notifications as, required by the telegram
+    /// notifier
+    #[clap(long, env = "TELEGRAM_BOT_TOKEN", hide_env_values = true)]
+    telegram_token: Option<String>,
+
+    /// The Telegram chat to send notifications to, required by the telegram notifier
+    #[clap(long)]
+    telegram_chat_id: Option<String>,
+
+    /// The URL of the Matrix homeserver to post notifications through, required by the matrix
+    /// notifier
+    #[clap(long)]
+    matrix_homeserver: Option<String>,
+
+    /// The id of the Matrix room to post notifications in (e.g. "!abc:example.org"), required
+    /// by the matrix notifier
+    #[clap(long)]
+    matrix_room: Option<String>,
+
+    /// The access token of the Matrix account to post notifications as, required by the
+    /// matrix notifier
+    #[clap(long, env = "MATRIX_ACCESS_TOKEN", hide_env_values = true)]
+    matrix_access_token: Option<String>,
+
+    /// The SMTP server to send emails through, required by the email notifier
+    #[clap(long)]
+    smtp_server: Option<String>,
+
+    /// The port of the SMTP server [default: depends on --smtp-security]
+    #[clap(long)]
+    smtp_port: Option<u16>,
+
+    /// How the connection to the SMTP server is secured [default: tls]
+    #[clap(long, value_enum)]
+    smtp_security: Option<SmtpSecurity>,
+
+    /// The username to log in to the SMTP server with
+    #[clap(long)]
+    smtp_username: Option<String>,
+
+    /// The password to log in to the SMTP server with
+    #[clap(long, env = "SMTP_PASSWORD", hide_env_values = true)]
+    smtp_password: Option<String>,
+
+    /// The address emails are sent from, required by the email notifier
+    #[clap(long)]
+    email_from: Option<String>,
+
+    /// The address to send emails to, required by the email notifier (repeatable)
+    #[clap(long)]
+    email_to: Vec<String>,
+
+    /// Instead of an email per entry, send a digest of the new entries at most this often
+    /// (e.g. "1h")
+    #[clap(long, value_parser = humantime::parse_duration)]
+    email_digest_interval: Option<Duration>,
+
+    /// The shell command the exec notifier runs for each new entry, which gets the entry as
+    /// JSON on stdin and its fields in ENTRY_* environment variables. The commands run one
+    /// after another
+    #[clap(long)]
+    exec_command: Option<String>,
+
+    /// The shell command the speech notifier reads out new entries with, "{text}" standing for
+    /// what is read out (e.g. "espeak-ng -v en {text}") [default: "spd-say {text}"]
+    #[clap(long)]
+    speech_command: Option<String>,
+
+    /// The MQTT broker to publish new entries to, as "host" or "host:port", required by the
+    /// mqtt notifier
+    #[clap(long)]
+    mqtt_broker: Option<String>,
+
+    /// The MQTT topic to publish new entries to, where "{feed_id}" stands for the ID of the
+    /// entry's feed [default: miniflux/entries]
+    #[clap(long)]
+    mqtt_topic: Option<String>,
+
+    /// The QoS level to publish MQTT messages with [default: 0]
+    #[clap(long, value_parser = clap::value_parser!(u8).range(0..=2))]
+    mqtt_qos: Option<u8>,
+
+    /// Have the broker retain the last entry published to each topic
+    #[clap(long)]
+    mqtt_retain: bool,
+
+    /// The username for publishing to the MQTT broker
+    #[clap(long)]
+    mqtt_username: Option<String>,
+
+    /// The password for publishing to the MQTT broker
+    #[clap(long, env = "MQTT_PASSWORD", hide_env_values = true)]
+    mqtt_password: Option<String>,
+
+    /// Where to send desktop notifications while the notification service is unavailable
+    #[clap(long, value_enum)]
+    fallback_backend: Option<FallbackBackend>,
+
+    /// The FIFO the fifo fallback backend writes notifications to, a line each
+    #[clap(long)]
+    fallback_fifo: Option<PathBuf>,
+
+    /// The shell command the command fallback backend runs for each notification, with
+    /// {summary}, {body} and {url} standing for its parts (e.g. "herbe {summary} {body}")
+    #[clap(long)]
+    fallback_command: Option<String>,
+
+    /// The notification summary, supporting the placeholders {source}, {feed}, {author},
+    /// {title}, {category}, {url}, {published_relative}, {created_relative}, {reading_time} and
+    /// {excerpt}
+    /// [default: "New RSS Entry from {source}"]
+    #[clap(long)]
+    summary_template: Option<String>,
+
+    /// The notification body, supporting the same placeholders as the summary
+    /// [default: "{title}\n\n{excerpt}"]
+    #[clap(long)]
+    body_template: Option<String>,
+
+    /// Prefix the notification summary with the category of the entry's feed
+    #[clap(long)]
+    show_category: bool,
+
+    /// Append the estimated reading time of the entry to the notification body, e.g. "~6 min
+    /// read"
+    #[clap(long)]
+    show_reading_time: bool,
+
+    /// The most characters of the entry's content to show beneath its title, 0 to only show
+    /// the title [default: 200]
+    #[clap(long)]
+    excerpt_length: Option<usize>,
+
+    /// Only notify about entries from this feed, given by id or title glob (repeatable)
+    #[clap(long = "include-feed")]
+    include_feeds: Vec<Matcher>,
+
+    /// Don't notify about entries from this feed, given by id or title glob (repeatable)
+    #[clap(long = "exclude-feed")]
+    exclude_feeds: Vec<Matcher>,
+
+    /// Only notify about entries from feeds in this category, given by id or title glob
+    /// (repeatable)
+    #[clap(long = "include-category")]
+    include_categories: Vec<Matcher>,
+
+    /// Don't notify about entries from feeds in this category, given by id or title glob
+    /// (repeatable)
+    #[clap(long = "exclude-category")]
+    exclude_categories: Vec<Matcher>,
+
+    /// Don't notify about entries whose title, author or URL matches this regular expression
+    /// (repeatable)
+    #[clap(long)]
+    mute: Vec<Regex>,
+
+    /// Don't notify about entries published longer ago than this (e.g. "7d"), such as the
+    /// old posts of a newly added feed
+    #[clap(long, value_parser = humantime::parse_duration)]
+    max_entry_age: Option<Duration>,
+
+    /// Collapse the notifications into a single one when a poll finds more than this many
+    /// new entries
+    #[clap(long)]
+    digest_threshold: Option<usize>,
+
+    /// The most notifications to send for the entries found by a single poll, the entries
+    /// beyond that are rolled into a digest, 0 for no limit [default: 5]
+    #[clap(long)]
+    max_notifications: Option<usize>,
+
+    /// Send a single notification per feed listing the titles of its new entries, instead of
+    /// one per entry
+    #[clap(long)]
+    group_by_feed: bool,
+
+    /// How long to wait before sending the notifications about entries of a lower priority
+    /// than the ones before them, so that the important ones aren't buried under the rest
+    ///
+    /// Polling carries on in the meantime. Whatever is still waiting when the notifier exits,
+    /// as with --once, is sent right away.
+    #[clap(long, value_parser = humantime::parse_duration)]
+    priority_delay: Option<Duration>,
+
+    /// Send a nudge once there are at least this many unread entries, independent of the
+    /// notifications about new entries. Not available with --incremental, which doesn't
+    /// fetch all unread entries.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    unread_threshold: Option<u32>,
+
+    /// Hold back notifications during this window (e.g. "22:00-08:00" or
+    /// "Mon-Fri 22:00-08:00") and send a digest once it is over (repeatable)
+    #[clap(long)]
+    quiet_hours: Vec<QuietHours>,
+
+    /// Hold back notifications while the screen is locked or the session is idle, and send a
+    /// digest once the user is back
+    #[clap(long)]
+    hold_while_locked: bool,
+
+    /// Hold back notifications while the desktop is in Do Not Disturb mode, and send a digest
+    /// once it is switched off
+    #[clap(long)]
+    hold_during_dnd: bool,
+
+    /// Instead of polling, listen on this address (e.g. "127.0.0.1:8081") for Miniflux
+    /// webhook events
+    #[clap(long)]
+    webhook_listen: Option<String>,
+
+    /// The secret used to verify the signature of webhook events
+    #[clap(long, env = "MINIFLUX_WEBHOOK_SECRET", hide_env_values = true)]
+    webhook_secret: Option<String>,
+}
+
+// The subcommands, which do something other than watching the server
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Write a service that starts the notifier with the current config file on login
+    InstallService(InstallServiceArgs),
+    /// Send a command to the running notifier, such as pausing or muting it
+    Ctl(CtlArgs),
+    /// List the feeds with their ids and categories, for writing filters
+    Feeds,
+    /// List the categories with their ids, for writing filters
+    Categories,
+    /// Send a notification about a made-up entry through the configured notifiers, to check
+    /// that they are set up right
+    TestNotify,
+    /// Mark the unread entries as read, optionally only those of one feed or published before
+    /// some time
+    MarkAllRead(MarkAllReadArgs),
+    /// Check the config file, the credentials and the feeds and categories it names, then
+    /// report any problems
+    Check,
+    /// List the entries notified about, newest first, or open one of them again
+    History(HistoryArgs),
+    /// Browse the unread entries in the terminal, opening, marking them as read or starring
+    /// them
+    Tui,
+}
+
+impl Args {
+    /// The config file given on the command line, or the default one if it exists
+    pub fn config_path(&self) -> Option<PathBuf> {
+        match &self.config {
+            Some(path) => Some(path.clone()),
+            None => default_config_path().filter(|path| path.exists()),
+        }
+    }
+
+    /// The control socket of the notifier started with these arguments, for sending it
+    /// commands
+    pub fn control_socket_path(&self) -> Result<PathBuf> {
+        if let Some(path) = &self.control_socket {
+            return Ok(path.clone());
+        }
+        let configured = match self.config_path() {
+            Some(path) => Config::load(&path)?
+                .into_iter()
+                .find_map(|config| config.control_socket),
+            None => None,
+        };
+        configured
+            .or_else(control::default_socket_path)
+            .ok_or_else(|| anyhow!("Could not determine the control socket, use --control-socket"))
+    }
+}
+
+/// The contents of the config file
+///
+/// Every option mirrors the command line option of the same name.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Identifies the server in notifications when several are configured
+    name: Option<String>,
+    server: Option<String>,
+    api_key: Option<String>,
+    api_key_file: Option<PathBuf>,
+    keyring: Option<bool>,
+    username: Option<String>,
+    password: Option<String>,
+    password_file: Option<PathBuf>,
+    source: Option<SourceKind>,
+    #[serde(with = "humantime_serde")]
+    poll_interval: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    battery_poll_interval: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    poll_jitter: Option<Duration>,
+    /// Feeds and categories polled on timers of their own
+    feed_poll_intervals: Vec<PollRule>,
+    #[serde(with = "humantime_serde")]
+    backoff_initial: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    backoff_max: Option<Duration>,
+    outage_threshold: Option<u32>,
+    pause_when_offline: Option<bool>,
+    incremental: Option<bool>,
+    once: Option<bool>,
+    page_size: Option<u32>,
+    entries_path: Option<String>,
+    healthcheck_path: Option<String>,
+    unix_socket: Option<PathBuf>,
+    #[serde(with = "humantime_serde")]
+    timeout: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    connect_timeout: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    keep_alive: Option<Duration>,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    ca_cert: Option<PathBuf>,
+    insecure: Option<bool>,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+    #[serde(with = "humantime_serde")]
+    feed_errors_interval: Option<Duration>,
+    feed_error_threshold: Option<u32>,
+    dedup_urls: Option<bool>,
+    dedup_titles: Option<bool>,
+    #[serde(with = "humantime_serde")]
+    dedup_window: Option<Duration>,
+    seen_limit: Option<usize>,
+    #[serde(with = "humantime_serde")]
+    seen_max_age: Option<Duration>,
+    state_file: Option<PathBuf>,
+    #[serde(with = "humantime_serde")]
+    state_flush_interval: Option<Duration>,
+    metrics_listen: Option<String>,
+    otlp_endpoint: Option<String>,
+    control: Option<bool>,
+    control_socket: Option<PathBuf>,
+    emit_jsonl: Option<bool>,
+    status_format: Option<StatusFormat>,
+    tray: Option<bool>,
+    launcher_badge: Option<String>,
+    notify: Option<bool>,
+    feed_icons: Option<bool>,
+    notify_failure_threshold: Option<u32>,
+    #[serde(with = "humantime_serde")]
+    notify_retry_interval: Option<Duration>,
+    max_pending_actions: Option<usize>,
+    replace_notifications: Option<bool>,
+    mark_read_on_open: Option<bool>,
+    #[serde(with = "humantime_serde")]
+    mark_read_after: Option<Duration>,
+    open_in_miniflux: Option<bool>,
+    open_command: Option<String>,
+    /// The first rule matching an entry decides where following its notification leads
+    feed_open: Vec<OpenRule>,
+    #[serde(with = "humantime_serde")]
+    snooze_delay: Option<Duration>,
+    notification_timeout: Option<NotificationTimeout>,
+    resident_notifications: Option<bool>,
+    macos_bundle_id: Option<String>,
+    notifiers: Vec<NotifierKind>,
+    /// Keyed by the notifier they apply to
+    notifier_filters: HashMap<NotifierKind, NotifierFilter>,
+    ntfy_server: Option<String>,
+    ntfy_topic: Option<String>,
+    ntfy_priority: Option<u8>,
+    ntfy_token: Option<String>,
+    gotify_server: Option<String>,
+    gotify_token: Option<String>,
+    gotify_priority: Option<u32>,
+    telegram_token: Option<String>,
+    telegram_chat_id: Option<String>,
+    matrix_homeserver: Option<String>,
+    matrix_room: Option<String>,
+    matrix_access_token: Option<String>,
+    smtp_server: Option<String>,
+    smtp_port: Option<u16>,
+    smtp_security: Option<SmtpSecurity>,
+    smtp_username: Option<String>,
+    smtp_password: Option<String>,
+    email_from: Option<String>,
+    email_to: Vec<String>,
+    #[serde(with = "humantime_serde")]
+    email_digest_interval: Option<Duration>,
+    exec_command: Option<String>,
+    speech_command: Option<String>,
+    mqtt_broker: Option<String>,
+    mqtt_topic: Option<String>,
+    mqtt_qos: Option<u8>,
+    mqtt_retain: Option<bool>,
+    mqtt_username: Option<String>,
+    mqtt_password: Option<String>,
+    fallback_backend: Option<FallbackBackend>,
+    fallback_fifo: Option<PathBuf>,
+    fallback_command: Option<String>,
+    /// The first rule matching an entry decides the urgency, sound and timeout of its
+    /// notification
+    feed_urgency: Vec<UrgencyRule>,
+    summary_template: Option<String>,
+    body_template: Option<String>,
+    /// The first template matching an entry takes the place of the global templates
+    feed_templates: Vec<FeedTemplate>,
+    show_category: Option<bool>,
+    show_reading_time: Option<bool>,
+    excerpt_length: Option<usize>,
+    webhook_listen: Option<String>,
+    webhook_secret: Option<String>,
+    include_feeds: Vec<Matcher>,
+    exclude_feeds: Vec<Matcher>,
+    include_categories: Vec<Matcher>,
+    exclude_categories: Vec<Matcher>,
+    mute: Vec<MuteRule>,
+    #[serde(with = "humantime_serde")]
+    max_entry_age: Option<Duration>,
+    digest_threshold: Option<usize>,
+    max_notifications: Option<usize>,
+    group_by_feed: Option<bool>,
+    /// The first rule matching an entry decides its priority, which orders the notifications
+    /// about the entries found at once
+    feed_priority: Vec<PriorityRule>,
+    #[serde(with = "humantime_serde")]
+    priority_delay: Option<Duration>,
+    unread_threshold: Option<u32>,
+    quiet_hours: Vec<QuietHours>,
+    hold_while_locked: Option<bool>,
+    hold_during_dnd: Option<bool>,
+}
+
+impl Config {
+    /// Reads the config file at `path`, returning a config for each server in it.
+    ///
+    /// Servers are given as `[[servers]]` tables, whose options take precedence over the ones
+    /// at the top level of the file. Without any, the top level describes the only server.
+    pub fn load(path: &Path) -> Result<Vec<Self>> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::parse(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    /// Parses the contents of a config file. The tables keep track of where in the file each
+    /// value came from, so that errors point at the line to fix even for merged servers.
+    fn parse(contents: &str) -> Result<Vec<Self>, toml::de::Error> {
+        let with_input = |mut e: toml::de::Error| {
+            e.set_input(Some(contents));
+            e
+        };
+        let document = DeTable::parse(contents)?;
+        let span = document.span();
+        let mut table = document.into_inner();
+
+        let servers = match table.remove("servers").map(Spanned::into_inner) {
+            None => {
+                return Ok(vec![
+                    Self::from_table(Spanned::new(span, table)).map_err(with_input)?
+                ])
+            }
+            Some(DeValue::Array(servers)) => servers,
+            Some(_) => {
+                return Err(serde::de::Error::custom(
+                    "servers must be an array of tables",
+                ))
+            }
+        };
+        servers
+            .into_iter()
+            .map(|server| {
+                let span = server.span();
+                match server.into_inner() {
+                    DeValue::Table(server) => {
+                        let mut merged = table.clone();
+                        merged.extend(server);
+                        Self::from_table(Spanned::new(span, merged)).map_err(with_input)
+                    }
+                    _ => Err(serde::de::Error::custom(
+                        "servers must be an array of tables",
+                    )),
+                }
+            })
+            .collect()
+    }
+
+    fn from_table(table: Spanned<DeTable>) -> Result<Self, toml::de::Error> {
+        Self::deserialize(toml::de::Deserializer::from(table))
+    }
+}
+
+/// How requests to the server are authenticated
+#[derive(Debug)]
+pub enum Auth {
+    ApiKey(String),
+    Basic { username: String, password: String },
+}
+
+/// The settings the notifier runs with
+#[derive(Debug)]
+pub struct Settings {
+    /// Set when several servers are configured, to tell them apart
+    pub name: Option<String>,
+    pub server: String,
+    pub source: SourceKind,
+    pub auth: Auth,
+    pub poll_interval: Duration,
+    pub battery_poll_interval: Option<Duration>,
+    pub poll_jitter: Duration,
+    pub poll_rules: Vec<PollRule>,
+    pub backoff_initial: Duration,
+    pub backoff_max: Duration,
+    pub outage_threshold: Option<u32>,
+    pub pause_when_offline: bool,
+    pub incremental: bool,
+    pub once: bool,
+    pub dry_run: bool,
+    pub page_size: u32,
+    pub entries_path: String,
+    pub healthcheck_path: String,
+    pub unix_socket: Option<PathBuf>,
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub keep_alive: Duration,
+    pub user_agent: String,
+    pub proxy: Option<String>,
+    /// Sent with every request to the server on top of the credentials
+    pub headers: Vec<(String, String)>,
+    pub ca_cert: Option<PathBuf>,
+    pub insecure: bool,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub feed_errors_interval: Option<Duration>,
+    pub feed_error_threshold: u32,
+    pub dedup_urls: bool,
+    pub dedup_titles: bool,
+    pub dedup_window: Option<Duration>,
+    pub seen_limit: usize,
+    pub seen_max_age: Duration,
+    pub state_file: Option<PathBuf>,
+    pub state_flush_interval: Duration,
+    pub metrics_listen: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    /// `None` if commands aren't listened for
+    pub control_socket: Option<PathBuf>,
+    pub emit_jsonl: bool,
+    pub status_format: Option<StatusFormat>,
+    pub tray: bool,
+    pub launcher_badge: Option<String>,
+    pub notify: bool,
+    pub feed_icons: bool,
+    pub notify_failure_threshold: u32,
+    pub notify_retry_interval: Duration,
+    pub max_pending_actions: usize,
+    pub replace_notifications: bool,
+    pub mark_read_on_open: bool,
+    pub mark_read_after: Option<Duration>,
+    pub open_in_miniflux: bool,
+    pub open_command: Option<String>,
+    pub open_rules: Vec<OpenRule>,
+    pub snooze_delay: Duration,
+    pub notification_timeout: NotificationTimeout,
+    pub resident_notifications: bool,
+    pub macos_bundle_id: Option<String>,
+    pub notifiers: Vec<NotifierKind>,
+    pub notifier_filters: HashMap<NotifierKind, NotifierFilter>,
+    pub ntfy_server: String,
+    pub ntfy_topic: Option<String>,
+    pub ntfy_priority: u8,
+    pub ntfy_token: Option<String>,
+    pub gotify_server: Option<String>,
+    pub gotify_token: Option<String>,
+    pub gotify_priority: u32,
+    pub telegram_token: Option<String>,
+    pub telegram_chat_id: Option<String>,
+    pub matrix_homeserver: Option<String>,
+    pub matrix_room: Option<String>,
+    pub matrix_access_token: Option<String>,
+    pub smtp_server: Option<String>,
+    pub smtp_port: Option<u16>,
+    pub smtp_security: SmtpSecurity,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub email_from: Option<String>,
+    pub email_to: Vec<String>,
+    pub email_digest_interval: Option<Duration>,
+    pub exec_command: Option<String>,
+    pub speech_command: Option<String>,
+    pub mqtt_broker: Option<String>,
+    pub mqtt_topic: String,
+    pub mqtt_qos: u8,
+    pub mqtt_retain: bool,
+    pub mqtt_username: Option<String>,
+    pub mqtt_password: Option<String>,
+    pub fallback_backend: Option<FallbackBackend>,
+    pub fallback_fifo: Option<PathBuf>,
+    pub fallback_command: Option<String>,
+    pub urgency_rules: Vec<UrgencyRule>,
+    pub summary_template: String,
+    pub body_template: String,
+    pub feed_templates: Vec<FeedTemplate>,
+    pub show_category: bool,
+    pub show_reading_time: bool,
+    pub excerpt_length: usize,
+    pub webhook_listen: Option<String>,
+    pub webhook_secret: Option<String>,
+    pub filter: EntryFilter,
+    pub mute_rules: Vec<MuteRule>,
+    pub max_entry_age: Option<Duration>,
+    pub digest_threshold: Option<usize>,
+    pub max_notifications: Option<usize>,
+    pub group_by_feed: bool,
+    pub priority_rules: Vec<PriorityRule>,
+    pub priority_delay: Option<Duration>,
+    pub unread_threshold: Option<u32>,
+    pub quiet_hours: Vec<QuietHours>,
+    pub hold_while_locked: bool,
+    pub hold_during_dnd: bool,
+}
+
+impl Settings {
+    /// Merges the command line arguments with the config file they point to, giving the
+    /// settings for each of the configured servers
+    ///
+    /// A missing config file is only an error if it was explicitly asked for.
+    pub fn load(args: Args) -> Result<Vec<Self>> {
+        let configs = match &args.config {
+            Some(path) => Config::load(path)?,
+            None => match default_config_path() {
+                Some(path) if path.exists() => Config::load(&path)?,
+                _ => vec![Config::default()],
+            },
+        };
+
+        let multiple = configs.len() > 1;
+        let mut names = HashSet::new();
+        let mut state_files = HashSet::new();
+        let mut settings = vec![];
+        for config in configs {
+            let server = Self::merge(args.clone(), config)?;
+            if multiple {
+                let name = server.name.as_deref().ok_or_else(|| {
+                    anyhow!("Every server needs a name when several servers are configured")
+                })?;
+                if name.is_empty() || name.contains(std::path::is_separator) {
+                    return Err(anyhow!("Invalid server name {:?}", name));
+                }
+                if !names.insert(name.to_string()) {
+                    return Err(anyhow!("The server name {:?} is used more than once", name));
+                }
+                if let Some(path) = &server.state_file {
+                    if !state_files.insert(path.clone()) {
+                        return Err(anyhow!(
+                            "The state file {} is used for more than one server",
+                            path.display()
+                        ));
+                    }
+                }
+            }
+            settings.push(server);
+        }
+        Ok(settings)
+    }
+
+    fn merge(args: Args, config: Config) -> Result<Self> {
+        let poll_interval = match (args.poll_interval, args.wait_period, config.poll_interval) {
+            (Some(interval), _, _) => interval,
+            (None, Some(minutes), _) => Duration::from_secs(60 * minutes),
+            (None, None, Some(interval)) => interval,
+            (None, None, None) => Duration::from_secs(60 * 60),
+        };
+
+        let server = args.server.or(config.server).ok_or_else(|| {
+            anyhow!("No server given, pass it as an argument or set it in the config file")
+        })?;
+        let api_key = match (args.miniflux_api_key, args.api_key_file) {
+            (Some(api_key), _) => Some(api_key),
+            (None, Some(path)) => Some(read_secret_file(&path)?),
+            (None, None) if args.keyring || config.keyring.unwrap_or(false) => {
+                Some(read_api_key_keyring(&server)?)
+            }
+            (None, None) => match (config.api_key, config.api_key_file) {
+                (Some(api_key), _) => Some(api_key),
+                (None, Some(path)) => Some(read_secret_file(&path)?),
+                (None, None) => None,
+            },
+        };
+        let auth = match (api_key, args.username.or(config.username)) {
+            (Some(api_key), _) => Auth::ApiKey(api_key),
+            (None, Some(username)) => {
+                let password = match (args.password, args.password_file) {
+                    (Some(password), _) => password,
+                    (None, Some(path)) => read_secret_file(&path)?,
+                    (None, None) => match (config.password, config.password_file) {
+                        (Some(password), _) => password,
+                        (None, Some(path)) => read_secret_file(&path)?,
+                        (None, None) => {
+                            return Err(anyhow!("No password given for user {}", username))
+                        }
+                    },
+                };
+                Auth::Basic { username, password }
+            }
+            (None, None) => {
+                return Err(anyhow!(
+                    "No API key or username given, use --miniflux-api-key or set it in the \
+                     config file"
+                ))
+            }
+        };
+
+        Ok(Self {
+            name: config.name,
+            server,
+            source: args
+                .source
+                .or(config.source)
+                .unwrap_or(SourceKind::Miniflux),
+            auth,
+            poll_interval: poll_interval.max(MIN_POLL_INTERVAL),
+            battery_poll_interval: args
+                .battery_poll_interval
+                .or(config.battery_poll_interval)
+                .map(|interval| interval.max(poll_interval).max(MIN_POLL_INTERVAL)),
+            poll_jitter: args.poll_jitter.or(config.poll_jitter).unwrap_or_default(),
+            poll_rules: config.feed_poll_intervals,
+            backoff_initial: args
+                .backoff_initial
+                .or(config.backoff_initial)
+                .unwrap_or(Duration::from_secs(10)),
+            backoff_max: args
+                .backoff_max
+                .or(config.backoff_max)
+                .unwrap_or(Duration::from_secs(30 * 60)),
+            outage_threshold: args.outage_threshold.or(config.outage_threshold),
+            pause_when_offline: args.pause_when_offline
+                || config.pause_when_offline.unwrap_or(false),
+            incremental: args.incremental || config.incremental.unwrap_or(false),
+            once: args.once || config.once.unwrap_or(false),
+            dry_run: args.dry_run,
+            page_size: args.page_size.or(config.page_size).unwrap_or(100).max(1),
+            entries_path: match args.entries_path {
+                Some(path) => path,
+                None => parse_api_path(config.entries_path.as_deref().unwrap_or("/v1/entries"))
+                    .map_err(|e| anyhow!("Invalid entries_path in config file: {}", e))?,
+            },
+            healthcheck_path: match args.healthcheck_path {
+                Some(path) => path,
+                None => {
+                    parse_api_path(config.healthcheck_path.as_deref().unwrap_or("/healthcheck"))
+                        .map_err(|e| anyhow!("Invalid healthcheck_path in config file: {}", e))?
+                }
+            },
+            unix_socket: args.unix_socket.or(config.unix_socket),
+            timeout: args
+                .timeout
+                .or(config.timeout)
+                .unwrap_or(Duration::from_secs(30)),
+            connect_timeout: args
+                .connect_timeout
+                .or(config.connect_timeout)
+                .unwrap_or(Duration::from_secs(10)),
+            keep_alive: args
+                .keep_alive
+                .or(config.keep_alive)
+                .unwrap_or(Duration::from_secs(90)),
+            user_agent: args
+                .user_agent
+                .or(config.user_agent)
+                .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string()),
+            proxy: args.proxy.or(config.proxy),
+            headers: or_config(args.headers, config.headers.into_iter().collect()),
+            ca_cert: args.ca_cert.or(config.ca_cert),
+            insecure: args.insecure || config.insecure.unwrap_or(false),
+            client_cert: args.client_cert.or(config.client_cert),
+            client_key: args.client_key.or(config.client_key),
+            feed_errors_interval: args.feed_errors_interval.or(config.feed_errors_interval),
+            feed_error_threshold: args
+                .feed_error_threshold
+                .or(config.feed_error_threshold)
+                .unwrap_or(1),
+            dedup_urls: args.dedup_urls || config.dedup_urls.unwrap_or(false),
+            dedup_titles: args.dedup_titles || config.dedup_titles.unwrap_or(false),
+            dedup_window: args.dedup_window.or(config.dedup_window),
+            seen_limit: args.seen_limit.or(config.seen_limit).unwrap_or(1000),
+            seen_max_age: args
+                .seen_max_age
+                .or(config.seen_max_age)
+                .unwrap_or(Duration::from_secs(30 * 24 * 60 * 60)),
+            state_file: args.state_file.or(config.state_file),
+            state_flush_interval: args
+                .state_flush_interval
+                .or(config.state_flush_interval)
+                .unwrap_or(Duration::from_secs(5 * 60)),
+            metrics_listen: args.metrics_listen.or(config.metrics_listen),
+            otlp_endpoint: args.otlp_endpoint.or(config.otlp_endpoint),
+            control_socket: match !args.no_control && config.control.unwrap_or(true) {
+                true => args
+                    .control_socket
+                    .or(config.control_socket)
+                    .or_else(control::default_socket_path),
+                false => None,
+            },
+            emit_jsonl: args.emit_jsonl || config.emit_jsonl.unwrap_or(false),
+            status_format: args.status_format.or(config.status_format),
+            tray: args.tray || config.tray.unwrap_or(false),
+            launcher_badge: args.launcher_badge.or(config.launcher_badge),
+            notify: !args.no_notify && config.notify.unwrap_or(true),
+            feed_icons: !args.no_feed_icons && config.feed_icons.unwrap_or(true),
+            notify_failure_threshold: args
+                .notify_failure_threshold
+                .or(config.notify_failure_threshold)
+                .unwrap_or(3),
+            notify_retry_interval: args
+                .notify_retry_interval
+                .or(config.notify_retry_interval)
+                .unwrap_or(Duration::from_secs(5 * 60)),
+            max_pending_actions: args
+                .max_pending_actions
+                .or(config.max_pending_actions)
+                .unwrap_or(32),
+            notifiers: match or_config(args.notifiers, config.notifiers) {
+                notifiers if notifiers.is_empty() => vec![NotifierKind::Desktop],
+                notifiers => notifiers,
+            },
+            notifier_filters: config.notifier_filters,
+            ntfy_server: args
+                .ntfy_server
+                .or(config.ntfy_server)
+                .unwrap_or_else(|| "https://ntfy.sh".to_string()),
+            ntfy_topic: args.ntfy_topic.or(config.ntfy_topic),
+            ntfy_priority: args
+                .ntfy_priority
+                .or(config.ntfy_priority)
+                .unwrap_or(3)
+                .clamp(1, 5),
+            ntfy_token: args.ntfy_token.or(config.ntfy_token),
+            gotify_server: args.gotify_server.or(config.gotify_server),
+            gotify_token: args.gotify_token.or(config.gotify_token),
+            gotify_priority: args.gotify_priority.or(config.gotify_priority).unwrap_or(5),
+            telegram_token: args.telegram_token.or(config.telegram_token),
+            telegram_chat_id: args.telegram_chat_id.or(config.telegram_chat_id),
+            matrix_homeserver: args.matrix_homeserver.or(config.matrix_homeserver),
+            matrix_room: args.matrix_room.or(config.matrix_room),
+            matrix_access_token: args.matrix_access_token.or(config.matrix_access_token),
+            smtp_server: args.smtp_server.or(config.smtp_server),
+            smtp_port: args.smtp_port.or(config.smtp_port),
+            smtp_security: args
+                .smtp_security
+                .or(config.smtp_security)
+                .unwrap_or(SmtpSecurity::Tls),
+            smtp_username: args.smtp_username.or(config.smtp_username),
+            smtp_password: args.smtp_password.or(config.smtp_password),
+            email_from: args.email_from.or(config.email_from),
+            email_to: or_config(args.email_to, config.email_to),
+            email_digest_interval: args.email_digest_interval.or(config.email_digest_interval),
+            exec_command: args.exec_command.or(config.exec_command),
+            speech_command: args.speech_command.or(config.speech_command),
+            mqtt_broker: args.mqtt_broker.or(config.mqtt_broker),
+            mqtt_topic: args
+                .mqtt_topic
+                .or(config.mqtt_topic)
+                .unwrap_or_else(|| "miniflux/entries".to_string()),
+            mqtt_qos: args.mqtt_qos.or(config.mqtt_qos).unwrap_or(0).min(2),
+            mqtt_retain: args.mqtt_retain || config.mqtt_retain.unwrap_or(false),
+            mqtt_username: args.mqtt_username.or(config.mqtt_username),
+            mqtt_password: args.mqtt_password.or(config.mqtt_password),
+            fallback_backend: args.fallback_backend.or(config.fallback_backend),
+            fallback_fifo: args.fallback_fifo.or(config.fallback_fifo),
+            fallback_command: args.fallback_command.or(config.fallback_command),
+            urgency_rules: config.feed_urgency,
+            summary_template: args
+                .summary_template
+                .or(config.summary_template)
+                .unwrap_or_else(|| "New RSS Entry from {source}".to_string()),
+            body_template: args
+                .body_template
+                .or(config.body_template)
+                .unwrap_or_else(|| "{title}\n\n{excerpt}".to_string()),
+            feed_templates: config.feed_templates,
+            replace_notifications: !args.no_replace_notifications
+                && config.replace_notifications.unwrap_or(true),
+            mark_read_on_open: args.mark_read_on_open || config.mark_read_on_open.unwrap_or(false),
+            mark_read_after: args.mark_read_after.or(config.mark_read_after),
+            open_in_miniflux: args.open_in_miniflux || config.open_in_miniflux.unwrap_or(false),
+            open_command: args.open_command.or(config.open_command),
+            open_rules: config.feed_open,
+            snooze_delay: args
+                .snooze_delay
+                .or(config.snooze_delay)
+                .unwrap_or(Duration::from_secs(15 * 60)),
+            notification_timeout: args
+                .notification_timeout
+                .or(config.notification_timeout)
+                .unwrap_or(NotificationTimeout::Default),
+            resident_notifications: args.resident_notifications
+                || config.resident_notifications.unwrap_or(false),
+            macos_bundle_id: args.macos_bundle_id.or(config.macos_bundle_id),
+            show_category: args.show_category || config.show_category.unwrap_or(false),
+            show_reading_time: args.show_reading_time || config.show_reading_time.unwrap_or(false),
+            excerpt_length: args.excerpt_length.or(config.excerpt_length).unwrap_or(200),
+            webhook_listen: args.webhook_listen.or(config.webhook_listen),
+            webhook_secret: args.webhook_secret.or(config.webhook_secret),
+            filter: EntryFilter {
+                include_feeds: or_config(args.include_feeds, config.include_feeds),
+                exclude_feeds: or_config(args.exclude_feeds, config.exclude_feeds),
+                include_categories: or_config(args.include_categories, config.include_categories),
+                exclude_categories: or_config(args.exclude_categories, config.exclude_categories),
+            },
+            mute_rules: or_config(
+                args.mute.into_iter().map(MuteRule::any).collect(),
+                config.mute,
+            ),
+            max_entry_age: args.max_entry_age.or(config.max_entry_age),
+            digest_threshold: args.digest_threshold.or(config.digest_threshold),
+            max_notifications: match args.max_notifications.or(config.max_notifications) {
+                Some(0) => None,
+                max => Some(max.unwrap_or(5)),
+            },
+            group_by_feed: args.group_by_feed || config.group_by_feed.unwrap_or(false),
+            priority_rules: config.feed_priority,
+            priority_delay: args.priority_delay.or(config.priority_delay),
+            unread_threshold: args.unread_threshold.or(config.unread_threshold),
+            quiet_hours: or_config(args.quiet_hours, config.quiet_hours),
+            hold_while_locked: args.hold_while_locked || config.hold_while_locked.unwrap_or(false),
+            hold_during_dnd: args.hold_during_dnd || config.hold_during_dnd.unwrap_or(false),
+        })
+    }
+}
+
+/// Lists on the command line replace the list from the config file rather than extending it
+fn or_config<T>(args: Vec<T>, config: Vec<T>) -> Vec<T> {
+    match args.is_empty() {
+        true => config,
+        false => args,
+    }
+}
+
+fn read_secret_file(path: &Path) -> Result<String> {
+    let secret =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(secret.trim().to_string())
+}
+
+#[cfg(feature = "keyring")]
+fn read_api_key_keyring(server: &str) -> Result<String> {
+    keyring::Entry::new("miniflux-notifier", server)
+        .and_then(|entry| entry.get_password())
+        .with_context(|| format!("Failed to read the API key for {} from the keyring", server))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn read_api_key_keyring(_server: &str) -> Result<String> {
+    Err(anyhow!(
+        "Reading the API key from the keyring requires building with the \"keyring\" feature"
+    ))
+}
+
+/// The config file read when no explicit path is given
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("miniflux-notifier").join("config.toml"))
+}
+
+/// Parses a human-friendly poll interval such as "30s" or "5m"
+fn parse_poll_interval(interval: &str) -> Result<Duration, String> {
+    let interval = humantime::parse_duration(interval).map_err(|e| e.to_string())?;
+    if interval < MIN_POLL_INTERVAL {
+        return Err(format!(
+            "the poll interval must be at least {}",
+            humantime::format_duration(MIN_POLL_INTERVAL)
+        ));
+    }
+    Ok(interval)
+}
+
+/// Parses a header given as "Name: value"
+fn parse_header(header: &str) -> Result<(String, String), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| "the header must look like \"Name: value\"".to_string())?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+/// Validates an API path
+///
+/// The path is joined onto the server URL, so it must not carry a scheme or host of its own.
+fn parse_api_path(path: &str) -> Result<String, String> {
+    if path.contains("://") || path.starts_with("//") {
+        return Err("the path must not contain a scheme or host".to_string());
+    }
+    if !path.starts_with('/') {
+        return Err("the path must start with a '/'".to_string());
+    }
+    let path = path.trim_end_matches('/');
+    if path.is_empty() {
+        return Err("the path must name an endpoint rather than the root".to_string());
+    }
+    Ok(path.to_string())
+}
+
+/// The settings for a server given by the command line arguments alone, for tests
+#[cfg(test)]
+pub(crate) fn test_settings(args: &[&str]) -> Settings {
+    let args = [
+        "miniflux-notifier",
+        "http://localhost",
+        "--miniflux-api-key",
+        "key",
+    ]
+    .iter()
+    .chain(args);
+    Settings::merge(Args::parse_from(args), Config::default()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_paths_are_normalized() {
+        assert_eq!(parse_api_path("/v1/entries").unwrap(), "/v1/entries");
+        assert_eq!(
+            parse_api_path("/proxy/v1/entries/").unwrap(),
+            "/proxy/v1/entries"
+        );
+    }
+
+    #[test]
+    fn invalid_api_paths_are_rejected() {
+        assert!(parse_api_path("/").is_err());
+        assert!(parse_api_path("v1/entries").is_err());
+        assert!(parse_api_path("https://example.com/v1/entries").is_err());
+        assert!(parse_api_path("//example.com/v1/entries").is_err());
+    }
+}
@@ -0,0 +1,384 @@
+//! Controlling the running notifier through a Unix socket, e.g. to mute it during a meeting
+//! without losing its state
+//!
+//! A client connects, writes a single command on a line and reads the answer until the
+//! connection is closed. Answers to commands that couldn't be carried out start with
+//! `error: `. The commands are:
+//!
+//! - `status`: whether polling is paused or notifications are muted, and the last status of
+//!   each server
+//! - `pause` and `resume`: stop and start polling the servers
+//! - `poll-now`: poll the servers right away instead of waiting for the next poll
+//! - `mute <duration>` and `unmute`: hold back notifications for a while, e.g. `mute 1h`. The
+//!   entries found in the meantime are sent as a digest once the notifier is unmuted.
+
+use anyhow::{anyhow, Result};
+use clap::Args as ClapArgs;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::state;
+
+/// What the notifier has been told to do, shared by every server it watches
+static CONTROL: Mutex<Control> = Mutex::new(Control {
+    paused: false,
+    muted_until: None,
+    poll_requests: 0,
+    statuses: BTreeMap::new(),
+});
+
+#[derive(Debug)]
+struct Control {
+    paused: bool,
+    /// In seconds since the unix epoch
+    muted_until: Option<u64>,
+    /// How many times polling right away has been asked for
+    poll_requests: u64,
+    /// The last status of each server, by the label identifying it
+    statuses: BTreeMap<String, String>,
+}
+
+/// A command sent to the control socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Status,
+    Pause,
+    Resume,
+    PollNow,
+    Mute(Duration),
+    Unmute,
+}
+
+impl FromStr for Request {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (command, argument) = match s.trim().split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, Some(argument.trim())),
+            None => (s.trim(), None),
+        };
+        match (command, argument) {
+            ("status", None) => Ok(Request::Status),
+            ("pause", None) => Ok(Request::Pause),
+            ("resume", None) => Ok(Request::Resume),
+            ("poll-now", None) => Ok(Request::PollNow),
+            ("mute", Some(duration)) => humantime::parse_duration(duration)
+                .map(Request::Mute)
+                .map_err(|e| format!("invalid duration {:?}: {}", duration, e)),
+            ("mute", None) => Err("mute needs a duration, e.g. \"mute 1h\"".to_string()),
+            ("unmute", None) => Ok(Request::Unmute),
+            ("status" | "pause" | "resume" | "poll-now" | "unmute", Some(_)) => {
+                Err(format!("{} doesn't take an argument", command))
+            }
+            _ => Err(format!("unknown command {:?}", command)),
+        }
+    }
+}
+
+/// Whether polling has been paused
+pub fn is_paused() -> bool {
+    CONTROL.lock().unwrap().paused
+}
+
+/// Whether notifications are being held back because the notifier has been muted
+pub fn is_muted() -> bool {
+    let mut control = CONTROL.lock().unwrap();
+    match control.muted_until {
+        Some(until) if until > state::now() => true,
+        Some(_) => {
+            control.muted_until = None;
+            false
+        }
+        None => false,
+    }
+}
+
+/// A number that changes whenever polling right away is asked for, to be compared with the
+/// number as of the last poll
+pub fn poll_requests() -> u64 {
+    CONTROL.lock().unwrap().poll_requests
+}
+
+/// Records the status of a server, for the `status` command
+pub fn set_status(server: &str, status: &str) {
+    CONTROL
+        .lock()
+        .unwrap()
+        .statuses
+        .insert(server.to_string(), status.to_string());
+}
+
+/// Carries out the request, returning the answer to send back
+pub fn handle(request: Request) -> String {
+    let mut control = CONTROL.lock().unwrap();
+    match request {
+        Request::Status => {
+            let mut lines = vec![match control.paused {
+                true => "Polling is paused".to_string(),
+                false => "Polling".to_string(),
+            }];
+            let now = state::now();
+            match control.muted_until {
+                Some(until) if until > now => lines.push(format!(
+                    "Muted for another {}",
+                    humantime::format_duration(Duration::from_secs(until - now))
+                )),
+                _ => lines.push("Not muted".to_string()),
+            }
+            for (server, status) in &control.statuses {
+                lines.push(format!("{}: {}", server, status));
+            }
+            lines.join("\n")
+        }
+        Request::Pause => {
+            control.paused = true;
+            "Paused polling".to_string()
+        }
+        Request::Resume => {
+            control.paused = false;
+            // Catch up on what was missed while paused
+            control.poll_requests += 1;
+            "Resumed polling".to_string()
+        }
+        Request::PollNow if control.paused => {
+            "error: polling is paused, resume it first".to_string()
+        }
+        Request::PollNow => {
+            control.poll_requests += 1;
+            "Polling now".to_string()
+        }
+        Request::Mute(duration) => {
+            control.muted_until = Some(state::now().saturating_add(duration.as_secs()));
+            format!("Muted for {}", humantime::format_duration(duration))
+        }
+        Request::Unmute => {
+            control.muted_until = None;
+            "Unmuted".to_string()
+        }
+    }
+}
+
+/// Where the control socket is unless configured otherwise
+pub fn default_socket_path() -> Option<PathBuf> {
+    dirs::runtime_dir()
+        .or_else(state::default_state_dir)
+        .map(|dir| dir.join("miniflux-notifier.sock"))
+}
+
+#[cfg(unix)]
+mod socket {
+    use anyhow::{anyhow, Context, Result};
+    use log::{error, info};
+    use std::fs;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::process;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::Request;
+
+    pub fn serve(path: &Path, shutdown: Arc<AtomicBool>) -> Result<()> {
+        if UnixStream::connect(path).is_ok() {
+            return Err(anyhow!(
+                "{} is already used by another notifier",
+                path.display()
+            ));
+        }
+        // Left behind by a notifier that didn't shut down cleanly
+        let _ = fs::remove_file(path);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        let listener = bind_privately(path, dir)
+            .with_context(|| format!("Failed to listen on {}", path.display()))?;
+        listener.set_nonblocking(true)?;
+        info!("Listening for control commands on {}", path.display());
+
+        thread::spawn(move || {
+            while !shutdown.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = handle_connection(stream) {
+                            error!("Failed to handle a control command: {:?}", e);
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(250));
+                    }
+                    Err(e) => error!("Failed to accept a control connection: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    pub fn remove(path: &Path) {
+        if let Err(e) = fs::remove_file(path) {
+            error!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
+
+    /// Listens on the socket, which only its owner can connect to from the start, as anyone
+    /// who can connect can control the notifier.
+    ///
+    /// The socket is bound in a directory of its own that nobody else can enter, and moved
+    /// into place once its permissions have been restricted.
+    fn bind_privately(path: &Path, dir: &Path) -> Result<UnixListener> {
+        let private = dir.join(format!(".miniflux-notifier-{}", process::id()));
+        // Left behind by a notifier with the same process id that didn't shut down cleanly
+        let _ = fs::remove_dir_all(&private);
+        fs::DirBuilder::new().mode(0o700).create(&private)?;
+        let bound = private.join("control.sock");
+        let result = UnixListener::bind(&bound)
+            .map_err(anyhow::Error::from)
+            .and_then(|listener| {
+                fs::set_permissions(&bound, fs::Permissions::from_mode(0o600))?;
+                fs::rename(&bound, path)?;
+                Ok(listener)
+            });
+        let _ = fs::remove_dir_all(&private);
+        result
+    }
+
+    fn handle_connection(stream: UnixStream) -> Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut line = String::new();
+        BufReader::new(&stream).take(1024).read_line(&mut line)?;
+        if line.is_empty() {
+            // Only checking whether the socket is in use
+            return Ok(());
+        }
+        let answer = match line.parse::<Request>() {
+            Ok(request) => {
+                info!("Received control command {:?}", line.trim());
+                super::handle(request)
+            }
+            Err(e) => format!("error: {}", e),
+        };
+        (&stream).write_all(answer.as_bytes())?;
+        (&stream).write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn send(path: &Path, command: &str) -> Result<String> {
+        let mut stream = UnixStream::connect(path).with_context(|| {
+            format!(
+                "Failed to connect to {}, is the notifier running?",
+                path.display()
+            )
+        })?;
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+        stream.write_all(command.as_bytes())?;
+        stream.write_all(b"\n")?;
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer)?;
+        Ok(answer)
+    }
+}
+
+/// Listens for commands on the socket at `path` in a thread of its own, until a shutdown is
+/// requested
+#[cfg(unix)]
+pub fn serve(path: &Path, shutdown: std::sync::Arc<std::sync::atomic::AtomicBool>) -> Result<()> {
+    socket::serve(path, shutdown)
+}
+
+/// Removes the socket, once the notifier is shutting down
+#[cfg(unix)]
+pub fn remove_socket(path: &Path) {
+    socket::remove(path)
+}
+
+#[cfg(not(unix))]
+pub fn remove_socket(_path: &Path) {}
+
+#[cfg(not(unix))]
+pub fn serve(_path: &Path, _shutdown: std::sync::Arc<std::sync::atomic::AtomicBool>) -> Result<()> {
+    Err(anyhow!("The control socket is only supported on Unix"))
+}
+
+/// The options of the `ctl` subcommand
+#[derive(ClapArgs, Debug, Clone)]
+pub struct CtlArgs {
+    /// The command to send: status, pause, resume, poll-now, mute <duration> or unmute
+    #[clap(required = true, num_args = 1..)]
+    command: Vec<String>,
+}
+
+/// Sends the command to the notifier listening on the socket at `path` and prints its answer
+pub fn ctl(args: &CtlArgs, path: &Path) -> Result<()> {
+    let command = args.command.join(" ");
+    command
+        .parse::<Request>()
+        .map_err(|e| anyhow!("Invalid command: {}", e))?;
+    let answer = send(path, &command)?;
+    let answer = answer.trim_end();
+    match answer.strip_prefix("error: ") {
+        Some(e) => Err(anyhow!("{}", e)),
+        None => {
+            println!("{}", answer);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(unix)]
+fn send(path: &Path, command: &str) -> Result<String> {
+    socket::send(path, command)
+}
+
+#[cfg(not(unix))]
+fn send(_path: &Path, _command: &str) -> Result<String> {
+    Err(anyhow!("The control socket is only supported on Unix"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_mutes_dont_overflow() {
+        handle(Request::Mute(Duration::MAX));
+        assert!(is_muted());
+        assert!(handle(Request::Status).contains("Muted for another"));
+        handle(Request::Unmute);
+        assert!(!is_muted());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_owner_can_connect() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Arc;
+
+        let dir = std::env::temp_dir().join(format!(
+            "miniflux-notifier-test-{}-control",
+            std::process::id()
+        ));
+        let path = dir.join("control.sock");
+        let shutdown = Arc::new(AtomicBool::new(false));
+        serve(&path, Arc::clone(&shutdown)).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Nothing is left of where the socket was bound
+        let entries: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, ["control.sock"]);
+        assert_eq!(send(&path, "unmute").unwrap().trim_end(), "Unmuted");
+
+        shutdown.store(true, std::sync::atomic::Ordering::Relaxed);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
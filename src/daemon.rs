@@ -0,0 +1,1084 @@
+//! The main loop of the notifier
+
+use anyhow::Result;
+use chrono::Local;
+use log::{debug, error, info, warn};
+use rand::RngExt;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::backoff::{Backoff, Outage};
+use crate::config::{Args, Settings, MIN_POLL_INTERVAL};
+use crate::control;
+use crate::diff::{find_new_entries, remember_urls};
+use crate::dnd;
+use crate::dry_run;
+use crate::feed_errors::FeedErrorCheck;
+use crate::filter::is_too_old;
+use crate::launcher;
+use crate::metrics::{self, Metrics};
+use crate::models::{Entries, Entry};
+use crate::network;
+use crate::notify::{
+    desktop::Urgency, send_digest, send_notification_batch, send_status_notification,
+    send_unread_count_notification, Notifier, Notifiers,
+};
+use crate::power;
+use crate::scoped_poll::ScopedPolls;
+use crate::session;
+use crate::source::{self, FeedSource, SourceKind};
+use crate::state::{self, StateFile};
+use crate::status_bar;
+use crate::systemd;
+use crate::telemetry::{self, Span, SpanKind};
+use crate::tray;
+use crate::webhook;
+
+/// How many times reloading the config file has been asked for
+static RELOADS: AtomicU64 = AtomicU64::new(0);
+
+/// Watches each of the servers in a thread of its own until a shutdown is requested.
+///
+/// The settings are loaded again from `args` whenever the process receives SIGHUP.
+pub fn run_all(args: &Args, servers: Vec<Settings>) -> Result<()> {
+    for settings in &servers {
+        check_conflicts(settings)?;
+    }
+    // A second signal terminates immediately, in case shutting down gracefully hangs
+    let shutdown = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(&shutdown))?;
+        signal_hook::flag::register(signal, Arc::clone(&shutdown))?;
+    }
+    #[cfg(unix)]
+    {
+        let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])?;
+        thread::spawn(move || {
+            for _ in signals.forever() {
+                info!("Reloading the config file");
+                RELOADS.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    }
+
+    let mut metrics_listen: Vec<&str> = servers
+        .iter()
+        .filter_map(|settings| settings.metrics_listen.as_deref())
+        .collect();
+    // Servers can share an address without being next to each other in the config
+    metrics_listen.sort_unstable();
+    metrics_listen.dedup();
+    for addr in metrics_listen {
+        metrics::serve(addr, Arc::clone(&shutdown))?;
+    }
+    // Traces are exported from the whole process, so only a single endpoint is used
+    if let Some(endpoint) = servers
+        .iter()
+        .find_map(|settings| settings.otlp_endpoint.as_deref())
+    {
+        telemetry::init(endpoint)?;
+    }
+    // Commands apply to every server, so a single socket is listened on as well. A single
+    // poll is over too soon to be controlled.
+    let control_socket = servers
+        .iter()
+        .filter(|settings| !settings.once)
+        .find_map(|settings| settings.control_socket.clone())
+        .filter(|path| match control::serve(path, Arc::clone(&shutdown)) {
+            Ok(()) => true,
+            // Losing the ability to control the notifier shouldn't keep it from running
+            Err(e) => {
+                warn!("Not listening for control commands: {:?}", e);
+                false
+            }
+        });
+
+    // A single icon covers every server
+    if servers
+        .iter()
+        .any(|settings| settings.tray && !settings.once)
+    {
+        if let Err(e) = tray::start(&servers) {
+            warn!("Not showing the tray icon: {:?}", e);
+        }
+    }
+    if let Some(desktop_file) = servers
+        .iter()
+        .filter(|settings| !settings.once)
+        .find_map(|settings| settings.launcher_badge.as_deref())
+    {
+        if let Err(e) = launcher::start(desktop_file) {
+            warn!("Not showing the launcher badge: {:?}", e);
+        }
+    }
+
+    let result = match servers.len() {
+        1 => run(servers.into_iter().next().unwrap(), args, &shutdown),
+        _ => run_each(servers, args, &shutdown),
+    };
+    telemetry::flush();
+    if let Some(path) = control_socket {
+        control::remove_socket(&path);
+    }
+    result
+}
+
+/// Rejects settings that don't work together, rather than having one of them silently do
+/// nothing
+fn check_conflicts(settings: &Settings) -> Result<()> {
+    if settings.status_format.is_some() && settings.emit_jsonl {
+        return Err(anyhow::anyhow!(
+            "--status-format can't be combined with --emit-jsonl"
+        ));
+    }
+    // Only the entries published since the last poll are fetched, so the number of unread
+    // entries isn't known
+    if settings.incremental {
+        let needing_count = [
+            ("--unread-threshold", settings.unread_threshold.is_some()),
+            ("--status-format", settings.status_format.is_some()),
+            ("--tray", settings.tray),
+            ("--launcher-badge", settings.launcher_badge.is_some()),
+        ];
+        if let Some((option, _)) = needing_count.iter().find(|(_, set)| *set) {
+            return Err(anyhow::anyhow!(
+                "{} can't be combined with --incremental",
+                option
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Watches each of the servers in a thread of its own
+fn run_each(servers: Vec<Settings>, args: &Args, shutdown: &AtomicBool) -> Result<()> {
+    let names: Vec<String> = servers
+        .iter()
+        .map(|settings| settings.name.clone().unwrap_or(settings.server.clone()))
+        .collect();
+    thread::scope(|scope| {
+        let threads: Vec<_> = servers
+            .into_iter()
+            .map(|settings| scope.spawn(|| run(settings, args, shutdown)))
+            .collect();
+        let mut result = Ok(());
+        for (name, thread) in names.iter().zip(threads) {
+            match thread.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    error!("Stopped watching {}: {:?}", name, e);
+                    result = Err(e.context(format!("Failed to watch {}", name)));
+                }
+                Err(_) => result = Err(anyhow::anyhow!("Watching {} panicked", name)),
+            }
+        }
+        result
+    })
+}
+
+/// Watches the server for new entries until a shutdown is requested, either by polling or
+/// by listening for webhooks
+pub fn run(mut settings: Settings, args: &Args, shutdown: &AtomicBool) -> Result<()> {
+    let state_file = state::path_for(&settings);
+    if state_file.is_none() {
+        warn!("Could not determine a state directory, state will not be persisted");
+    }
+    let mut state = StateFile::open(
+        state_file,
+        settings.state_flush_interval,
+        settings.seen_limit,
+        settings.seen_max_age,
+    )?;
+
+    let mut client = source::from_settings(&settings)?;
+    let mut notifiers = notifiers_for(&settings, &client, &mut state)?;
+
+    info!("Checking for server existence");
+    if client.healthcheck().is_err() {
+        eprintln!(
+            "Server was not found! Make sure it is running and the specified URL is correct."
+        );
+    }
+
+    match client.me() {
+        Ok(user) => info!("Authenticated as {} on {}", user.username, settings.server),
+        Err(e) if e.is_auth() => {
+            return Err(anyhow::Error::new(e).context("The server rejected the credentials"));
+        }
+        Err(e) => error!("Failed to check the credentials!\n\t{}", e),
+    }
+
+    info!("Server check passed. Beginning main loop.");
+    systemd::ready();
+
+    let mut result = Ok(());
+    if let Some(addr) = settings.webhook_listen.clone() {
+        if settings.once {
+            return Err(anyhow::anyhow!(
+                "--once can't be combined with --webhook-listen"
+            ));
+        }
+        if settings.source != SourceKind::Miniflux {
+            return Err(anyhow::anyhow!("Only Miniflux can send webhooks"));
+        }
+        let secret = settings.webhook_secret.clone().ok_or_else(|| {
+            anyhow::anyhow!("--webhook-secret is required to verify webhook requests")
+        })?;
+        report_status(
+            &settings,
+            &format!("Listening for webhook events on {}", addr),
+        );
+        let mut reloads = RELOADS.load(Ordering::Relaxed);
+        webhook::serve(&addr, &secret, shutdown, |new_entries| {
+            if RELOADS.load(Ordering::Relaxed) != reloads {
+                reloads = RELOADS.load(Ordering::Relaxed);
+                reload(args, &mut settings, &mut client, &mut notifiers, &mut state);
+            }
+            if !new_entries.is_empty() {
+                handle_new_entries(new_entries, false, &settings, &mut state, &mut notifiers);
+            }
+            finish_round(client.as_ref(), &settings, &mut state, &mut notifiers);
+        })?;
+    } else if settings.once {
+        result = poll_once(client.as_ref(), &settings, &mut state, &mut notifiers);
+    } else {
+        while let Stopped::Reload = poll(
+            client.as_ref(),
+            &settings,
+            &mut state,
+            &mut notifiers,
+            shutdown,
+        ) {
+            reload(args, &mut settings, &mut client, &mut notifiers, &mut state);
+        }
+    }
+
+    info!("Shutting down");
+    systemd::stopping();
+    if let Err(e) = notifiers.flush() {
+        error!("{:?}", e);
+    }
+    state.flush(state::now())?;
+
+    result
+}
+
+/// Where notifications about the server's entries are sent, nowhere in a dry run
+fn notifiers_for(
+    settings: &Settings,
+    client: &Arc<dyn FeedSource>,
+    state: &mut StateFile,
+) -> Result<Notifiers> {
+    match settings.dry_run {
+        true => {
+            // The state from a real run is still used to tell which entries are new
+            state.keep_in_memory();
+            Ok(Notifiers::new())
+        }
+        false => Notifiers::from_settings(settings, client, state.snoozes(), state.history()),
+    }
+}
+
+/// Loads the config file again and switches to the new settings of the server, keeping the
+/// state. The current settings are kept if the new ones are invalid.
+fn reload(
+    args: &Args,
+    settings: &mut Settings,
+    client: &mut Arc<dyn FeedSource>,
+    notifiers: &mut Notifiers,
+    state: &mut StateFile,
+) {
+    let reloaded = reloaded_settings(args, settings).and_then(|new_settings| {
+        let new_client = source::from_settings(&new_settings)?;
+        let new_notifiers = notifiers_for(&new_settings, &new_client, state)?;
+        Ok((new_settings, new_client, new_notifiers))
+    });
+    let (new_settings, new_client, new_notifiers) = match reloaded {
+        Ok(reloaded) => reloaded,
+        Err(e) => {
+            error!(
+                "Failed to reload the config file, keeping the current settings: {:?}",
+                e
+            );
+            return;
+        }
+    };
+
+    // Whatever the current notifiers are holding back would be lost otherwise
+    if let Err(e) = notifiers.flush() {
+        error!("{:?}", e);
+    }
+    *settings = new_settings;
+    *client = new_client;
+    *notifiers = new_notifiers;
+    info!(
+        "Reloaded the settings of {}",
+        settings.name.as_deref().unwrap_or(&settings.server)
+    );
+}
+
+/// The settings of the same server as `current` in the config file as it is now
+fn reloaded_settings(args: &Args, current: &Settings) -> Result<Settings> {
+    let mut servers = Settings::load(args.clone())?;
+    let i = servers
+        .iter()
+        .position(|settings| settings.name == current.name)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} is no longer in the config file",
+                current.name.as_deref().unwrap_or(&current.server)
+            )
+        })?;
+    let new = servers.swap_remove(i);
+    check_conflicts(&new)?;
+
+    // These are only used when starting up
+    let restart_only = [
+        ("state_file", new.state_file != current.state_file),
+        (
+            "state_flush_interval",
+            new.state_flush_interval != current.state_flush_interval,
+        ),
+        ("seen_limit", new.seen_limit != current.seen_limit),
+        ("seen_max_age", new.seen_max_age != current.seen_max_age),
+        (
+            "webhook_listen",
+            new.webhook_listen != current.webhook_listen,
+        ),
+        (
+            "webhook_secret",
+            new.webhook_secret != current.webhook_secret,
+        ),
+        (
+            "metrics_listen",
+            new.metrics_listen != current.metrics_listen,
+        ),
+        ("otlp_endpoint", new.otlp_endpoint != current.otlp_endpoint),
+        (
+            "control_socket",
+            new.control_socket != current.control_socket,
+        ),
+    ];
+    let changed: Vec<&str> = restart_only
+        .iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(name, _)| *name)
+        .collect();
+    if !changed.is_empty() {
+        warn!(
+            "Restart the notifier for the changes to {} to take effect",
+            changed.join(", ")
+        );
+    }
+    Ok(new)
+}
+
+/// Why polling the server stopped
+enum Stopped {
+    Shutdown,
+    /// Reloading the config file was asked for
+    Reload,
+}
+
+/// Polls the server for new entries until a shutdown or reloading the config file is
+/// requested
+fn poll(
+    client: &dyn FeedSource,
+    settings: &Settings,
+    state: &mut StateFile,
+    notifiers: &mut Notifiers,
+    shutdown: &AtomicBool,
+) -> Stopped {
+    let reloads = RELOADS.load(Ordering::Relaxed);
+    let metrics = Metrics::for_server(settings);
+    let mut backoff = Backoff::new(settings.backoff_initial, settings.backoff_max);
+    let mut outage = settings.outage_threshold.map(Outage::new);
+    let mut feed_errors = FeedErrorCheck::new(settings);
+    let mut scoped_polls = ScopedPolls::new(settings);
+    // When all feeds are polled next, `None` for right away
+    let mut next_poll = match settings.poll_jitter.is_zero() {
+        true => None,
+        false => Some(Instant::now() + jitter(settings)),
+    };
+    let mut poll_requests = control::poll_requests();
+    let watch_network = settings.pause_when_offline
+        && settings.unix_socket.is_none()
+        && !network::is_local(&settings.server);
+    let mut offline = false;
+    // Set while waiting the longer interval for running on battery
+    let mut saving_battery = false;
+    // Set after waking up from sleep until the server has been polled, to catch up on what
+    // arrived in the meantime with a digest
+    let mut catching_up = false;
+    while !shutdown.load(Ordering::Relaxed) {
+        if let Some(next) = next_poll {
+            let mut delay = next.saturating_duration_since(Instant::now());
+            if !offline && !control::is_paused() {
+                if let Some(until_next) = scoped_polls.as_ref().and_then(ScopedPolls::until_next) {
+                    delay = delay.min(until_next);
+                }
+            }
+            // Whatever becomes due while waiting, such as snoozed entries, shouldn't have to
+            // wait for the next poll
+            let mut wake = WakeDetector::new();
+            let interrupted = sleep_unless_shutdown(delay, shutdown, || {
+                let woke_up = wake.woke_up();
+                if woke_up {
+                    info!("Woke up from sleep, polling right away");
+                    catching_up = true;
+                } else {
+                    finish_round(client, settings, state, notifiers);
+                }
+                let cut_short = woke_up
+                    || control::poll_requests() != poll_requests
+                    || RELOADS.load(Ordering::Relaxed) != reloads
+                    || (offline && !network::is_offline())
+                    || (saving_battery && !power::on_battery());
+                if cut_short {
+                    // Whatever cut the wait short calls for polling all feeds
+                    next_poll = None;
+                }
+                cut_short
+            });
+            if interrupted {
+                break;
+            }
+        }
+        if RELOADS.load(Ordering::Relaxed) != reloads {
+            return Stopped::Reload;
+        }
+        let poll_all = next_poll.is_none_or(|next_poll| Instant::now() >= next_poll);
+        if poll_all {
+            next_poll = Some(Instant::now() + poll_interval(settings, &mut saving_battery));
+        }
+        poll_requests = control::poll_requests();
+        if control::is_paused() {
+            if poll_all {
+                report_status(settings, "Paused");
+            }
+            continue;
+        }
+        if watch_network && network::is_offline() {
+            if !offline {
+                info!("Offline, waiting for a connection to poll again");
+                report_status(settings, "Offline");
+                offline = true;
+            }
+            continue;
+        }
+        if offline {
+            info!("Back online");
+            offline = false;
+            backoff.reset();
+        }
+
+        if let Some(scoped_polls) = &mut scoped_polls {
+            let new_entries = scoped_polls.poll_due(client, settings, state);
+            if !new_entries.is_empty() {
+                handle_new_entries(new_entries, false, settings, state, notifiers);
+            }
+        }
+        if !poll_all {
+            finish_round(client, settings, state, notifiers);
+            continue;
+        }
+
+        let mut span = poll_span(settings);
+        let published_after = match settings.incremental {
+            true => state.state.newest_published_at,
+            false => None,
+        };
+        let unread_entries = match client.unread_entries(published_after) {
+            Ok(unread_entries) => unread_entries,
+            Err(e) => {
+                metrics.record_poll_error();
+                span.set_error(&e);
+                error!("Failed to get unread entries!\n\t{}", e);
+                report_status(settings, &format!("Failed to get unread entries: {}", e));
+                if outage.as_mut().is_some_and(Outage::record_failure) {
+                    notify_status(
+                        notifiers,
+                        "Miniflux is unreachable",
+                        &format!("Failed to reach {}: {}", settings.server, e),
+                        settings,
+                    );
+                }
+                if e.is_transient() {
+                    // The server knows best when it can take requests again
+                    let retry_delay = match e.retry_after() {
+                        Some(retry_after) => retry_after.max(MIN_POLL_INTERVAL),
+                        None => backoff.next_delay(),
+                    };
+                    info!("Retrying in {}", humantime::format_duration(retry_delay));
+                    next_poll = Some(Instant::now() + retry_delay);
+                }
+                continue;
+            }
+        };
+        metrics.record_poll(unread_entries.total, fetched(&unread_entries));
+        backoff.reset();
+        if outage.as_mut().is_some_and(Outage::record_success) {
+            notify_status(
+                notifiers,
+                "Miniflux is reachable again",
+                &format!("Reconnected to {}", settings.server),
+                settings,
+            );
+        }
+        if let Some(feed_errors) = &mut feed_errors {
+            feed_errors.check_if_due(client, settings, state, notifiers);
+        }
+        if !settings.incremental {
+            nudge_about_unread(unread_entries.total, settings, state, notifiers);
+            status_bar::update(settings, &unread_entries);
+        }
+
+        span.set_attribute("unread_entries", unread_entries.total);
+        if unread_entries.not_modified {
+            debug!("The unread entries haven't changed");
+            catching_up = false;
+            finish_round(client, settings, state, notifiers);
+            continue;
+        }
+        let mut unread_entries = unread_entries;
+        if let Some(scoped_polls) = &scoped_polls {
+            scoped_polls.leave_out(&mut unread_entries, published_after, state);
+        }
+        let new_entries = handle_unread_entries(
+            unread_entries,
+            published_after,
+            catching_up,
+            settings,
+            state,
+            notifiers,
+        );
+        catching_up = false;
+        span.set_attribute("new_entries", new_entries);
+        finish_round(client, settings, state, notifiers);
+    }
+    Stopped::Shutdown
+}
+
+/// How long to wait until the next poll, longer while the computer is running on battery and
+/// with the jitter on top
+fn poll_interval(settings: &Settings, saving_battery: &mut bool) -> Duration {
+    let battery_poll_interval = settings
+        .battery_poll_interval
+        .filter(|_| power::on_battery());
+    if battery_poll_interval.is_some() != *saving_battery {
+        *saving_battery = battery_poll_interval.is_some();
+        match *saving_battery {
+            true => info!("On battery, polling less often"),
+            false => info!("Plugged in, polling as usual again"),
+        }
+    }
+    battery_poll_interval.unwrap_or(settings.poll_interval) + jitter(settings)
+}
+
+/// A random delay of up to `--poll-jitter`, so that notifiers started at the same time drift
+/// apart rather than polling the server in lockstep
+fn jitter(settings: &Settings) -> Duration {
+    rand::rng().random_range(Duration::ZERO..=settings.poll_jitter)
+}
+
+/// A span for a poll of the server, covering the requests made and the notifications sent
+fn poll_span(settings: &Settings) -> Span {
+    let mut span = telemetry::span("poll", SpanKind::Internal);
+    span.set_attribute("server.address", settings.server.as_str());
+    if let Some(name) = &settings.name {
+        span.set_attribute("server.name", name.as_str());
+    }
+    span
+}
+
+/// Polls the server a single time, for running from cron or a timer rather than as a daemon
+fn poll_once(
+    client: &dyn FeedSource,
+    settings: &Settings,
+    state: &mut StateFile,
+    notifiers: &mut Notifiers,
+) -> Result<()> {
+    let published_after = match settings.incremental {
+        true => state.state.newest_published_at,
+        false => None,
+    };
+    let metrics = Metrics::for_server(settings);
+    let mut span = poll_span(settings);
+    let unread_entries = client.unread_entries(published_after).map_err(|e| {
+        metrics.record_poll_error();
+        span.set_error(&e);
+        anyhow::Error::new(e).context("Failed to get unread entries")
+    })?;
+    metrics.record_poll(unread_entries.total, fetched(&unread_entries));
+    span.set_attribute("unread_entries", unread_entries.total);
+    let unread_entries_total = unread_entries.total;
+    if !settings.incremental {
+        status_bar::update(settings, &unread_entries);
+    }
+    let new_entries = handle_unread_entries(
+        unread_entries,
+        published_after,
+        false,
+        settings,
+        state,
+        notifiers,
+    );
+    span.set_attribute("new_entries", new_entries);
+    info!("Found {} new entries", new_entries);
+    if !settings.incremental {
+        nudge_about_unread(unread_entries_total, settings, state, notifiers);
+    }
+    if let Some(mut feed_errors) = FeedErrorCheck::new(settings) {
+        feed_errors.check_if_due(client, settings, state, notifiers);
+    }
+    finish_round(client, settings, state, notifiers);
+    Ok(())
+}
+
+/// Sends a nudge once the number of unread entries has reached the threshold, and again only
+/// after it has dropped below it in between
+fn nudge_about_unread(
+    total: u32,
+    settings: &Settings,
+    state: &mut StateFile,
+    notifiers: &mut Notifiers,
+) {
+    let Some(threshold) = settings.unread_threshold else {
+        return;
+    };
+    let past_threshold = total >= threshold;
+    if past_threshold && !state.state.unread_nudged {
+        if let Err(e) = send_unread_count_notification(notifiers, total, threshold, settings) {
+            error!("{:?}", e);
+        }
+    }
+    state.set_unread_nudged(past_threshold);
+}
+
+/// How many entries were fetched from the server, none if they were cached
+fn fetched(unread_entries: &Entries) -> usize {
+    match unread_entries.not_modified {
+        true => 0,
+        false => unread_entries.entries.len(),
+    }
+}
+
+/// Works out which of the unread entries are new and sends them wherever they have been
+/// configured to go, returning how many were new. With `digest`, they are notified about in a
+/// single digest.
+fn handle_unread_entries(
+    unread_entries: Entries,
+    published_after: Option<i64>,
+    digest: bool,
+    settings: &Settings,
+    state: &mut StateFile,
+    notifiers: &mut Notifiers,
+) -> usize {
+    state.update_newest_published_at(&unread_entries.entries);
+
+    // Everything published after the newest entry seen so far is new
+    if published_after.is_some() {
+        info!("New entries: {}", unread_entries.total);
+        report_status(settings, &format!("New entries: {}", unread_entries.total));
+        let new_entries = unread_entries.entries.len();
+        if new_entries > 0 {
+            handle_new_entries(unread_entries.entries, digest, settings, state, notifiers);
+        }
+        return new_entries;
+    }
+
+    info!("Unread entries: {}", unread_entries.total);
+    report_status(
+        settings,
+        &format!("Unread entries: {}", unread_entries.total),
+    );
+
+    // Don't consider any "new" entries when there is no cache
+    let mut count = 0;
+    if let Some(last_entries) = &state.state.last_entries {
+        let new_entries = find_new_entries(last_entries, &unread_entries.entries);
+        count = new_entries.len();
+        if !new_entries.is_empty() {
+            handle_new_entries(new_entries, digest, settings, state, notifiers);
+        }
+    }
+
+    state.set_last_entries(&unread_entries.entries);
+    count
+}
+
+/// Sends the new entries wherever they have been configured to go, in a single digest with
+/// `digest`
+fn handle_new_entries(
+    mut new_entries: Vec<Entry>,
+    digest: bool,
+    settings: &Settings,
+    state: &mut StateFile,
+    notifiers: &mut Notifiers,
+) {
+    if settings.dry_run {
+        let quiet = is_quiet_time(settings);
+        for entry in &new_entries {
+            println!(
+                "{}",
+                dry_run::describe(entry, settings, &state.state.seen_urls, quiet)
+            );
+        }
+    }
+
+    new_entries.retain(|entry| {
+        settings.filter.allows(entry)
+            && !settings.mute_rules.iter().any(|r| r.matches(entry))
+            && !is_too_old(entry, settings.max_entry_age)
+    });
+    if settings.dedup_urls || settings.dedup_titles {
+        new_entries = remember_urls(state, new_entries, settings);
+    }
+    if settings.dry_run {
+        return;
+    }
+    if settings.emit_jsonl {
+        if let Err(e) = emit_jsonl(&new_entries) {
+            error!("{:?}", e);
+        }
+    }
+    if settings.notify {
+        if let Some(reason) = hold_back_reason(settings, false) {
+            // Critical notifications are still shown during Do Not Disturb, so their entries
+            // don't have to wait for it to end
+            let (critical, held): (Vec<_>, Vec<_>) = match hold_back_reason(settings, true) {
+                None => new_entries
+                    .into_iter()
+                    .partition(|entry| is_critical(settings, entry)),
+                Some(_) => (vec![], new_entries),
+            };
+            if !held.is_empty() {
+                info!("Holding back {} entries {}", held.len(), reason);
+                state.queue_entries(held);
+            }
+            if critical.is_empty() {
+                return;
+            }
+            new_entries = critical;
+        }
+        let result = match digest && new_entries.len() > 1 {
+            true => send_digest(notifiers, new_entries, settings),
+            false => send_notification_batch(notifiers, new_entries, settings),
+        };
+        if let Err(e) = result {
+            error!("{:?}", e);
+        }
+    }
+}
+
+fn is_quiet_time(settings: &Settings) -> bool {
+    let now = Local::now().naive_local();
+    settings.quiet_hours.iter().any(|hours| hours.contains(now))
+}
+
+/// Whether the user has locked the screen or left the session idle, if that is to hold back
+/// notifications
+fn is_away(settings: &Settings) -> bool {
+    settings.hold_while_locked && session::is_locked()
+}
+
+/// Whether the desktop is in Do Not Disturb mode, if that is to hold back notifications
+fn is_do_not_disturb(settings: &Settings) -> bool {
+    settings.hold_during_dnd && dnd::is_active()
+}
+
+/// Whether the entry gets a critical notification through `feed_urgency`
+fn is_critical(settings: &Settings, entry: &Entry) -> bool {
+    settings
+        .urgency_rules
+        .iter()
+        .find(|rule| rule.matches(entry))
+        .is_some_and(|rule| rule.urgency == Some(Urgency::Critical))
+}
+
+/// Why notifications are being held back right now, if they are. Critical ones aren't held
+/// back during Do Not Disturb.
+fn hold_back_reason(settings: &Settings, critical: bool) -> Option<&'static str> {
+    if control::is_muted() {
+        Some("while muted")
+    } else if is_away(settings) {
+        Some("while the screen is locked")
+    } else if !critical && is_do_not_disturb(settings) {
+        Some("while Do Not Disturb is on")
+    } else if is_quiet_time(settings) {
+        Some("during quiet hours")
+    } else {
+        None
+    }
+}
+
+/// Sends a digest of the entries held back during quiet hours or while muted once that is over
+fn release_queued_entries(settings: &Settings, state: &mut StateFile, notifiers: &mut Notifiers) {
+    if state.state.queued_entries.is_empty() || hold_back_reason(settings, false).is_some() {
+        return;
+    }
+    let queued = state.take_queued_entries();
+    info!("Sending {} held back entries", queued.len());
+    if let Err(e) = send_digest(notifiers, queued, settings) {
+        error!("{:?}", e);
+    }
+}
+
+/// Marks the entries read whose notifications went unanswered for `--mark-read-after`
+fn mark_expired_read(client: &dyn FeedSource, settings: &Settings, state: &StateFile) {
+    let Some(mark_read_after) = settings.mark_read_after.filter(|_| !settings.dry_run) else {
+        return;
+    };
+    let cutoff = state::now().saturating_sub(mark_read_after.as_secs());
+    // The entries are taken even if marking them fails, so as not to try again on every step
+    let expired = state.history().take_expired(cutoff);
+    if expired.is_empty() {
+        return;
+    }
+    match client.mark_read(&expired) {
+        Ok(()) => info!(
+            "Marked {} entries as read after their notifications went unanswered",
+            expired.len()
+        ),
+        Err(e) => error!(
+            "Failed to mark entries with expired notifications as read: {}",
+            e
+        ),
+    }
+}
+
+/// Sends whatever has become due and writes the state if it is time to, after each poll or
+/// webhook request
+fn finish_round(
+    client: &dyn FeedSource,
+    settings: &Settings,
+    state: &mut StateFile,
+    notifiers: &mut Notifiers,
+) {
+    systemd::watchdog();
+    release_queued_entries(settings, state, notifiers);
+    mark_expired_read(client, settings, state);
+    // Snoozed entries and digests wait until the notifier is unmuted or the user is back too
+    if !control::is_muted() && !is_away(settings) && !is_do_not_disturb(settings) {
+        if let Err(e) = notifiers.tick() {
+            error!("{:?}", e);
+        }
+    }
+    if let Err(e) = state.flush_if_due(state::now()) {
+        error!("{:?}", e);
+    }
+}
+
+/// Shows how polling the server went in `systemctl status` and in the answer to the `status`
+/// control command
+fn report_status(settings: &Settings, status: &str) {
+    match &settings.name {
+        Some(name) => systemd::status(&format!("{}: {}", name, status)),
+        None => systemd::status(status),
+    }
+    control::set_status(settings.name.as_deref().unwrap_or(&settings.server), status);
+}
+
+fn notify_status(notifiers: &mut Notifiers, summary: &str, body: &str, settings: &Settings) {
+    if let Err(e) = send_status_notification(notifiers, summary, body, settings) {
+        error!("{:?}", e);
+    }
+}
+
+/// Notices the computer waking up from sleep, during which the monotonic clock stands still
+/// while the wall clock keeps going
+struct WakeDetector {
+    wall: SystemTime,
+    monotonic: Instant,
+}
+
+impl WakeDetector {
+    /// How far the clocks have to drift apart, so that the wall clock being adjusted isn't
+    /// mistaken for sleep
+    const THRESHOLD: Duration = Duration::from_secs(30);
+
+    fn new() -> Self {
+        Self {
+            wall: SystemTime::now(),
+            monotonic: Instant::now(),
+        }
+    }
+
+    /// Whether the computer has slept since the last call
+    fn woke_up(&mut self) -> bool {
+        let wall = self.wall.elapsed().unwrap_or_default();
+        let monotonic = self.monotonic.elapsed();
+        *self = Self::new();
+        wall > monotonic + Self::THRESHOLD
+    }
+}
+
+/// Sleeps for `duration`, calling `on_step` every so often, and returns early with `true` if
+/// a shutdown was requested in the meantime. `on_step` returning `true` ends the sleep early
+/// as well, but with `false`.
+fn sleep_unless_shutdown(
+    duration: Duration,
+    shutdown: &AtomicBool,
+    mut on_step: impl FnMut() -> bool,
+) -> bool {
+    const STEP: Duration = Duration::from_millis(250);
+    let start = Instant::now();
+    while !shutdown.load(Ordering::Relaxed) {
+        let elapsed = start.elapsed();
+        if elapsed >= duration {
+            return false;
+        }
+        thread::sleep(STEP.min(duration - elapsed));
+        if on_step() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Writes each entry to stdout as a single line of JSON
+///
+/// Stdout is flushed after every batch so that the entries reach whatever is reading them as
+/// soon as they are detected, even when stdout is a pipe.
+#[cfg(not(test))]
+fn emit_jsonl(entries: &[Entry]) -> Result<()> {
+    write_jsonl(&mut std::io::stdout().lock(), entries)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// What the test running on the thread had written to stdout
+    static EMITTED: std::cell::RefCell<Vec<u8>> = std::cell::RefCell::default();
+}
+
+#[cfg(test)]
+fn emit_jsonl(entries: &[Entry]) -> Result<()> {
+    EMITTED.with(|emitted| write_jsonl(&mut *emitted.borrow_mut(), entries))
+}
+
+fn write_jsonl(out: &mut impl Write, entries: &[Entry]) -> Result<()> {
+    for entry in entries {
+        serde_json::to_writer(&mut *out, entry)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_settings;
+    use crate::filter::Matcher;
+    use crate::models::test_entry;
+    use crate::notify::desktop::UrgencyRule;
+
+    fn unread(ids: &[u64]) -> Entries {
+        Entries {
+            total: ids.len() as u32,
+            entries: ids.iter().map(|id| test_entry(*id)).collect(),
+            not_modified: false,
+        }
+    }
+
+    fn emitted_ids() -> Vec<u64> {
+        let emitted = EMITTED.with(|emitted| emitted.take());
+        String::from_utf8(emitted)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Entry>(line).unwrap().id)
+            .collect()
+    }
+
+    #[test]
+    fn jsonl_lines_across_polls() {
+        let settings = test_settings(&["--emit-jsonl", "--no-notify"]);
+        let mut state =
+            StateFile::open(None, Duration::ZERO, 100, Duration::from_secs(86400)).unwrap();
+        let mut notifiers = Notifiers::new();
+        state.set_last_entries(&unread(&[1]).entries);
+
+        handle_unread_entries(
+            unread(&[2, 1]),
+            None,
+            false,
+            &settings,
+            &mut state,
+            &mut notifiers,
+        );
+        assert_eq!(emitted_ids(), [2]);
+
+        // Only the entry that arrived since is written on the second poll
+        handle_unread_entries(
+            unread(&[3, 2, 1]),
+            None,
+            false,
+            &settings,
+            &mut state,
+            &mut notifiers,
+        );
+        assert_eq!(emitted_ids(), [3]);
+    }
+
+    #[test]
+    fn conflicting_settings_are_rejected() {
+        assert!(check_conflicts(&test_settings(&["--incremental"])).is_ok());
+        assert!(check_conflicts(&test_settings(&["--unread-threshold", "10"])).is_ok());
+        assert!(check_conflicts(&test_settings(&[
+            "--incremental",
+            "--unread-threshold",
+            "10"
+        ]))
+        .is_err());
+        for args in [
+            ["--status-format", "waybar"].as_slice(),
+            &["--tray"],
+            &["--launcher-badge", "firefox.desktop"],
+        ] {
+            assert!(check_conflicts(&test_settings(args)).is_ok());
+            let incremental = [args, &["--incremental"]].concat();
+            assert!(check_conflicts(&test_settings(&incremental)).is_err());
+        }
+    }
+
+    #[test]
+    fn critical_entries_follow_the_first_matching_rule() {
+        let mut settings = test_settings(&[]);
+        settings.urgency_rules = vec![
+            UrgencyRule {
+                feeds: vec![Matcher::Id(2)],
+                categories: vec![],
+                urgency: Some(Urgency::Critical),
+                sound: None,
+                timeout: None,
+                resident: None,
+            },
+            UrgencyRule {
+                feeds: vec![Matcher::Id(1)],
+                categories: vec![],
+                urgency: Some(Urgency::Low),
+                sound: None,
+                timeout: None,
+                resident: None,
+            },
+            UrgencyRule {
+                feeds: vec![],
+                categories: vec![Matcher::Id(1)],
+                urgency: Some(Urgency::Critical),
+                sound: None,
+                timeout: None,
+                resident: None,
+            },
+        ];
+        let mut entry = test_entry(1);
+        assert!(!is_critical(&settings, &entry));
+        entry.feed.id = 2;
+        assert!(is_critical(&settings, &entry));
+        entry.feed.id = 3;
+        assert!(is_critical(&settings, &entry));
+    }
+}
@@ -0,0 +1,245 @@
+//! Working out which entries haven't been seen before
+
+use log::debug;
+use reqwest::Url;
+use std::collections::HashSet;
+
+use crate::config::Settings;
+use crate::models::Entry;
+use crate::state::{self, SeenEntry, SeenUrls, StateFile};
+
+/// Finds the unread entries that weren't unread on the previous poll.
+///
+/// An entry counts as seen if either its id or its hash matches one of the cached entries.
+/// Comparing sets rather than positions means entries that were marked as read elsewhere or
+/// that moved around in the list don't throw off the comparison. The new entries are returned
+/// in the order they appear in `unread`.
+pub fn find_new_entries(cache: &[SeenEntry], unread: &[Entry]) -> Vec<Entry> {
+    let ids: HashSet<u64> = cache.iter().map(|entry| entry.id).collect();
+    let hashes: HashSet<&str> = cache.iter().map(|entry| entry.hash.as_str()).collect();
+
+    unread
+        .iter()
+        .filter(|entry| !ids.contains(&entry.id) && !hashes.contains(entry.hash.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Query parameters that only tell where a visitor came from, which differ between the feeds
+/// reposting an article. Parameters starting with `utm_` are dropped as well.
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid", "_hsenc",
+    "_hsmi", "ref", "ref_src",
+];
+
+/// How many of the words of two titles have to be shared for them to count as the same
+/// article
+const TITLE_SIMILARITY: f64 = 0.8;
+
+/// Titles with fewer words than this only count as the same if they are, as a word or two
+/// in common says little
+const MIN_FUZZY_WORDS: usize = 3;
+
+/// Drops the entries that have already been notified about, by their URL with `--dedup-urls`
+/// or by a similar title with `--dedup-titles`, and remembers all of them as seen now.
+///
+/// Duplicates are remembered too, so that an article that keeps being reposted stays within
+/// `--dedup-window` and isn't pruned while it keeps coming back.
+pub fn remember_urls(
+    state: &mut StateFile,
+    entries: Vec<Entry>,
+    settings: &Settings,
+) -> Vec<Entry> {
+    remember_urls_at(state, entries, settings, state::now())
+}
+
+fn remember_urls_at(
+    state: &mut StateFile,
+    entries: Vec<Entry>,
+    settings: &Settings,
+    now: u64,
+) -> Vec<Entry> {
+    state.compact(now);
+
+    let mut unseen = vec![];
+    for entry in entries {
+        let duplicate = duplicate(&state.state.seen_urls, &entry, settings, now);
+        state
+            .state
+            .seen_urls
+            .insert(&normalize_url(&entry.url), &entry.title, now);
+        state.mark_dirty();
+        match duplicate {
+            Some(reason) => debug!("Skipping entry {} as {}: {}", entry.id, reason, entry.url),
+            None => unseen.push(entry),
+        }
+    }
+
+    state.compact(now);
+    unseen
+}
+
+/// Why the entry counts as already notified about, if it does
+pub fn duplicate(
+    seen_urls: &SeenUrls,
+    entry: &Entry,
+    settings: &Settings,
+    now: u64,
+) -> Option<&'static str> {
+    if !settings.dedup_urls && !settings.dedup_titles {
+        return None;
+    }
+    let since = settings
+        .dedup_window
+        .map_or(0, |window| now.saturating_sub(window.as_secs()));
+    let seen_at = seen_urls.seen_at(&normalize_url(&entry.url));
+    if settings.dedup_urls && seen_at.is_some_and(|seen_at| seen_at >= since) {
+        return Some("its URL has already been notified about");
+    }
+    if settings.dedup_titles {
+        let words = title_words(&entry.title);
+        if seen_urls
+            .iter()
+            .filter(|seen| seen.seen_at >= since)
+            .any(|seen| similar_titles(&words, &title_words(&seen.title)))
+        {
+            return Some("an entry with a similar title has already been notified about");
+        }
+    }
+    None
+}
+
+/// The URL without its fragment, tracking parameters and trailing slash, so that the links
+/// of different feeds to the same article compare equal
+pub fn normalize_url(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    parsed.set_fragment(None);
+    let query: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(name, _)| !name.starts_with("utm_") && !TRACKING_PARAMS.contains(&&**name))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if query.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(query);
+    }
+    let mut url = parsed.to_string();
+    if url.ends_with('/') {
+        url.pop();
+    }
+    url
+}
+
+fn title_words(title: &str) -> HashSet<String> {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Whether the titles share most of their words, going by the Jaccard index of the sets of
+/// words
+fn similar_titles(a: &HashSet<String>, b: &HashSet<String>) -> bool {
+    if a.is_empty() || b.is_empty() {
+        return false;
+    }
+    if a.len() < MIN_FUZZY_WORDS || b.len() < MIN_FUZZY_WORDS {
+        return a == b;
+    }
+    let shared = a.intersection(b).count();
+    let all = a.union(b).count();
+    shared as f64 / all as f64 >= TITLE_SIMILARITY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_settings;
+    use crate::models::test_entry;
+    use std::time::Duration;
+
+    fn seen(entries: &[Entry]) -> Vec<SeenEntry> {
+        entries.iter().map(SeenEntry::from).collect()
+    }
+
+    fn ids(entries: &[Entry]) -> Vec<u64> {
+        entries.iter().map(|entry| entry.id).collect()
+    }
+
+    #[test]
+    fn url_dedup_survives_a_changed_id() {
+        let settings = test_settings(&["--dedup-urls"]);
+        let mut state =
+            StateFile::open(None, Duration::ZERO, 100, Duration::from_secs(86400)).unwrap();
+        let first = test_entry(1);
+        assert_eq!(
+            ids(&remember_urls(&mut state, vec![first.clone()], &settings)),
+            [1]
+        );
+
+        // The feed was added again, giving the same article a new id and hash
+        let mut recreated = test_entry(2);
+        recreated.url = first.url.clone();
+        let new_entries = find_new_entries(&seen(&[first]), &[recreated]);
+        assert_eq!(ids(&new_entries), [2]);
+        assert!(remember_urls(&mut state, new_entries, &settings).is_empty());
+    }
+
+    #[test]
+    fn reposts_keep_the_dedup_window_open() {
+        const MINUTE: u64 = 60;
+        let settings = test_settings(&["--dedup-urls", "--dedup-window", "1h"]);
+        let mut state =
+            StateFile::open(None, Duration::ZERO, 100, Duration::from_secs(86400)).unwrap();
+        let start = 1_000_000;
+        let mut remember = |id, minutes| {
+            let mut repost = test_entry(id);
+            repost.url = test_entry(1).url;
+            ids(&remember_urls_at(
+                &mut state,
+                vec![repost],
+                &settings,
+                start + minutes * MINUTE,
+            ))
+        };
+        assert_eq!(remember(1, 0), [1]);
+        assert!(remember(2, 30).is_empty());
+        // More than the window after the first post, but not after the last repost
+        assert!(remember(3, 80).is_empty());
+        assert_eq!(remember(4, 200), [4]);
+        assert!(remember(5, 210).is_empty());
+    }
+
+    #[test]
+    fn everything_is_new_with_an_empty_cache() {
+        let unread = [test_entry(1), test_entry(2)];
+        assert_eq!(ids(&find_new_entries(&[], &unread)), [1, 2]);
+    }
+
+    #[test]
+    fn reordered_entries_are_not_new() {
+        let cache = seen(&[test_entry(1), test_entry(2), test_entry(3)]);
+        let unread = [test_entry(3), test_entry(1), test_entry(2)];
+        assert!(find_new_entries(&cache, &unread).is_empty());
+    }
+
+    #[test]
+    fn removed_entries_dont_hide_new_ones() {
+        // 1 and 2 were marked as read elsewhere while 4 and 5 arrived
+        let cache = seen(&[test_entry(1), test_entry(2), test_entry(3)]);
+        let unread = [test_entry(4), test_entry(3), test_entry(5)];
+        assert_eq!(ids(&find_new_entries(&cache, &unread)), [4, 5]);
+    }
+
+    #[test]
+    fn entries_with_a_known_hash_are_not_new() {
+        let cache = seen(&[test_entry(1)]);
+        let mut moved = test_entry(2);
+        moved.hash = test_entry(1).hash;
+        assert!(find_new_entries(&cache, &[moved]).is_empty());
+    }
+}
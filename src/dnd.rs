@@ -0,0 +1,81 @@
+//! Telling whether the desktop is in Do Not Disturb mode, in which the notification service
+//! would swallow notifications without showing them
+//!
+//! There is no standard way to ask, so each desktop is asked in its own way: KDE and others
+//! through the `Inhibited` property of the notification service, GNOME through its
+//! `show-banners` setting, XFCE through xfconf, and dunst and mako through their control
+//! commands.
+
+use std::process::Command;
+use std::time::Duration;
+
+use crate::cached_check::CachedCheck;
+
+/// Asked less often than the other checks, as asking may mean running commands
+static CHECK: CachedCheck = CachedCheck::new(Duration::from_secs(5), check);
+
+/// Whether Do Not Disturb is on
+pub fn is_active() -> bool {
+    CHECK.get()
+}
+
+fn check() -> bool {
+    if notifications_inhibited() {
+        return true;
+    }
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .to_lowercase();
+    if desktop.contains("gnome") || desktop.contains("unity") || desktop.contains("budgie") {
+        output(
+            "gsettings",
+            &["get", "org.gnome.desktop.notifications", "show-banners"],
+        )
+        .is_some_and(|output| output == "false")
+    } else if desktop.contains("xfce") {
+        output(
+            "xfconf-query",
+            &["-c", "xfce4-notifyd", "-p", "/do-not-disturb"],
+        )
+        .is_some_and(|output| output == "true")
+    } else {
+        output("dunstctl", &["is-paused"]).is_some_and(|output| output == "true")
+            || output("makoctl", &["mode"])
+                .is_some_and(|output| output.lines().any(|mode| mode == "do-not-disturb"))
+    }
+}
+
+/// The trimmed output of the command, `None` if it isn't installed or fails
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn notifications_inhibited() -> bool {
+    use zbus::zvariant::OwnedValue;
+
+    let Some(connection) = crate::session::session_bus() else {
+        return false;
+    };
+    connection
+        .call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &("org.freedesktop.Notifications", "Inhibited"),
+        )
+        .and_then(|reply| reply.body::<OwnedValue>())
+        .ok()
+        .and_then(|value| bool::try_from(value).ok())
+        .unwrap_or(false)
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn notifications_inhibited() -> bool {
+    false
+}
@@ -0,0 +1,69 @@
+//! Describing what would be done about new entries, for trying out the settings without
+//! sending any notifications
+
+use clap::ValueEnum;
+
+use crate::config::Settings;
+use crate::diff;
+use crate::filter::is_too_old;
+use crate::models::Entry;
+use crate::state::{self, SeenUrls};
+
+/// A line describing whether a notification would be sent about the entry and which rules
+/// it matched, e.g. `notify 42 "Title" from Feed: template 1, urgency rule 2, to desktop`.
+///
+/// Rules are numbered from 1 in the order they appear in the config file.
+pub fn describe(entry: &Entry, settings: &Settings, seen_urls: &SeenUrls, quiet: bool) -> String {
+    let what = format!("{} {:?} from {}", entry.id, entry.title, entry.feed.title);
+    if !settings.filter.allows(entry) {
+        return format!("skip {}: excluded by the feed and category filters", what);
+    }
+    if let Some(rule) = position(&settings.mute_rules, |rule| rule.matches(entry)) {
+        return format!("skip {}: muted by mute rule {}", what, rule);
+    }
+    if is_too_old(entry, settings.max_entry_age) {
+        return format!("skip {}: published too long ago", what);
+    }
+    if let Some(reason) = diff::duplicate(seen_urls, entry, settings, state::now()) {
+        return format!("skip {}: {}", what, reason);
+    }
+    if !settings.notify {
+        return format!("skip {}: notifications are turned off", what);
+    }
+
+    let mut matched = vec![];
+    if let Some(rule) = position(&settings.feed_templates, |t| t.matches(entry)) {
+        matched.push(format!("template {}", rule));
+    }
+    if let Some(rule) = position(&settings.urgency_rules, |rule| rule.matches(entry)) {
+        matched.push(format!("urgency rule {}", rule));
+    }
+    if let Some(rule) = position(&settings.open_rules, |rule| rule.matches(entry)) {
+        matched.push(format!("open rule {}", rule));
+    }
+    let notifiers: Vec<String> = settings
+        .notifiers
+        .iter()
+        .filter(|kind| {
+            settings
+                .notifier_filters
+                .get(kind)
+                .is_none_or(|filter| filter.allows(entry))
+        })
+        .filter_map(|kind| kind.to_possible_value())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    if notifiers.is_empty() {
+        return format!("skip {}: excluded by the filters of every notifier", what);
+    }
+    matched.push(format!("to {}", notifiers.join(", ")));
+    if quiet {
+        matched.push("held back during quiet hours".to_string());
+    }
+    format!("notify {}: {}", what, matched.join(", "))
+}
+
+/// The number of the first item matching, counting from 1
+fn position<T>(items: &[T], matches: impl Fn(&T) -> bool) -> Option<usize> {
+    items.iter().position(matches).map(|i| i + 1)
+}
@@ -0,0 +1,82 @@
+//! Warning about feeds that Miniflux keeps failing to fetch, which otherwise go unnoticed
+//! until someone wonders why a feed has gone quiet
+//!
+//! Each feed is only notified about once, when it starts failing. Once it can be fetched
+//! again, it is notified about the next time it fails.
+
+use log::{error, info};
+use std::time::{Duration, Instant};
+
+use crate::config::Settings;
+use crate::notify::{send_feed_error_notification, Notifiers};
+use crate::source::{FeedSource, SourceKind};
+use crate::state::StateFile;
+
+/// Checks the feeds for errors every so often
+#[derive(Debug)]
+pub struct FeedErrorCheck {
+    interval: Duration,
+    threshold: u32,
+    last_check: Option<Instant>,
+}
+
+impl FeedErrorCheck {
+    /// `None` if feeds aren't checked, which is only supported with Miniflux
+    pub fn new(settings: &Settings) -> Option<Self> {
+        let interval = settings.feed_errors_interval?;
+        if settings.source != SourceKind::Miniflux {
+            error!("Checking feeds for errors is only supported with Miniflux");
+            return None;
+        }
+        Some(Self {
+            interval,
+            threshold: settings.feed_error_threshold.max(1),
+            last_check: None,
+        })
+    }
+
+    /// Checks the feeds if the interval has passed since the last check, notifying about
+    /// the ones that have started failing
+    pub fn check_if_due(
+        &mut self,
+        client: &dyn FeedSource,
+        settings: &Settings,
+        state: &mut StateFile,
+        notifiers: &mut Notifiers,
+    ) {
+        if self
+            .last_check
+            .is_some_and(|last_check| last_check.elapsed() < self.interval)
+        {
+            return;
+        }
+        self.last_check = Some(Instant::now());
+
+        let feeds = match client.feeds() {
+            Ok(Some(feeds)) => feeds,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to check the feeds for errors: {}", e);
+                return;
+            }
+        };
+        let mut failing = vec![];
+        for feed in feeds {
+            if feed.parsing_error_count < self.threshold {
+                continue;
+            }
+            failing.push(feed.id);
+            if state.state.failing_feeds.contains(&feed.id) {
+                continue;
+            }
+            info!(
+                "Miniflux failed to fetch {} {} times: {}",
+                feed.title, feed.parsing_error_count, feed.parsing_error_message
+            );
+            if let Err(e) = send_feed_error_notification(notifiers, feed, settings) {
+                error!("{:?}", e);
+            }
+        }
+        state.set_failing_feeds(failing);
+    }
+}
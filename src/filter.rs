@@ -0,0 +1,288 @@
+//! Filtering which entries are notified about
+
+use chrono::Utc;
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::models::Entry;
+use crate::rules::MuteRule;
+
+/// A feed or category, given either by its id or by a glob matched against its title
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawMatcher")]
+pub enum Matcher {
+    Id(u32),
+    Title(Pattern),
+}
+
+impl Matcher {
+    pub fn matches(&self, id: u32, title: &str) -> bool {
+        match self {
+            Matcher::Id(matcher_id) => id == *matcher_id,
+            Matcher::Title(pattern) => pattern.matches_with(
+                title,
+                MatchOptions {
+                    case_sensitive: false,
+                    ..MatchOptions::new()
+                },
+            ),
+        }
+    }
+}
+
+impl FromStr for Matcher {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(id) = s.parse() {
+            return Ok(Matcher::Id(id));
+        }
+        Pattern::new(s)
+            .map(Matcher::Title)
+            .map_err(|e| format!("invalid title pattern {:?}: {}", s, e))
+    }
+}
+
+/// Matchers in the config file can be given as a bare id or as a string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMatcher {
+    Id(u32),
+    Text(String),
+}
+
+impl TryFrom<RawMatcher> for Matcher {
+    type Error = String;
+
+    fn try_from(raw: RawMatcher) -> Result<Self, Self::Error> {
+        match raw {
+            RawMatcher::Id(id) => Ok(Matcher::Id(id)),
+            RawMatcher::Text(text) => text.parse(),
+        }
+    }
+}
+
+/// Whether the entry's feed matches one of `feeds` or its category one of `categories`
+pub fn matches_feed_or_category(feeds: &[Matcher], categories: &[Matcher], entry: &Entry) -> bool {
+    let feed = &entry.feed;
+    feeds.iter().any(|m| m.matches(feed.id, &feed.title))
+        || feed.category.as_ref().is_some_and(|category| {
+            categories
+                .iter()
+                .any(|m| m.matches(category.id, &category.title))
+        })
+}
+
+/// Whether the entry was published longer ago than `max_age`
+pub fn is_too_old(entry: &Entry, max_age: Option<Duration>) -> bool {
+    max_age.is_some_and(|max_age| {
+        let age = Utc::now() - entry.published_at;
+        age.to_std().is_ok_and(|age| age > max_age)
+    })
+}
+
+/// Decides which entries are notified about based on their feed and its category
+///
+/// An entry passes if its feed matches one of the included feeds (or no feeds are included
+/// explicitly) and none of the excluded feeds, and the same goes for its category. Entries
+/// whose feed has no category only pass if no categories are included explicitly.
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilter {
+    pub include_feeds: Vec<Matcher>,
+    pub exclude_feeds: Vec<Matcher>,
+    pub include_categories: Vec<Matcher>,
+    pub exclude_categories: Vec<Matcher>,
+}
+
+impl EntryFilter {
+    pub fn allows(&self, entry: &Entry) -> bool {
+        let feed = &entry.feed;
+        let feed_matches = |m: &Matcher| m.matches(feed.id, &feed.title);
+        let category_matches = |m: &Matcher| match &feed.category {
+            Some(category) => m.matches(category.id, &category.title),
+            None => false,
+        };
+
+        (self.include_feeds.is_empty() || self.include_feeds.iter().any(feed_matches))
+            && !self.exclude_feeds.iter().any(feed_matches)
+            && (self.include_categories.is_empty()
+                || self.include_categories.iter().any(category_matches))
+            && !self.exclude_categories.iter().any(category_matches)
+    }
+}
+
+/// Narrows down which of the entries that pass the global filters are sent to one notifier,
+/// e.g. to only push the important feeds to a phone
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "RawNotifierFilter")]
+pub struct NotifierFilter {
+    pub entries: EntryFilter,
+    pub mute_rules: Vec<MuteRule>,
+}
+
+impl NotifierFilter {
+    pub fn allows(&self, entry: &Entry) -> bool {
+        self.entries.allows(entry) && !self.mute_rules.iter().any(|rule| rule.matches(entry))
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawNotifierFilter {
+    include_feeds: Vec<Matcher>,
+    exclude_feeds: Vec<Matcher>,
+    include_categories: Vec<Matcher>,
+    exclude_categories: Vec<Matcher>,
+    mute: Vec<MuteRule>,
+}
+
+impl From<RawNotifierFilter> for NotifierFilter {
+    fn from(raw: RawNotifierFilter) -> Self {
+        Self {
+            entries: EntryFilter {
+                include_feeds: raw.include_feeds,
+                exclude_feeds: raw.exclude_feeds,
+                include_categories: raw.include_categories,
+                exclude_categories: raw.exclude_categories,
+            },
+            mute_rules: raw.mute,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{test_entry, Category};
+
+    fn matchers(specs: &[&str]) -> Vec<Matcher> {
+        specs.iter().map(|spec| spec.parse().unwrap()).collect()
+    }
+
+    fn from_feed(id: u32, title: &str) -> Entry {
+        let mut entry = test_entry(1);
+        entry.feed.id = id;
+        entry.feed.title = title.to_string();
+        entry
+    }
+
+    #[test]
+    fn matchers_are_ids_or_title_globs() {
+        assert!(matches!("12".parse(), Ok(Matcher::Id(12))));
+        assert!(matches!("Tech*".parse(), Ok(Matcher::Title(_))));
+        assert!("[".parse::<Matcher>().is_err());
+
+        let glob: Matcher = "tech *".parse().unwrap();
+        assert!(glob.matches(5, "Tech Blog"));
+        assert!(!glob.matches(5, "Fintech Blog"));
+        // An id only matches the id, even if the title happens to be that number
+        assert!(!Matcher::Id(5).matches(6, "5"));
+    }
+
+    fn in_category(category: Option<(u32, &str)>) -> Entry {
+        let mut entry = test_entry(1);
+        entry.feed.category = category.map(|(id, title)| Category {
+            id,
+            title: title.to_string(),
+        });
+        entry
+    }
+
+    #[test]
+    fn feed_filters() {
+        let feeds = [from_feed(1, "Feed"), from_feed(2, "Tech Blog")];
+        // Included feeds, excluded feeds, and whether each of the feeds passes
+        let cases: [(&[&str], &[&str], [bool; 2]); 7] = [
+            (&[], &[], [true, true]),
+            (&["1"], &[], [true, false]),
+            (&["tech*"], &[], [false, true]),
+            (&["1", "2"], &[], [true, true]),
+            (&[], &["2"], [true, false]),
+            // Excluding wins over including
+            (&["*"], &["Tech Blog"], [true, false]),
+            (&["1"], &["1"], [false, false]),
+        ];
+        for (include, exclude, expected) in cases {
+            let filter = EntryFilter {
+                include_feeds: matchers(include),
+                exclude_feeds: matchers(exclude),
+                ..EntryFilter::default()
+            };
+            let allowed = feeds.each_ref().map(|entry| filter.allows(entry));
+            assert_eq!(
+                allowed, expected,
+                "including {:?}, excluding {:?}",
+                include, exclude
+            );
+        }
+    }
+
+    #[test]
+    fn category_filters() {
+        let entries = [
+            in_category(Some((1, "News"))),
+            in_category(Some((2, "Tech"))),
+            in_category(None),
+        ];
+        // Included categories, excluded categories, and whether each of the entries passes
+        let cases: [(&[&str], &[&str], [bool; 3]); 6] = [
+            (&[], &[], [true, true, true]),
+            // Entries without a category can't be in an included one
+            (&["news"], &[], [true, false, false]),
+            (&["1", "Tech"], &[], [true, true, false]),
+            (&[], &["2"], [true, false, true]),
+            (&["*"], &["Tech"], [true, false, false]),
+            (&["News"], &["1"], [false, false, false]),
+        ];
+        for (include, exclude, expected) in cases {
+            let filter = EntryFilter {
+                include_categories: matchers(include),
+                exclude_categories: matchers(exclude),
+                ..EntryFilter::default()
+            };
+            let allowed = entries.each_ref().map(|entry| filter.allows(entry));
+            assert_eq!(
+                allowed, expected,
+                "including {:?}, excluding {:?}",
+                include, exclude
+            );
+        }
+    }
+
+    #[test]
+    fn feed_and_category_filters_both_apply() {
+        let filter = EntryFilter {
+            include_feeds: matchers(&["1"]),
+            include_categories: matchers(&["Tech"]),
+            ..EntryFilter::default()
+        };
+        let mut entry = in_category(Some((2, "Tech")));
+        assert!(filter.allows(&entry));
+        entry.feed.id = 2;
+        assert!(!filter.allows(&entry));
+        assert!(!filter.allows(&in_category(Some((1, "News")))));
+    }
+
+    #[test]
+    fn feed_or_category_matches() {
+        let (feeds, categories) = (matchers(&["Tech Blog"]), matchers(&["News"]));
+        assert!(matches_feed_or_category(
+            &feeds,
+            &categories,
+            &in_category(Some((1, "News")))
+        ));
+        assert!(matches_feed_or_category(
+            &feeds,
+            &categories,
+            &from_feed(2, "Tech Blog")
+        ));
+        assert!(!matches_feed_or_category(
+            &feeds,
+            &categories,
+            &in_category(None)
+        ));
+        assert!(!matches_feed_or_category(&[], &[], &test_entry(1)));
+    }
+}
@@ -0,0 +1,304 @@
+//! The entries notified about, kept in the state file, and the `history` subcommand for looking
+//! back at what was dismissed too quickly
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Utc};
+use clap::Args as ClapArgs;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::config::Settings;
+use crate::list::print_table;
+use crate::mark_all_read::parse_time;
+use crate::models::Entry;
+use crate::state::{self, State};
+
+/// How many notified entries are kept, dropping the oldest first
+const HISTORY_LIMIT: usize = 1000;
+/// The action recorded for notifications that went unanswered for `--mark-read-after`
+const EXPIRED: &str = "expired";
+
+/// An entry that was notified about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotifiedEntry {
+    /// Seconds since the unix epoch
+    pub notified_at: u64,
+    pub entry_id: u64,
+    pub feed: String,
+    pub title: String,
+    /// Where following the notification led
+    pub url: String,
+    /// The last action used on the notification, e.g. "open" or "mark_read", `None` if it was
+    /// dismissed or timed out
+    #[serde(default)]
+    pub action: Option<String>,
+}
+
+/// The notified entries, shared between the notifiers recording them, the threads handling the
+/// actions of notifications and the state file.
+///
+/// Cloning gives another handle to the same entries.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: VecDeque<NotifiedEntry>,
+    /// The entries whose notifications are waiting to be answered for `--mark-read-after`,
+    /// with when they were shown. Not persisted, as the notifications can't be answered
+    /// after a restart.
+    awaiting: BTreeMap<u64, u64>,
+    /// Whether the entries changed since they were last persisted
+    changed: bool,
+}
+
+impl History {
+    pub fn new(entries: Vec<NotifiedEntry>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                entries: entries.into(),
+                awaiting: BTreeMap::new(),
+                changed: false,
+            })),
+        }
+    }
+
+    /// Records that the entry was notified about, leading to `url`
+    pub fn record(&self, entry: &Entry, url: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.push_back(NotifiedEntry {
+            notified_at: state::now(),
+            entry_id: entry.id,
+            feed: entry.feed.title.clone(),
+            title: entry.title.clone(),
+            url: url.to_string(),
+            action: None,
+        });
+        while inner.entries.len() > HISTORY_LIMIT {
+            inner.entries.pop_front();
+        }
+        inner.changed = true;
+    }
+
+    /// Records that a notification with actions was shown for the entry, for it to expire if
+    /// none of them is used
+    pub fn await_answer(&self, entry_id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.awaiting.insert(entry_id, state::now());
+    }
+
+    /// Records the action used on the last notification about the entry
+    pub fn record_action(&self, entry_id: u64, action: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.awaiting.remove(&entry_id);
+        if let Some(notified) = inner
+            .entries
+            .iter_mut()
+            .rev()
+            .find(|notified| notified.entry_id == entry_id)
+        {
+            notified.action = Some(action.to_string());
+            inner.changed = true;
+        }
+    }
+
+    /// Records the entries whose notifications were shown at or before `cutoff` without any
+    /// action used on them since as having expired, and returns their IDs
+    pub fn take_expired(&self, cutoff: u64) -> Vec<u64> {
+        let mut inner = self.inner.lock().unwrap();
+        let expired: Vec<u64> = inner
+            .awaiting
+            .iter()
+            .filter(|(_, shown_at)| **shown_at <= cutoff)
+            .map(|(entry_id, _)| *entry_id)
+            .collect();
+        for entry_id in &expired {
+            inner.awaiting.remove(entry_id);
+            if let Some(notified) = inner
+                .entries
+                .iter_mut()
+                .rev()
+                .find(|notified| notified.entry_id == *entry_id)
+                .filter(|notified| notified.action.is_none())
+            {
+                notified.action = Some(EXPIRED.to_string());
+                inner.changed = true;
+            }
+        }
+        expired
+    }
+
+    /// All of the notified entries, if they changed since the last call
+    pub fn take_changes(&self) -> Option<Vec<NotifiedEntry>> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.changed {
+            return None;
+        }
+        inner.changed = false;
+        Some(inner.entries.iter().cloned().collect())
+    }
+}
+
+/// The options of the `history` subcommand
+#[derive(ClapArgs, Debug, Clone)]
+pub struct HistoryArgs {
+    /// Only list the entries notified about since this time, given as a date, an RFC 3339 time
+    /// or how long ago, e.g. "2024-06-01" or "1h"
+    #[clap(long, value_parser = parse_time)]
+    since: Option<DateTime<Utc>>,
+
+    /// Print the entries as JSON
+    #[clap(long)]
+    json: bool,
+
+    /// Open the entry with this number in the list instead of listing them
+    #[clap(long, value_name = "NUMBER")]
+    open: Option<usize>,
+}
+
+/// A notified entry as listed, newest first
+#[derive(Debug, Serialize)]
+struct Listed {
+    number: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server: Option<String>,
+    notified_at: DateTime<Utc>,
+    entry_id: u64,
+    feed: String,
+    title: String,
+    url: String,
+    action: Option<String>,
+}
+
+/// Lists the entries notified about on each server, or opens one of them
+///
+/// The history is read from the state file, which a running notifier only writes every
+/// `--state-flush-interval`.
+pub fn run(args: &HistoryArgs, servers: &[Settings]) -> Result<()> {
+    let mut notified = vec![];
+    for settings in servers {
+        let Some(path) = state::path_for(settings) else {
+            return Err(anyhow!(
+                "Could not determine the state file, use --state-file"
+            ));
+        };
+        let server =
+            (servers.len() > 1).then(|| settings.name.clone().unwrap_or(settings.server.clone()));
+        notified.extend(
+            State::load(&path)?
+                .history
+                .into_iter()
+                .map(|entry| (server.clone(), entry)),
+        );
+    }
+    let since = args.since.map(|since| since.timestamp().max(0) as u64);
+    notified.retain(|(_, entry)| since.is_none_or(|since| entry.notified_at >= since));
+    notified.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.notified_at));
+
+    let listed: Vec<Listed> = notified
+        .into_iter()
+        .enumerate()
+        .map(|(i, (server, entry))| Listed {
+            number: i + 1,
+            server,
+            notified_at: DateTime::from_timestamp(entry.notified_at as i64, 0).unwrap_or_default(),
+            entry_id: entry.entry_id,
+            feed: entry.feed,
+            title: entry.title,
+            url: entry.url,
+            action: entry.action,
+        })
+        .collect();
+
+    if let Some(number) = args.open {
+        let entry = listed
+            .iter()
+            .find(|entry| entry.number == number)
+            .ok_or_else(|| anyhow!("There is no entry {} in the history", number))?;
+        println!("Opening {}", entry.url);
+        open::that_detached(&entry.url)?;
+        return Ok(());
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&listed)?);
+        return Ok(());
+    }
+    let rows: Vec<[String; 5]> = listed
+        .into_iter()
+        .map(|entry| {
+            let feed = match entry.server {
+                Some(server) => format!("{}: {}", server, entry.feed),
+                None => entry.feed,
+            };
+            [
+                entry.number.to_string(),
+                entry
+                    .notified_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string(),
+                feed,
+                entry.title,
+                entry.action.unwrap_or_default().replace('_', " "),
+            ]
+        })
+        .collect();
+    print_table(["#", "NOTIFIED", "FEED", "TITLE", "ACTION"], &rows);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_entry;
+
+    fn notified(entry_id: u64) -> NotifiedEntry {
+        NotifiedEntry {
+            notified_at: 0,
+            entry_id,
+            feed: "Feed".to_string(),
+            title: format!("Entry {}", entry_id),
+            url: format!("https://example.com/{}", entry_id),
+            action: None,
+        }
+    }
+
+    #[test]
+    fn only_shown_notifications_expire() {
+        // Left unanswered before a restart, and so long ago
+        let history = History::new(vec![notified(1)]);
+        let cutoff = state::now();
+        assert!(history.take_expired(cutoff).is_empty());
+
+        // Recorded before sending, which then failed
+        history.record(&test_entry(2), "https://example.com/2");
+        history.record(&test_entry(3), "https://example.com/3");
+        history.record(&test_entry(4), "https://example.com/4");
+        history.await_answer(3);
+        history.await_answer(4);
+        history.record_action(4, "open");
+        assert!(history.take_expired(cutoff.saturating_sub(1)).is_empty());
+        assert_eq!(history.take_expired(state::now()), [3]);
+        assert!(history.take_expired(state::now()).is_empty());
+
+        let actions: Vec<_> = history
+            .take_changes()
+            .unwrap()
+            .into_iter()
+            .map(|notified| (notified.entry_id, notified.action))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                (1, None),
+                (2, None),
+                (3, Some(EXPIRED.to_string())),
+                (4, Some("open".to_string())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Caching the icons of feeds on disk, to show them in notifications
+
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::*;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::Settings;
+use crate::source::FeedSource;
+
+/// The icons of feeds, fetched from the source the first time they are needed and kept in
+/// the cache directory from then on
+pub struct IconCache {
+    dir: PathBuf,
+    source: Arc<dyn FeedSource>,
+    /// The icons looked up so far, `None` for feeds without one
+    icons: HashMap<u32, Option<PathBuf>>,
+}
+
+impl IconCache {
+    /// A cache in the user's cache directory, `None` if there is none. Each server gets a
+    /// directory of its own, as their feed ids overlap.
+    pub fn new(settings: &Settings, source: Arc<dyn FeedSource>) -> Option<Self> {
+        let mut dir = dirs::cache_dir()?.join("miniflux-notifier").join("icons");
+        if let Some(name) = &settings.name {
+            dir = dir.join(name);
+        }
+        Some(Self {
+            dir,
+            source,
+            icons: HashMap::new(),
+        })
+    }
+
+    /// The path of the feed's icon, fetching it if it isn't cached yet
+    pub fn get(&mut self, feed_id: u32) -> Option<PathBuf> {
+        if let Some(icon) = self.icons.get(&feed_id) {
+            return icon.clone();
+        }
+        let icon = match self.find_cached(feed_id) {
+            Some(path) => Some(path),
+            None => self.fetch(feed_id).unwrap_or_else(|e| {
+                warn!("Failed to get the icon of feed {}: {:?}", feed_id, e);
+                None
+            }),
+        };
+        self.icons.insert(feed_id, icon.clone());
+        icon
+    }
+
+    fn find_cached(&self, feed_id: u32) -> Option<PathBuf> {
+        let prefix = format!("{}.", feed_id);
+        fs::read_dir(&self.dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(&prefix))
+            })
+    }
+
+    fn fetch(&self, feed_id: u32) -> Result<Option<PathBuf>> {
+        let Some(icon) = self.source.feed_icon(feed_id)? else {
+            debug!("Feed {} has no icon", feed_id);
+            return Ok(None);
+        };
+        // The data is given as "<mime type>;base64,<data>"
+        let (_, data) = icon
+            .data
+            .split_once(";base64,")
+            .ok_or_else(|| anyhow!("the icon isn't base64 encoded"))?;
+        let data = BASE64_STANDARD.decode(data)?;
+
+        let path = self
+            .dir
+            .join(format!("{}.{}", feed_id, extension(&icon.mime_type)));
+        write(&path, &data).with_context(|| format!("Failed to write icon {}", path.display()))?;
+        Ok(Some(path))
+    }
+}
+
+fn write(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, data)
+}
+
+/// The file extension for the mime type, which the notification service may need to
+/// recognize the format
+fn extension(mime_type: &str) -> &str {
+    match mime_type {
+        "image/x-icon" | "image/vnd.microsoft.icon" => "ico",
+        "image/svg+xml" => "svg",
+        "image/jpeg" => "jpg",
+        mime_type => mime_type.strip_prefix("image/").unwrap_or("img"),
+    }
+}
@@ -0,0 +1,117 @@
+//! A badge with the number of unread entries on the launcher of Miniflux or the browser in
+//! docks such as Dash to Dock and Plank
+//!
+//! The count is published with the `com.canonical.Unity.LauncherEntry` API, which the docks
+//! match to a launcher by the name of its desktop file.
+
+use anyhow::Result;
+
+/// Shows the badge on the launcher of the desktop file until the process exits
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn start(desktop_file: &str) -> Result<()> {
+    unity::start(desktop_file)
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub fn start(_desktop_file: &str) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Launcher badges are only supported on Linux and BSD"
+    ))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod unity {
+    use anyhow::{Context, Result};
+    use log::debug;
+    use std::collections::HashMap;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use zbus::blocking::{Connection, ConnectionBuilder};
+    use zbus::zvariant::{OwnedValue, Value};
+    use zbus::{dbus_interface, SignalContext};
+
+    use crate::status_bar;
+
+    const PATH: &str = "/com/canonical/unity/launcherentry/miniflux_notifier";
+    /// How often the unread entries are checked for a change of the count
+    const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+    /// How often the count is sent again even if it didn't change, for docks started since
+    const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn start(desktop_file: &str) -> Result<()> {
+        let desktop_file = match desktop_file.ends_with(".desktop") {
+            true => desktop_file.to_string(),
+            false => format!("{}.desktop", desktop_file),
+        };
+        let connection = ConnectionBuilder::session()?
+            .serve_at(
+                PATH,
+                LauncherEntry(format!("application://{}", desktop_file)),
+            )?
+            .build()
+            .context("Failed to connect to the session bus for the launcher badge")?;
+        thread::spawn(move || update(connection));
+        Ok(())
+    }
+
+    /// Sends the count whenever it changes
+    fn update(connection: Connection) {
+        let mut last = None;
+        let mut sent = Instant::now();
+        loop {
+            let count = count();
+            if last != Some(count) || sent.elapsed() >= REFRESH_INTERVAL {
+                if let Err(e) = signal_update(&connection) {
+                    debug!("Failed to update the launcher badge: {}", e);
+                }
+                last = Some(count);
+                sent = Instant::now();
+            }
+            thread::sleep(UPDATE_INTERVAL);
+        }
+    }
+
+    fn signal_update(connection: &Connection) -> zbus::Result<()> {
+        let entry = connection
+            .object_server()
+            .interface::<_, LauncherEntry>(PATH)?;
+        let app_uri = entry.get().0.clone();
+        zbus::block_on(LauncherEntry::update(
+            entry.signal_context(),
+            &app_uri,
+            properties(),
+        ))
+    }
+
+    /// The number of unread entries on all servers, 0 until a server has been polled
+    fn count() -> u32 {
+        status_bar::unread().map_or(0, |(total, _)| total)
+    }
+
+    /// The badge is hidden while there are no unread entries
+    fn properties() -> HashMap<String, OwnedValue> {
+        let count = count();
+        HashMap::from([
+            ("count".to_string(), Value::from(i64::from(count)).into()),
+            ("count-visible".to_string(), Value::from(count > 0).into()),
+        ])
+    }
+
+    /// The launcher entry, with the URI of the desktop file it is for
+    struct LauncherEntry(String);
+
+    #[dbus_interface(name = "com.canonical.Unity.LauncherEntry")]
+    impl LauncherEntry {
+        /// Lets docks ask for the badge rather than waiting for the next update
+        fn query(&self) -> (String, HashMap<String, OwnedValue>) {
+            (self.0.clone(), properties())
+        }
+
+        #[dbus_interface(signal)]
+        async fn update(
+            ctxt: &SignalContext<'_>,
+            app_uri: &str,
+            properties: HashMap<String, OwnedValue>,
+        ) -> zbus::Result<()>;
+    }
+}
@@ -0,0 +1,48 @@
+//! Desktop notifications for new entries on a Miniflux server, or on any server speaking the
+//! Google Reader API
+//!
+//! Besides the `miniflux-notifier` binary, the pieces it is built from can be used on their
+//! own: the [`source`]s fetch the entries, [`diff`] works out which entries are new and
+//! [`filter`] and [`rules`] decide which of them are worth a notification.
+
+pub mod backoff;
+pub mod cached_check;
+pub mod check;
+pub mod client;
+pub mod clipboard;
+pub mod config;
+pub mod control;
+pub mod daemon;
+pub mod diff;
+pub mod dnd;
+pub mod dry_run;
+pub mod feed_errors;
+pub mod filter;
+pub mod history;
+pub mod icons;
+pub mod launcher;
+pub mod list;
+pub mod logging;
+pub mod mark_all_read;
+pub mod metrics;
+pub mod models;
+pub mod network;
+pub mod notify;
+pub mod power;
+pub mod rules;
+pub mod schedule;
+pub mod scoped_poll;
+pub mod service;
+pub mod session;
+pub mod snooze;
+pub mod source;
+pub mod state;
+pub mod status_bar;
+pub mod systemd;
+pub mod telemetry;
+pub mod template;
+pub mod test_notify;
+pub mod tray;
+pub mod tui;
+pub mod unix_socket;
+pub mod webhook;
@@ -0,0 +1,88 @@
+//! The `feeds` and `categories` subcommands, listing the ids and titles that filters and rules
+//! can match on
+
+use anyhow::{anyhow, Result};
+
+use crate::config::Settings;
+use crate::source::{self, FeedSource};
+
+/// Prints a table of the feeds of each server
+pub fn feeds(servers: &[Settings]) -> Result<()> {
+    for_each_server(servers, |client| {
+        let mut feeds = client
+            .feeds()?
+            .ok_or_else(|| anyhow!("The source doesn't support listing feeds"))?;
+        feeds.sort_by_key(|feed| feed.id);
+        let rows = feeds
+            .into_iter()
+            .map(|feed| {
+                let category = feed.category.map(|category| category.title);
+                [
+                    feed.id.to_string(),
+                    feed.title,
+                    category.unwrap_or_default(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        print_table(["ID", "TITLE", "CATEGORY"], &rows);
+        Ok(())
+    })
+}
+
+/// Prints a table of the categories of each server
+pub fn categories(servers: &[Settings]) -> Result<()> {
+    for_each_server(servers, |client| {
+        let mut categories = client
+            .categories()?
+            .ok_or_else(|| anyhow!("The source doesn't support listing categories"))?;
+        categories.sort_by_key(|category| category.id);
+        let rows = categories
+            .into_iter()
+            .map(|category| [category.id.to_string(), category.title])
+            .collect::<Vec<_>>();
+        print_table(["ID", "TITLE"], &rows);
+        Ok(())
+    })
+}
+
+/// Runs `list` for the source of each server, with the names of the servers in between if
+/// there are several
+fn for_each_server(
+    servers: &[Settings],
+    mut list: impl FnMut(&dyn FeedSource) -> Result<()>,
+) -> Result<()> {
+    for (i, settings) in servers.iter().enumerate() {
+        if servers.len() > 1 {
+            if i > 0 {
+                println!();
+            }
+            println!("{}:", settings.name.as_deref().unwrap_or(&settings.server));
+        }
+        let client = source::from_settings(settings)?;
+        list(client.as_ref())?;
+    }
+    Ok(())
+}
+
+/// Prints the rows in columns as wide as their widest cell
+pub(crate) fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let mut widths = header.map(|title| title.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let print_row = |cells: [&str; N]| {
+        let line = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    };
+    print_row(header);
+    for row in rows {
+        print_row(row.each_ref().map(String::as_str));
+    }
+}
@@ -0,0 +1,38 @@
+//! Setting up the log, either as text for people or as JSON for log pipelines
+
+use clap::ValueEnum;
+use log::LevelFilter;
+use serde_json::json;
+use std::io::Write;
+
+/// How log messages are written to stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// A line of text per message
+    #[default]
+    Text,
+    /// A JSON object per line, with the fields timestamp, level, target and message
+    Json,
+}
+
+/// Sets up the logger. The level takes the place of the default level in the `RUST_LOG`
+/// environment variable, which is info if it isn't set.
+pub fn init(format: LogFormat, level: Option<LevelFilter>) {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    if let Some(level) = level {
+        builder.filter_level(level);
+    }
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = json!({
+                "timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+}
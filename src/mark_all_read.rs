@@ -0,0 +1,71 @@
+//! The `mark-all-read` subcommand, for clearing out the unread entries after reading a digest
+//! or coming back from a holiday
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use clap::Args as ClapArgs;
+
+use crate::config::Settings;
+use crate::source;
+
+/// How many entries are marked as read with each request
+const BATCH_SIZE: usize = 100;
+
+/// The options of the `mark-all-read` subcommand
+#[derive(ClapArgs, Debug, Clone)]
+pub struct MarkAllReadArgs {
+    /// Only mark the entries of the feed with this id as read
+    #[clap(long)]
+    feed: Option<u32>,
+
+    /// Only mark the entries published before this time as read, given as a date, an RFC 3339
+    /// time or how long ago, e.g. "2024-06-01" or "3d"
+    #[clap(long, value_parser = parse_time)]
+    before: Option<DateTime<Utc>>,
+}
+
+/// Marks the matching unread entries of each server as read
+pub fn run(args: &MarkAllReadArgs, servers: &[Settings]) -> Result<()> {
+    for settings in servers {
+        let client = source::from_settings(settings)?;
+        let ids: Vec<u64> = client
+            .unread_entries(None)?
+            .entries
+            .into_iter()
+            .filter(|entry| args.feed.is_none_or(|feed| entry.feed.id == feed))
+            .filter(|entry| args.before.is_none_or(|before| entry.published_at < before))
+            .map(|entry| entry.id)
+            .collect();
+        for batch in ids.chunks(BATCH_SIZE) {
+            client.mark_read(batch)?;
+        }
+
+        let count = match ids.len() {
+            1 => "1 entry".to_string(),
+            count => format!("{} entries", count),
+        };
+        match &settings.name {
+            Some(name) => println!("{}: marked {} as read", name, count),
+            None => println!("Marked {} as read", count),
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn parse_time(s: &str) -> Result<DateTime<Utc>> {
+    if let Ok(ago) = humantime::parse_duration(s) {
+        return Ok(Utc::now() - ago);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.to_utc());
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| {
+            date.and_hms_opt(0, 0, 0)?
+                .and_local_timezone(Local)
+                .earliest()
+        })
+        .map(|time| time.to_utc())
+        .ok_or_else(|| anyhow!("expected a date, an RFC 3339 time or a duration"))
+}
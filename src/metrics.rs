@@ -0,0 +1,206 @@
+//! Metrics about what the notifier is doing, served in the Prometheus text format
+
+use anyhow::{anyhow, Result};
+use log::{error, info};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Response, Server};
+
+use crate::config::Settings;
+
+/// The metrics of each server, by the label identifying it
+static SERVERS: Mutex<BTreeMap<String, Arc<Metrics>>> = Mutex::new(BTreeMap::new());
+
+/// The metrics of a single server
+#[derive(Debug, Default)]
+pub struct Metrics {
+    polls: AtomicU64,
+    poll_errors: AtomicU64,
+    entries_fetched: AtomicU64,
+    unread_entries: AtomicU64,
+    last_successful_poll: AtomicI64,
+    /// By notifier
+    notifications_sent: Mutex<BTreeMap<&'static str, u64>>,
+    /// By action
+    actions: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    /// The metrics of the server, shared by everything watching it
+    pub fn for_server(settings: &Settings) -> Arc<Self> {
+        let label = settings.name.clone().unwrap_or(settings.server.clone());
+        Arc::clone(SERVERS.lock().unwrap().entry(label).or_default())
+    }
+
+    /// Records a poll that fetched `fetched` of the `total` unread entries
+    pub fn record_poll(&self, total: u32, fetched: usize) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.entries_fetched
+            .fetch_add(fetched as u64, Ordering::Relaxed);
+        self.unread_entries.store(total as u64, Ordering::Relaxed);
+        self.last_successful_poll
+            .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    pub fn record_poll_error(&self) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.poll_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_notification(&self, notifier: &'static str) {
+        *self
+            .notifications_sent
+            .lock()
+            .unwrap()
+            .entry(notifier)
+            .or_default() += 1;
+    }
+
+    pub fn record_action(&self, action: &str) {
+        *self
+            .actions
+            .lock()
+            .unwrap()
+            .entry(action.to_string())
+            .or_default() += 1;
+    }
+}
+
+/// Serves the metrics of all servers at `/metrics` on `addr` in a thread of its own, until a
+/// shutdown is requested
+pub fn serve(addr: &str, shutdown: Arc<AtomicBool>) -> Result<()> {
+    let server = Server::http(addr)
+        .map_err(|e| anyhow!("Failed to listen on {} for metrics: {}", addr, e))?;
+    info!("Serving metrics on http://{}/metrics", addr);
+    thread::spawn(move || {
+        while !shutdown.load(Ordering::Relaxed) {
+            let request = match server.recv_timeout(Duration::from_millis(250)) {
+                Ok(Some(request)) => request,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("Failed to receive a metrics request: {}", e);
+                    continue;
+                }
+            };
+            let response = match request.url() {
+                "/metrics" => Response::from_string(render()).with_header(
+                    "Content-Type: text/plain; version=0.0.4"
+                        .parse::<Header>()
+                        .unwrap(),
+                ),
+                _ => Response::from_string("Not found").with_status_code(404),
+            };
+            if let Err(e) = request.respond(response) {
+                error!("Failed to respond to a metrics request: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// The metrics of all servers in the Prometheus text format
+fn render() -> String {
+    let servers = SERVERS.lock().unwrap();
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, values: Vec<(String, String)>| {
+        let _ = writeln!(out, "# HELP miniflux_notifier_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE miniflux_notifier_{} {}", name, kind);
+        for (labels, value) in values {
+            let _ = writeln!(out, "miniflux_notifier_{}{{{}}} {}", name, labels, value);
+        }
+    };
+    let simple = |get: &dyn Fn(&Metrics) -> String| {
+        servers
+            .iter()
+            .map(|(server, metrics)| (label("server", server), get(metrics)))
+            .collect()
+    };
+
+    metric(
+        "polls_total",
+        "counter",
+        "Polls of the server for unread entries",
+        simple(&|m| m.polls.load(Ordering::Relaxed).to_string()),
+    );
+    metric(
+        "poll_errors_total",
+        "counter",
+        "Polls that failed because the server couldn't be reached or returned an error",
+        simple(&|m| m.poll_errors.load(Ordering::Relaxed).to_string()),
+    );
+    metric(
+        "entries_fetched_total",
+        "counter",
+        "Entries fetched from the server",
+        simple(&|m| m.entries_fetched.load(Ordering::Relaxed).to_string()),
+    );
+    metric(
+        "unread_entries",
+        "gauge",
+        "Unread entries as of the last successful poll",
+        simple(&|m| m.unread_entries.load(Ordering::Relaxed).to_string()),
+    );
+    metric(
+        "last_successful_poll_timestamp_seconds",
+        "gauge",
+        "When the server was last polled successfully, 0 if it hasn't been yet",
+        simple(&|m| m.last_successful_poll.load(Ordering::Relaxed).to_string()),
+    );
+    metric(
+        "notifications_sent_total",
+        "counter",
+        "Notifications sent, by notifier",
+        servers
+            .iter()
+            .flat_map(|(server, metrics)| {
+                let sent = metrics.notifications_sent.lock().unwrap();
+                sent.iter()
+                    .map(|(notifier, count)| {
+                        (
+                            format!(
+                                "{},{}",
+                                label("server", server),
+                                label("notifier", notifier)
+                            ),
+                            count.to_string(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+    );
+    metric(
+        "notification_actions_total",
+        "counter",
+        "Actions used on desktop notifications, by action",
+        servers
+            .iter()
+            .flat_map(|(server, metrics)| {
+                let actions = metrics.actions.lock().unwrap();
+                actions
+                    .iter()
+                    .map(|(action, count)| {
+                        (
+                            format!("{},{}", label("server", server), label("action", action)),
+                            count.to_string(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+    );
+    out
+}
+
+/// A label with its value escaped for the text format
+fn label(name: &str, value: &str) -> String {
+    let value = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("{}=\"{}\"", name, value)
+}
@@ -1,24 +1,155 @@
-//! Structs representing the various response playloads the Miniflux API might respond with
+//! Structs representing the various response playloads the Miniflux API might respond with,
+//! which the other sources map their own responses into
 
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Entries {
     pub total: u32,
     pub entries: Vec<Entry>,
+    /// Set when the server said that the entries haven't changed since they were last
+    /// fetched, in which case they are the ones fetched back then
+    #[serde(skip)]
+    pub not_modified: bool,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Entry {
-    pub id: u32,
+    pub id: u64,
     pub title: String,
     pub author: String,
     pub hash: String,
     pub feed: Feed,
     pub url: String,
+    pub published_at: DateTime<Utc>,
+    /// When the server first fetched the entry, which is later than its publication for
+    /// entries of newly added feeds. `None` for sources that don't tell.
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// The estimated reading time in minutes, `None` for sources that don't estimate it
+    #[serde(default)]
+    pub reading_time: Option<u32>,
+    /// The content of the entry as HTML
+    #[serde(default)]
+    pub content: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Feed {
+    pub id: u32,
+    pub title: String,
+    #[serde(default)]
+    pub category: Option<Category>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Category {
+    pub id: u32,
+    pub title: String,
+}
+
+/// A feed with the details Miniflux keeps about it, as listed by `/v1/feeds`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FeedDetails {
+    pub id: u32,
+    pub title: String,
+    #[serde(default)]
+    pub site_url: String,
+    #[serde(default)]
+    pub feed_url: String,
+    #[serde(default)]
+    pub category: Option<Category>,
+    /// How many times in a row fetching the feed has failed
+    #[serde(default)]
+    pub parsing_error_count: u32,
+    /// Why fetching the feed failed the last time, empty if it didn't
+    #[serde(default)]
+    pub parsing_error_message: String,
+}
+
+/// The icon of a feed
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FeedIcon {
+    pub id: u32,
+    /// The image as "<mime type>;base64,<data>"
+    pub data: String,
+    pub mime_type: String,
+}
+
+/// The user the API credentials belong to
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct User {
+    pub id: u32,
+    pub username: String,
+}
+
+/// The payload of a `new_entries` webhook event
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookNewEntries {
+    pub feed: Feed,
+    pub entries: Vec<WebhookEntry>,
+}
+
+/// An entry as sent in a webhook event, which carries its feed separately
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookEntry {
+    pub id: u64,
     pub title: String,
+    #[serde(default)]
+    pub author: String,
+    pub hash: String,
+    pub url: String,
+    pub published_at: DateTime<Utc>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub reading_time: Option<u32>,
+    #[serde(default)]
+    pub content: String,
+}
+
+impl WebhookNewEntries {
+    pub fn into_entries(self) -> Vec<Entry> {
+        let feed = self.feed;
+        self.entries
+            .into_iter()
+            .map(|entry| Entry {
+                id: entry.id,
+                title: entry.title,
+                author: entry.author,
+                hash: entry.hash,
+                feed: feed.clone(),
+                url: entry.url,
+                published_at: entry.published_at,
+                created_at: entry.created_at,
+                reading_time: entry.reading_time,
+                content: entry.content,
+            })
+            .collect()
+    }
+}
+
+/// An entry of the feed "Feed" in the category "News", for tests
+#[cfg(test)]
+pub(crate) fn test_entry(id: u64) -> Entry {
+    Entry {
+        id,
+        title: format!("Entry {}", id),
+        author: String::new(),
+        hash: format!("hash-{}", id),
+        feed: Feed {
+            id: 1,
+            title: "Feed".to_string(),
+            category: Some(Category {
+                id: 1,
+                title: "News".to_string(),
+            }),
+        },
+        url: format!("https://example.com/{}", id),
+        published_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        created_at: None,
+        reading_time: None,
+        content: String::new(),
+    }
 }
@@ -0,0 +1,62 @@
+//! Telling whether the computer is offline, so that polling can wait for a connection instead
+//! of failing over and over, e.g. on a laptop in flight mode
+//!
+//! NetworkManager is asked for its state. Without it, the computer is never considered
+//! offline.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::cached_check::CachedCheck;
+
+static CHECK: CachedCheck = CachedCheck::new(Duration::from_secs(1), check);
+
+/// Whether the computer has no network connection
+pub fn is_offline() -> bool {
+    CHECK.get()
+}
+
+/// Whether the server runs on this computer, and can be reached without a connection
+pub fn is_local(server: &str) -> bool {
+    let Some(host) = reqwest::Url::parse(server)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+    else {
+        return false;
+    };
+    // IPv6 addresses are given in brackets
+    match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(ip) => ip.is_loopback(),
+        Err(_) => host == "localhost",
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn check() -> bool {
+    use zbus::zvariant::OwnedValue;
+
+    /// `NM_STATE_CONNECTED_LOCAL`, the first of the states in which there is a connection
+    const CONNECTED_LOCAL: u32 = 50;
+
+    let Some(connection) = crate::session::system_bus() else {
+        return false;
+    };
+    let state = connection
+        .call_method(
+            Some("org.freedesktop.NetworkManager"),
+            "/org/freedesktop/NetworkManager",
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &("org.freedesktop.NetworkManager", "State"),
+        )
+        .and_then(|reply| reply.body::<OwnedValue>())
+        .ok()
+        .and_then(|value| u32::try_from(value).ok());
+    // 0 is NM_STATE_UNKNOWN
+    matches!(state, Some(state) if state != 0 && state < CONNECTED_LOCAL)
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn check() -> bool {
+    false
+}
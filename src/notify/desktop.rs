@@ -0,0 +1,865 @@
+//! Desktop notifications, including tracking whether the desktop notification service can be
+//! reached and what to do when it can't
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use log::{debug, error, info, warn};
+#[cfg(not(any(windows, target_os = "macos")))]
+use notify_rust::{Notification, NotificationHandle};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "macos")]
+use super::macos::{Notification, NotificationHandle};
+#[cfg(windows)]
+use super::toast::{Notification, NotificationHandle};
+use super::{Message, Notifier, OpenRule, Subject};
+use crate::clipboard;
+use crate::config::Settings;
+use crate::filter::{matches_feed_or_category, Matcher};
+use crate::history::History;
+use crate::icons::IconCache;
+use crate::metrics::Metrics;
+use crate::models::Entry;
+use crate::snooze::{SnoozedEntry, Snoozes};
+use crate::source::{FeedSource, SourceKind};
+use crate::state;
+
+/// The maximum number of characters in a notification summary
+const MAX_SUMMARY_LEN: usize = 256;
+
+/// The maximum number of characters in a notification body
+const MAX_BODY_LEN: usize = 1024;
+
+/// The number of threads currently waiting for an action on a notification
+static PENDING_ACTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The most lines waiting for a reader of the fallback FIFO, after which further ones are
+/// dropped
+const MAX_FIFO_BACKLOG: usize = 100;
+
+/// Where notifications are sent when the desktop notification service is unavailable, e.g.
+/// in an SSH session or under a window manager without one
+#[derive(Debug, Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FallbackBackend {
+    /// Print the notification to stdout
+    Stdout,
+    /// Write the notification as a line to the FIFO given with --fallback-fifo, e.g. for a
+    /// status bar to read
+    Fifo,
+    /// Run the shell command given with --fallback-command
+    Command,
+}
+
+/// A fallback backend set up to send notifications
+enum Fallback {
+    Stdout,
+    /// The lines for the thread writing to the FIFO
+    Fifo(SyncSender<String>),
+    Command(String),
+}
+
+impl Fallback {
+    fn new(settings: &Settings) -> Result<Option<Self>> {
+        let fallback = match settings.fallback_backend {
+            None => return Ok(None),
+            Some(FallbackBackend::Stdout) => Fallback::Stdout,
+            Some(FallbackBackend::Fifo) => {
+                let path = settings.fallback_fifo.clone().ok_or_else(|| {
+                    anyhow!("--fallback-fifo is required by the fifo fallback backend")
+                })?;
+                Fallback::Fifo(spawn_fifo_writer(path))
+            }
+            Some(FallbackBackend::Command) => {
+                Fallback::Command(settings.fallback_command.clone().ok_or_else(|| {
+                    anyhow!("--fallback-command is required by the command fallback backend")
+                })?)
+            }
+        };
+        Ok(Some(fallback))
+    }
+
+    fn send(&self, summary: &str, body: &str, url: &str) {
+        match self {
+            Fallback::Stdout => println!("{}: {} <{}>", summary, body, url),
+            Fallback::Fifo(lines) => {
+                // A line per notification, for readers going line by line
+                let line = format!("{}: {} <{}>", summary, body, url)
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                match lines.try_send(line) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        warn!("Nothing is reading the fallback FIFO, dropping the notification")
+                    }
+                    Err(TrySendError::Disconnected(_)) => {
+                        error!("The fallback FIFO can't be written to")
+                    }
+                }
+            }
+            Fallback::Command(command) => {
+                if let Err(e) = run_fallback_command(command, summary, body, url) {
+                    error!("Failed to run `{}`: {}", command, e);
+                }
+            }
+        }
+    }
+}
+
+/// Writes the lines sent to it to the FIFO in a thread of its own, as opening a FIFO blocks
+/// until something opens it for reading
+fn spawn_fifo_writer(path: PathBuf) -> SyncSender<String> {
+    let (sender, lines) = mpsc::sync_channel::<String>(MAX_FIFO_BACKLOG);
+    thread::spawn(move || {
+        while let Ok(line) = lines.recv() {
+            // Opened again for every batch of lines, as readers come and go
+            let result = OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .and_then(|mut fifo| {
+                    writeln!(fifo, "{}", line)?;
+                    while let Ok(line) = lines.try_recv() {
+                        writeln!(fifo, "{}", line)?;
+                    }
+                    Ok(())
+                });
+            if let Err(e) = result {
+                error!("Failed to write to {}: {}", path.display(), e);
+            }
+        }
+    });
+    sender
+}
+
+/// Runs the fallback command with the shell, with `{summary}`, `{body}` and `{url}` standing
+/// for the parts of the notification, which are handed to the shell as arguments like the URL
+/// given to the open command
+fn run_fallback_command(command: &str, summary: &str, body: &str, url: &str) -> Result<()> {
+    let script = command
+        .replace("{summary}", "\"$1\"")
+        .replace("{body}", "\"$2\"")
+        .replace("{url}", "\"$3\"");
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .args(["sh", summary, body, url])
+        .spawn()?;
+    let command = command.to_string();
+    thread::spawn(move || match child.wait() {
+        Ok(status) if !status.success() => warn!("`{}` exited with {}", command, status),
+        Ok(_) => {}
+        Err(e) => error!("Failed to wait for `{}`: {}", command, e),
+    });
+    Ok(())
+}
+
+/// How urgent a notification is. Notification services usually still show critical ones
+/// while do-not-disturb is on, and may show low ones without a popup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl From<Urgency> for notify_rust::Urgency {
+    fn from(urgency: Urgency) -> Self {
+        match urgency {
+            Urgency::Low => notify_rust::Urgency::Low,
+            Urgency::Normal => notify_rust::Urgency::Normal,
+            Urgency::Critical => notify_rust::Urgency::Critical,
+        }
+    }
+}
+
+/// How long a notification stays on screen, written as "default", "never" or a duration
+/// such as "30s"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum NotificationTimeout {
+    /// Whatever the notification service does by default
+    Default,
+    /// Until it is closed or acted on
+    Never,
+    After(Duration),
+}
+
+impl From<NotificationTimeout> for notify_rust::Timeout {
+    fn from(timeout: NotificationTimeout) -> Self {
+        match timeout {
+            NotificationTimeout::Default => notify_rust::Timeout::Default,
+            NotificationTimeout::Never => notify_rust::Timeout::Never,
+            NotificationTimeout::After(duration) => notify_rust::Timeout::Milliseconds(
+                duration.as_millis().clamp(1, u32::MAX as u128) as u32,
+            ),
+        }
+    }
+}
+
+impl FromStr for NotificationTimeout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(NotificationTimeout::Default),
+            "never" => Ok(NotificationTimeout::Never),
+            s => humantime::parse_duration(s)
+                .map(NotificationTimeout::After)
+                .map_err(|e| format!("invalid timeout {:?}: {}", s, e)),
+        }
+    }
+}
+
+impl TryFrom<String> for NotificationTimeout {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Gives the notifications about entries from some feeds or categories an urgency, a sound
+/// and a timeout of their own
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UrgencyRule {
+    #[serde(default)]
+    pub feeds: Vec<Matcher>,
+    #[serde(default)]
+    pub categories: Vec<Matcher>,
+    pub urgency: Option<Urgency>,
+    /// The name of a sound from the freedesktop sound theme, e.g. "message-new-instant"
+    pub sound: Option<String>,
+    pub timeout: Option<NotificationTimeout>,
+    pub resident: Option<bool>,
+}
+
+impl UrgencyRule {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        matches_feed_or_category(&self.feeds, &self.categories, entry)
+    }
+}
+
+/// Shows notifications through the desktop notification service, with actions to open, mark
+/// as read, star, snooze or copy the link of the entry, and with Miniflux to fetch its full
+/// content or save it to a third-party service
+pub struct DesktopNotifier {
+    client: Arc<dyn FeedSource>,
+    health: DesktopHealth,
+    fallback: Option<Fallback>,
+    max_pending_actions: usize,
+    mark_read_on_open: bool,
+    /// The Miniflux server, for the actions only Miniflux supports. `None` with other sources.
+    miniflux_server: Option<String>,
+    open_command: Option<String>,
+    open_rules: Vec<OpenRule>,
+    urgency_rules: Vec<UrgencyRule>,
+    timeout: NotificationTimeout,
+    resident: bool,
+    icons: Option<IconCache>,
+    replace_notifications: bool,
+    /// The notification last shown for each feed, for the next one to replace
+    feed_notifications: HashMap<u32, u32>,
+    action_handlers: ActionHandlers,
+    snoozes: Snoozes,
+    history: History,
+    /// Whether unanswered notifications about entries are to be marked read, for
+    /// `--mark-read-after`
+    mark_read_after: bool,
+    snooze_delay: Duration,
+    metrics: Arc<Metrics>,
+}
+
+impl DesktopNotifier {
+    pub fn new(
+        settings: &Settings,
+        client: Arc<dyn FeedSource>,
+        snoozes: Snoozes,
+        history: History,
+    ) -> Result<Self> {
+        #[cfg(target_os = "macos")]
+        if let Err(e) = super::macos::set_bundle_id(settings.macos_bundle_id.as_deref()) {
+            error!("{:?}", e);
+        }
+        Ok(Self {
+            icons: match settings.feed_icons {
+                true => IconCache::new(settings, Arc::clone(&client)),
+                false => None,
+            },
+            client,
+            health: DesktopHealth::new(
+                settings.notify_failure_threshold,
+                settings.notify_retry_interval,
+            ),
+            fallback: Fallback::new(settings)?,
+            max_pending_actions: settings.max_pending_actions,
+            mark_read_on_open: settings.mark_read_on_open,
+            miniflux_server: match settings.source {
+                SourceKind::Miniflux => Some(settings.server.clone()),
+                _ => None,
+            },
+            open_command: settings.open_command.clone(),
+            open_rules: settings.open_rules.clone(),
+            urgency_rules: settings.urgency_rules.clone(),
+            timeout: settings.notification_timeout,
+            resident: settings.resident_notifications,
+            replace_notifications: settings.replace_notifications,
+            feed_notifications: HashMap::new(),
+            action_handlers: ActionHandlers::default(),
+            snoozes,
+            history,
+            mark_read_after: settings.mark_read_after.is_some(),
+            snooze_delay: settings.snooze_delay,
+            metrics: Metrics::for_server(settings),
+        })
+    }
+
+    /// The command to open the entry with, `None` for the default browser
+    fn open_command(&self, entry: &Entry) -> Option<String> {
+        self.open_rules
+            .iter()
+            .find(|rule| rule.matches(entry))
+            .and_then(|rule| rule.command.clone())
+            .or_else(|| self.open_command.clone())
+    }
+
+    /// The first of the urgency rules matching the entry
+    fn urgency_rule(&self, entry: &Entry) -> Option<&UrgencyRule> {
+        self.urgency_rules.iter().find(|rule| rule.matches(entry))
+    }
+
+    /// Shows a notification, handing it to the fallback backend instead if the notification
+    /// service is unavailable
+    fn show(&mut self, notif: &Notification, url: &str) -> Option<NotificationHandle> {
+        if self.health.is_available() {
+            match notif.show() {
+                Ok(handle) => {
+                    self.health.record_success();
+                    return Some(handle);
+                }
+                Err(e) => {
+                    // Once degraded, the failure has already been reported
+                    if !self.health.is_degraded() {
+                        error!("{:?}", e);
+                    }
+                    self.health.record_failure();
+                }
+            }
+        }
+
+        if let Some(fallback) = &self.fallback {
+            fallback.send(&notif.summary, &notif.body, url);
+        }
+        None
+    }
+}
+
+impl Notifier for DesktopNotifier {
+    fn name(&self) -> &'static str {
+        "desktop"
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        let mut notif = Notification::new();
+        notif
+            .summary(&truncate_for_safety(
+                message.summary.clone(),
+                MAX_SUMMARY_LEN,
+                "summary",
+            ))
+            .body(&truncate_for_safety(
+                message.body.clone(),
+                MAX_BODY_LEN,
+                "body",
+            ));
+        match &message.subject {
+            Subject::Entry(_) => {
+                notif
+                    .action("open", "Open in web browser")
+                    .action("mark_read", "Mark as read")
+                    .action("star", "Star")
+                    .action("snooze", "Remind me later")
+                    .action("copy_url", "Copy link");
+                if self.miniflux_server.is_some() {
+                    notif
+                        .action("fetch_content", "Fetch full content")
+                        .action("save", "Save");
+                }
+            }
+            Subject::Digest(_) => {
+                notif.action("open", "Open Miniflux");
+            }
+            Subject::FeedError(_) => {
+                notif.action("open", "Open feed settings");
+            }
+            Subject::UnreadCount(_) => {
+                notif.action("open", "Open Miniflux");
+            }
+            Subject::Status => {}
+        }
+
+        // A digest is as urgent as the most urgent entry in it
+        let rule = match &message.subject {
+            Subject::Entry(entry) => self.urgency_rule(entry),
+            Subject::Digest(entries) => entries
+                .iter()
+                .filter_map(|entry| self.urgency_rule(entry))
+                .max_by_key(|rule| rule.urgency.unwrap_or(Urgency::Normal)),
+            Subject::Status | Subject::FeedError(_) | Subject::UnreadCount(_) => None,
+        };
+        let mut timeout = self.timeout;
+        let mut resident = self.resident;
+        if let Some(rule) = rule {
+            if let Some(urgency) = rule.urgency {
+                notif.urgency(urgency.into());
+            }
+            if let Some(sound) = &rule.sound {
+                notif.sound_name(sound);
+            }
+            timeout = rule.timeout.unwrap_or(timeout);
+            resident = rule.resident.unwrap_or(resident);
+        }
+        notif.timeout(timeout);
+        // Resident notifications stay in the notification center after an action, rather than
+        // being closed by it
+        if resident {
+            notif.hint(notify_rust::Hint::Resident(true));
+        }
+
+        // A digest only gets an icon, or replaces the notification of a feed, if all of its
+        // entries are from the same feed
+        let feed_id = match &message.subject {
+            Subject::Entry(entry) => Some(entry.feed.id),
+            Subject::Digest(entries) => match entries.split_first() {
+                Some((first, rest)) if rest.iter().all(|e| e.feed.id == first.feed.id) => {
+                    Some(first.feed.id)
+                }
+                _ => None,
+            },
+            Subject::Status | Subject::FeedError(_) | Subject::UnreadCount(_) => None,
+        };
+        if let (Some(icons), Some(feed_id)) = (&mut self.icons, feed_id) {
+            if let Some(path) = icons.get(feed_id) {
+                notif.icon(&path.to_string_lossy());
+            }
+        }
+
+        if self.replace_notifications {
+            if let Some(id) = feed_id.and_then(|feed_id| self.feed_notifications.get(&feed_id)) {
+                notif.id(*id);
+            }
+        }
+
+        let Some(handle) = self.show(&notif, &message.url) else {
+            return Ok(());
+        };
+        if let (true, Some(feed_id)) = (self.replace_notifications, feed_id) {
+            self.feed_notifications.insert(feed_id, handle.id());
+        }
+        let is_current = self.action_handlers.take_over(handle.id());
+
+        let url = message.url;
+        match message.subject {
+            Subject::Entry(entry) => {
+                if self.mark_read_after {
+                    self.history.await_answer(entry.id);
+                }
+                let client = Arc::clone(&self.client);
+                let mark_read_on_open = self.mark_read_on_open;
+                let snoozes = self.snoozes.clone();
+                let history = self.history.clone();
+                let snooze_delay = self.snooze_delay;
+                let (summary, body) = (message.summary, message.body);
+                let open_command = self.open_command(&entry);
+                let miniflux_url = self
+                    .miniflux_server
+                    .as_deref()
+                    .map(|server| super::miniflux_entry_url(server, entry.id));
+                spawn_action_handler(
+                    handle,
+                    self.max_pending_actions,
+                    is_current,
+                    Arc::clone(&self.metrics),
+                    move |action| {
+                        let entry_id = entry.id;
+                        match action {
+                            "open" => {
+                                if let Err(e) = open_url(&url, open_command.as_deref()) {
+                                    error!("{:?}", e);
+                                }
+                                if mark_read_on_open {
+                                    if let Err(e) = client.mark_read(&[entry.id]) {
+                                        error!("Failed to mark the entry as read: {}", e);
+                                    }
+                                }
+                            }
+                            "mark_read" => {
+                                if let Err(e) = client.mark_read(&[entry.id]) {
+                                    error!("Failed to mark the entry as read: {}", e);
+                                }
+                            }
+                            "star" => {
+                                if let Err(e) = client.toggle_bookmark(entry.id) {
+                                    error!("Failed to star the entry: {}", e);
+                                }
+                            }
+                            "fetch_content" => match client.fetch_content(entry.id) {
+                                Ok(_) => {
+                                    if let Some(miniflux_url) = &miniflux_url {
+                                        if let Err(e) = open_url(miniflux_url, None) {
+                                            error!("{:?}", e);
+                                        }
+                                    }
+                                }
+                                Err(e) => error!("Failed to fetch the full content: {}", e),
+                            },
+                            "save" => {
+                                if let Err(e) = client.save_entry(entry.id) {
+                                    error!("Failed to save the entry: {}", e);
+                                }
+                            }
+                            "copy_url" => {
+                                if let Err(e) = clipboard::copy(&url) {
+                                    error!("Failed to copy the link: {:?}", e);
+                                }
+                            }
+                            "snooze" => {
+                                debug!(
+                                    "Snoozing entry {} for {}",
+                                    entry.id,
+                                    humantime::format_duration(snooze_delay)
+                                );
+                                snoozes.add(SnoozedEntry {
+                                    entry,
+                                    summary,
+                                    body,
+                                    url,
+                                    due: state::now() + snooze_delay.as_secs(),
+                                });
+                            }
+                            _ => return,
+                        }
+                        history.record_action(entry_id, action);
+                    },
+                );
+            }
+            Subject::Digest(_) => {
+                let open_command = self.open_command.clone();
+                spawn_action_handler(
+                    handle,
+                    self.max_pending_actions,
+                    is_current,
+                    Arc::clone(&self.metrics),
+                    move |action| {
+                        if action == "open" {
+                            if let Err(e) = open_url(&url, open_command.as_deref()) {
+                                error!("{:?}", e);
+                            }
+                        }
+                    },
+                );
+            }
+            Subject::FeedError(_) | Subject::UnreadCount(_) => {
+                spawn_action_handler(
+                    handle,
+                    self.max_pending_actions,
+                    is_current,
+                    Arc::clone(&self.metrics),
+                    move |action| {
+                        if action == "open" {
+                            if let Err(e) = open_url(&url, None) {
+                                error!("{:?}", e);
+                            }
+                        }
+                    },
+                );
+            }
+            Subject::Status => {}
+        }
+        Ok(())
+    }
+
+    /// Shows the notifications of the snoozed entries that are due again
+    fn tick(&mut self) -> Result<()> {
+        for snoozed in self.snoozes.take_due(state::now()) {
+            self.send(Message {
+                summary: snoozed.summary,
+                body: snoozed.body,
+                url: snoozed.url,
+                subject: Subject::Entry(snoozed.entry),
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps track of failures to send desktop notifications.
+///
+/// On a freshly booted or headless session the notification service may not be running, in
+/// which case every notification fails. After `threshold` consecutive failures the desktop is
+/// considered degraded: notifications are no longer attempted per entry, and instead the
+/// service is probed once every `retry_interval` until it answers again.
+#[derive(Debug)]
+pub struct DesktopHealth {
+    threshold: u32,
+    retry_interval: Duration,
+    consecutive_failures: u32,
+    /// When the service was last probed, set while degraded
+    last_probe: Option<Instant>,
+    /// Checks whether the service answers again
+    probe: fn() -> bool,
+}
+
+impl DesktopHealth {
+    pub fn new(threshold: u32, retry_interval: Duration) -> Self {
+        Self {
+            threshold,
+            retry_interval,
+            consecutive_failures: 0,
+            last_probe: None,
+            probe,
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.last_probe.is_some()
+    }
+
+    /// Whether notifications should be attempted right now
+    ///
+    /// While degraded this probes the notification service whenever the retry interval has
+    /// passed, and leaves the degraded state if it responds.
+    pub fn is_available(&mut self) -> bool {
+        match self.last_probe {
+            None => true,
+            Some(last_probe) if last_probe.elapsed() >= self.retry_interval => {
+                if (self.probe)() {
+                    self.record_success();
+                    true
+                } else {
+                    self.last_probe = Some(Instant::now());
+                    false
+                }
+            }
+            Some(_) => false,
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        if self.is_degraded() {
+            info!("Desktop notification service is available again");
+        }
+        self.consecutive_failures = 0;
+        self.last_probe = None;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.consecutive_failures += 1;
+        if !self.is_degraded() && self.consecutive_failures >= self.threshold {
+            warn!(
+                "Failed to send {} notifications in a row, retrying every {} until the \
+                 notification service becomes available",
+                self.consecutive_failures,
+                humantime::format_duration(self.retry_interval)
+            );
+            self.last_probe = Some(Instant::now());
+        }
+    }
+}
+
+/// Keeps track of which handler is in charge of the actions of each notification.
+///
+/// Replacing a notification keeps its id, so the handlers still waiting on the notifications
+/// it replaced see its actions too and have to leave them to the newest handler.
+#[derive(Default)]
+struct ActionHandlers {
+    current: Arc<Mutex<HashMap<u32, u64>>>,
+    count: u64,
+}
+
+impl ActionHandlers {
+    /// Puts a new handler in charge of the notification's actions, returning the check for
+    /// whether it still is once an action arrives
+    fn take_over(&mut self, id: u32) -> impl FnOnce() -> bool + Send + 'static {
+        self.count += 1;
+        let handler = self.count;
+        self.current.lock().unwrap().insert(id, handler);
+
+        let current = Arc::clone(&self.current);
+        move || {
+            let mut current = current.lock().unwrap();
+            if current.get(&id) != Some(&handler) {
+                return false;
+            }
+            // The notification is gone after an action or being closed
+            current.remove(&id);
+            true
+        }
+    }
+}
+
+/// Waits for an action on the notification in a background thread and calls `on_action` with
+/// it, so that polling carries on while notifications sit on screen.
+///
+/// Every notification still on screen holds on to a thread, so once `max` threads are waiting
+/// the actions of further notifications are not handled.
+fn spawn_action_handler(
+    handle: NotificationHandle,
+    max: usize,
+    is_current: impl FnOnce() -> bool + Send + 'static,
+    metrics: Arc<Metrics>,
+    on_action: impl FnOnce(&str) + Send + 'static,
+) {
+    if PENDING_ACTIONS.fetch_add(1, Ordering::SeqCst) >= max {
+        PENDING_ACTIONS.fetch_sub(1, Ordering::SeqCst);
+        debug!("Too many notifications waiting for an action, not handling this one's");
+        return;
+    }
+    thread::spawn(move || {
+        handle.wait_for_action(|action| {
+            if is_current() {
+                // Closing the notification is reported as an action too
+                if action != "__closed" {
+                    metrics.record_action(action);
+                }
+                on_action(action);
+            }
+        });
+        PENDING_ACTIONS.fetch_sub(1, Ordering::SeqCst);
+    });
+}
+
+/// How many notifications are still waiting for an action
+pub fn pending_actions() -> usize {
+    PENDING_ACTIONS.load(Ordering::SeqCst)
+}
+
+/// Opens the URL with the command, or in the default browser without one.
+///
+/// The command is run by the shell, with `{url}` standing for the URL. The URL comes from the
+/// feed, so it is handed to the shell as an argument rather than pasted into the command.
+fn open_url(url: &str, command: Option<&str>) -> Result<()> {
+    let Some(command) = command else {
+        open::that_detached(url)?;
+        return Ok(());
+    };
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command.replace("{url}", "\"$1\""))
+        .arg("sh")
+        .arg(url)
+        .spawn()?;
+    let command = command.to_string();
+    // Players and browsers keep running for a while, which shouldn't hold up handling the
+    // actions of other notifications
+    thread::spawn(move || match child.wait() {
+        Ok(status) if !status.success() => warn!("`{}` exited with {}", command, status),
+        Ok(_) => {}
+        Err(e) => error!("Failed to wait for `{}`: {}", command, e),
+    });
+    Ok(())
+}
+
+/// Truncates `text` to at most `max_len` characters, ending it with an ellipsis.
+///
+/// Some notification daemons crash or misbehave when handed multi-kilobyte strings, so this is
+/// applied to everything we pass on to them regardless of what the feed contains.
+fn truncate_for_safety(text: String, max_len: usize, what: &str) -> String {
+    if text.chars().count() <= max_len {
+        return text;
+    }
+    warn!(
+        "Truncating notification {} of {} characters to {} characters",
+        what,
+        text.chars().count(),
+        max_len
+    );
+    let mut truncated: String = text.chars().take(max_len.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Checks whether a notification server is answering on D-Bus
+#[cfg(not(any(windows, target_os = "macos")))]
+fn probe() -> bool {
+    notify_rust::get_server_information().is_ok()
+}
+
+/// macOS and Windows have no notification service to probe, it is always there
+#[cfg(any(windows, target_os = "macos"))]
+fn probe() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degrades_after_failures_and_recovers() {
+        let mut health = DesktopHealth {
+            probe: || false,
+            ..DesktopHealth::new(3, Duration::ZERO)
+        };
+        health.record_failure();
+        health.record_failure();
+        assert!(!health.is_degraded());
+        assert!(health.is_available());
+
+        health.record_failure();
+        assert!(health.is_degraded());
+        // The service is probed rather than notified while degraded, and still isn't there
+        assert!(!health.is_available());
+        assert!(health.is_degraded());
+
+        health.probe = || true;
+        assert!(health.is_available());
+        assert!(!health.is_degraded());
+        assert_eq!(health.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn waits_for_the_retry_interval_while_degraded() {
+        let mut health = DesktopHealth {
+            probe: || panic!("probed before the retry interval passed"),
+            ..DesktopHealth::new(1, Duration::from_secs(3600))
+        };
+        health.record_failure();
+        assert!(health.is_degraded());
+        assert!(!health.is_available());
+    }
+
+    #[test]
+    fn huge_titles_are_bounded() {
+        // Multi-byte characters, so that cutting by bytes would split one
+        let title = "aé🦀".repeat(10 * 1024 / "aé🦀".len() + 1);
+        assert!(title.len() > 10 * 1024);
+        let truncated = truncate_for_safety(title.clone(), MAX_SUMMARY_LEN, "summary");
+        assert_eq!(truncated.chars().count(), MAX_SUMMARY_LEN);
+        assert!(truncated.ends_with('…'));
+        let kept = truncated.trim_end_matches('…');
+        assert!(title.starts_with(kept));
+    }
+
+    #[test]
+    fn short_titles_are_kept() {
+        let title = "A short title".to_string();
+        assert_eq!(
+            truncate_for_safety(title.clone(), MAX_SUMMARY_LEN, "summary"),
+            title
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Emails through an SMTP server
+
+use anyhow::{anyhow, Context, Result};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+use std::time::{Duration, Instant};
+
+use super::{digest_summary, escape_html, Message, Notifier, SmtpSecurity, Subject};
+use crate::config::Settings;
+use crate::models::Entry;
+
+/// Sends an email per notification, or collects the entries and sends a digest of them at
+/// most once every digest interval
+pub struct EmailNotifier {
+    transport: SmtpTransport,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    digest_interval: Option<Duration>,
+    /// The entries waiting for the next digest
+    pending: Vec<Entry>,
+    last_digest: Instant,
+}
+
+impl EmailNotifier {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let server = settings
+            .smtp_server
+            .as_deref()
+            .ok_or_else(|| anyhow!("--smtp-server is required by the email notifier"))?;
+        let from = settings
+            .email_from
+            .as_deref()
+            .ok_or_else(|| anyhow!("--email-from is required by the email notifier"))?;
+        if settings.email_to.is_empty() {
+            return Err(anyhow!("--email-to is required by the email notifier"));
+        }
+
+        let mut transport = match settings.smtp_security {
+            SmtpSecurity::Tls => SmtpTransport::relay(server)?,
+            SmtpSecurity::Starttls => SmtpTransport::starttls_relay(server)?,
+            SmtpSecurity::None => SmtpTransport::builder_dangerous(server),
+        }
+        .timeout(Some(settings.timeout));
+        if let Some(port) = settings.smtp_port {
+            transport = transport.port(port);
+        }
+        if let Some(username) = &settings.smtp_username {
+            let password = settings.smtp_password.clone().unwrap_or_default();
+            transport = transport.credentials(Credentials::new(username.clone(), password));
+        }
+
+        Ok(Self {
+            transport: transport.build(),
+            from: parse_mailbox(from)?,
+            to: settings
+                .email_to
+                .iter()
+                .map(|to| parse_mailbox(to))
+                .collect::<Result<_>>()?,
+            digest_interval: settings.email_digest_interval,
+            pending: vec![],
+            last_digest: Instant::now(),
+        })
+    }
+
+    fn send_email(&self, subject: &str, plain: String, html: String) -> Result<()> {
+        let mut email = lettre::Message::builder()
+            .from(self.from.clone())
+            .subject(subject);
+        for to in &self.to {
+            email = email.to(to.clone());
+        }
+        let email = email.multipart(MultiPart::alternative_plain_html(plain, html))?;
+        self.transport.send(&email)?;
+        Ok(())
+    }
+
+    fn send_digest(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let entries = std::mem::take(&mut self.pending);
+        self.last_digest = Instant::now();
+
+        let subject = digest_summary(&entries);
+        let plain = entries
+            .iter()
+            .map(|entry| format!("{} ({})\n{}\n", entry.title, entry.feed.title, entry.url))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let items = entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "<li><a href=\"{}\">{}</a> ({})</li>",
+                    escape_html(&entry.url),
+                    escape_html(&entry.title),
+                    escape_html(&entry.feed.title)
+                )
+            })
+            .collect::<String>();
+        let html = format!("<h2>{}</h2><ul>{}</ul>", escape_html(&subject), items);
+        self.send_email(&subject, plain, html)
+    }
+}
+
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        if self.digest_interval.is_some() {
+            match message.subject {
+                Subject::Entry(entry) => {
+                    self.pending.push(entry);
+                    return self.tick();
+                }
+                Subject::Digest(entries) => {
+                    self.pending.extend(entries);
+                    return self.tick();
+                }
+                Subject::Status | Subject::FeedError(_) | Subject::UnreadCount(_) => {}
+            }
+        }
+
+        let plain = format!("{}\n\n{}", message.body, message.url);
+        let html = format!(
+            "<p><a href=\"{}\"><b>{}</b></a></p><p>{}</p>",
+            escape_html(&message.url),
+            escape_html(&message.summary),
+            escape_html(&message.body).replace('\n', "<br>")
+        );
+        self.send_email(&message.summary, plain, html)
+    }
+
+    fn tick(&mut self) -> Result<()> {
+        match self.digest_interval {
+            Some(interval) if self.last_digest.elapsed() >= interval => self.send_digest(),
+            _ => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.send_digest()
+    }
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .with_context(|| format!("Invalid email address {}", address))
+}
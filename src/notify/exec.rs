@@ -0,0 +1,167 @@
+//! Running a command for each new entry
+
+use anyhow::{anyhow, Result};
+use log::{debug, error, warn};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+
+use super::{Message, Notifier, Subject};
+use crate::config::Settings;
+use crate::models::Entry;
+
+/// The most entries waiting for the command, after which further ones are dropped
+const MAX_BACKLOG: usize = 100;
+
+/// Runs a shell command for every new entry, with the entry as JSON on its stdin and its
+/// fields in `ENTRY_*` environment variables.
+///
+/// The commands run one after another in a thread of their own, so that commands waiting for
+/// input (e.g. a dmenu picker) don't hold up polling, and a burst of entries doesn't start a
+/// burst of processes.
+pub struct ExecNotifier {
+    command: String,
+    /// Where the entries are handed to the thread, along with the summaries of their
+    /// notifications
+    entries: SyncSender<(Entry, String)>,
+}
+
+impl ExecNotifier {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let command = settings
+            .exec_command
+            .clone()
+            .ok_or_else(|| anyhow!("--exec-command is required by the exec notifier"))?;
+        let (sender, entries) = mpsc::sync_channel::<(Entry, String)>(MAX_BACKLOG);
+        thread::spawn({
+            let command = command.clone();
+            // Ends once the notifier is gone and the entries still waiting have been handled
+            move || {
+                while let Ok((entry, summary)) = entries.recv() {
+                    if let Err(e) = run(&command, &entry, &summary) {
+                        error!("Failed to run `{}`: {}", command, e);
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            command,
+            entries: sender,
+        })
+    }
+
+    /// Hands the entry to the thread, to run the command for it once the ones before it are done
+    fn queue(&self, entry: &Entry, summary: &str) -> Result<()> {
+        match self.entries.try_send((entry.clone(), summary.to_string())) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                warn!(
+                    "Too many entries are waiting for `{}`, leaving out \"{}\"",
+                    self.command, entry.title
+                );
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err(anyhow!("the command runner has stopped")),
+        }
+    }
+}
+
+/// Runs the command for the entry and waits for it to exit
+fn run(command: &str, entry: &Entry, summary: &str) -> Result<()> {
+    let json = serde_json::to_vec(entry)?;
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("ENTRY_ID", entry.id.to_string())
+        .env("ENTRY_TITLE", &entry.title)
+        .env("ENTRY_URL", &entry.url)
+        .env("ENTRY_AUTHOR", &entry.author)
+        .env("ENTRY_FEED", &entry.feed.title)
+        .env(
+            "ENTRY_CATEGORY",
+            entry
+                .feed
+                .category
+                .as_ref()
+                .map_or("", |c| c.title.as_str()),
+        )
+        .env("ENTRY_PUBLISHED_AT", entry.published_at.to_rfc3339())
+        .env("NOTIFICATION_SUMMARY", summary)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take();
+
+    // The command is free to ignore its stdin and exit without reading it
+    if let Some(stdin) = &mut stdin {
+        if let Err(e) = stdin.write_all(&json) {
+            debug!("Failed to write the entry to the command's stdin: {}", e);
+        }
+    }
+    drop(stdin);
+    let status = child.wait()?;
+    if !status.success() {
+        warn!("`{}` exited with {}", command, status);
+    }
+    Ok(())
+}
+
+impl Notifier for ExecNotifier {
+    fn name(&self) -> &'static str {
+        "exec"
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        match &message.subject {
+            Subject::Entry(entry) => self.queue(entry, &message.summary),
+            Subject::Digest(entries) => {
+                for entry in entries {
+                    self.queue(entry, &message.summary)?;
+                }
+                Ok(())
+            }
+            Subject::Status | Subject::FeedError(_) | Subject::UnreadCount(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_settings;
+    use crate::models::test_entry;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn commands_run_one_at_a_time() {
+        let log =
+            std::env::temp_dir().join(format!("miniflux-notifier-exec-{}", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let command = format!(
+            "echo \"$ENTRY_ID start\" >> {0}; sleep 0.1; echo \"$ENTRY_ID end\" >> {0}",
+            log.display()
+        );
+        let mut notifier =
+            ExecNotifier::new(&test_settings(&["--exec-command", &command])).unwrap();
+        let entries = vec![test_entry(1), test_entry(2), test_entry(3)];
+        notifier
+            .send(Message {
+                summary: "3 new entries".to_string(),
+                body: String::new(),
+                url: String::new(),
+                subject: Subject::Digest(entries),
+            })
+            .unwrap();
+
+        let started = Instant::now();
+        let lines = loop {
+            let lines = std::fs::read_to_string(&log).unwrap_or_default();
+            if lines.lines().count() == 6 || started.elapsed() > Duration::from_secs(10) {
+                break lines;
+            }
+            thread::sleep(Duration::from_millis(50));
+        };
+        std::fs::remove_file(&log).unwrap();
+        assert_eq!(lines, "1 start\n1 end\n2 start\n2 end\n3 start\n3 end\n");
+    }
+}
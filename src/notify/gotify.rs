@@ -0,0 +1,72 @@
+//! Push notifications through [Gotify](https://gotify.net)
+
+use anyhow::{anyhow, Result};
+use reqwest::blocking::Client;
+use serde_json::json;
+
+use super::{Message, Notifier, Subject};
+use crate::client;
+use crate::config::Settings;
+
+/// Sends notifications as messages of a Gotify application
+pub struct GotifyNotifier {
+    http: Client,
+    server: String,
+    token: String,
+    priority: u32,
+}
+
+impl GotifyNotifier {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let server = settings
+            .gotify_server
+            .as_deref()
+            .ok_or_else(|| anyhow!("--gotify-server is required by the gotify notifier"))?;
+        let token = settings
+            .gotify_token
+            .clone()
+            .ok_or_else(|| anyhow!("--gotify-token is required by the gotify notifier"))?;
+        Ok(Self {
+            http: client::build_external(settings)?,
+            server: server.trim_end_matches('/').to_string(),
+            token,
+            priority: settings.gotify_priority,
+        })
+    }
+}
+
+impl Notifier for GotifyNotifier {
+    fn name(&self) -> &'static str {
+        "gotify"
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        let mut extras = json!({
+            "client::display": { "contentType": "text/plain" },
+            "client::notification": { "click": { "url": message.url } },
+        });
+        // Lets Gotify clients and plugins act on the entry itself
+        if let Subject::Entry(entry) = &message.subject {
+            extras["miniflux::entry"] = json!({
+                "id": entry.id,
+                "title": entry.title,
+                "author": entry.author,
+                "url": entry.url,
+                "feed": entry.feed.title,
+            });
+        }
+
+        self.http
+            .post(format!("{}/message", self.server))
+            .header("X-Gotify-Key", &self.token)
+            .json(&json!({
+                "title": message.summary,
+                "message": message.body,
+                "priority": self.priority,
+                "extras": extras,
+            }))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
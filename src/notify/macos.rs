@@ -0,0 +1,191 @@
+//! Notifications through the Notification Center of macOS, standing in for the parts of
+//! notify-rust that only exist with a notification service on D-Bus: actions, and waiting for
+//! one of them to be used
+//!
+//! The Notification Center only shows the notifications of app bundles, so they are shown as
+//! coming from the bundle identifier set with [`set_bundle_id`].
+
+use anyhow::{anyhow, Result};
+use log::{debug, error, info};
+use mac_notification_sys::{MainButton, NotificationResponse, Sound};
+use notify_rust::{Hint, Timeout, Urgency};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::OnceLock;
+use std::thread;
+
+/// The name of the app bundle notifications are shown as coming from unless configured
+/// otherwise, if one by that name is installed
+const APP_NAME: &str = "Miniflux Notifier";
+
+/// The id of the next notification shown, as the Notification Center doesn't hand them out
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// The outcome of setting the bundle identifier, which can only be done once
+static BUNDLE_ID: OnceLock<Result<String, String>> = OnceLock::new();
+
+/// Sets the bundle identifier notifications are shown as coming from, e.g.
+/// "com.apple.Terminal", which decides their icon and where they are configured in the system
+/// settings. Without one, the bundle of an app called "Miniflux Notifier" is used if it is
+/// installed, and the one of Finder otherwise.
+pub fn set_bundle_id(bundle_id: Option<&str>) -> Result<()> {
+    let result = BUNDLE_ID.get_or_init(|| {
+        let bundle_id = match bundle_id {
+            Some(bundle_id) => bundle_id.to_string(),
+            None => mac_notification_sys::get_bundle_identifier_or_default(APP_NAME),
+        };
+        info!("Showing notifications as {}", bundle_id);
+        mac_notification_sys::set_application(&bundle_id)
+            .map(|_| bundle_id.clone())
+            .map_err(|e| format!("Failed to show notifications as {}: {}", bundle_id, e))
+    });
+    result.as_ref().map(|_| ()).map_err(|e| anyhow!("{}", e))
+}
+
+/// A notification, built the way notify-rust builds them
+#[derive(Debug, Default)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    icon: Option<String>,
+    /// The ids and labels of the actions
+    actions: Vec<(String, String)>,
+    sound: Option<String>,
+}
+
+impl Notification {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn summary(&mut self, summary: &str) -> &mut Self {
+        self.summary = summary.to_string();
+        self
+    }
+
+    pub fn body(&mut self, body: &str) -> &mut Self {
+        self.body = body.to_string();
+        self
+    }
+
+    /// Adds an action, listed in the menu of the notification
+    pub fn action(&mut self, id: &str, label: &str) -> &mut Self {
+        self.actions.push((id.to_string(), label.to_string()));
+        self
+    }
+
+    /// Shows the image file in the notification
+    pub fn icon(&mut self, path: &str) -> &mut Self {
+        self.icon = Some(path.to_string());
+        self
+    }
+
+    /// Plays one of the system sounds, e.g. "Glass" or "Ping"
+    pub fn sound_name(&mut self, name: &str) -> &mut Self {
+        self.sound = Some(name.to_string());
+        self
+    }
+
+    /// The Notification Center decides by itself how long notifications stay on screen
+    pub fn timeout(&mut self, _timeout: impl Into<Timeout>) -> &mut Self {
+        self
+    }
+
+    /// The Notification Center has no urgencies
+    pub fn urgency(&mut self, _urgency: Urgency) -> &mut Self {
+        self
+    }
+
+    /// Notifications stay in the Notification Center until they're used anyway
+    pub fn hint(&mut self, _hint: Hint) -> &mut Self {
+        self
+    }
+
+    /// Replacing an earlier notification isn't supported, so this shows a new one instead
+    pub fn id(&mut self, _id: u32) -> &mut Self {
+        self
+    }
+
+    pub fn show(&self) -> Result<NotificationHandle> {
+        if BUNDLE_ID.get().is_none() {
+            set_bundle_id(None)?;
+        }
+        let (sender, actions) = mpsc::channel();
+        let (summary, body, icon, buttons, sound) = (
+            self.summary.clone(),
+            self.body.clone(),
+            self.icon.clone(),
+            self.actions.clone(),
+            self.sound.clone(),
+        );
+        // Sending a notification with actions only returns once one of them is used
+        thread::spawn(move || {
+            let labels: Vec<&str> = buttons.iter().map(|(_, label)| label.as_str()).collect();
+            let mut notification = mac_notification_sys::Notification::new();
+            notification.title(&summary).message(&body);
+            if let Some(icon) = &icon {
+                notification.content_image(icon);
+            }
+            if let Some(sound) = &sound {
+                notification.sound(Sound::Custom(sound.clone()));
+            }
+            match labels.as_slice() {
+                [] => {}
+                [label] => {
+                    notification.main_button(MainButton::SingleAction(label));
+                }
+                labels => {
+                    notification.main_button(MainButton::DropdownActions("Actions", labels));
+                }
+            }
+            if !labels.is_empty() {
+                notification.close_button("Close");
+            }
+            let action = match notification.send() {
+                Ok(NotificationResponse::ActionButton(label)) => buttons
+                    .iter()
+                    .find(|(_, l)| *l == label)
+                    .map(|(id, _)| id.clone()),
+                // Clicking the notification itself does what its first action does, like the
+                // default action of a notification on Linux
+                Ok(NotificationResponse::Click) => buttons.first().map(|(id, _)| id.clone()),
+                Ok(response) => {
+                    debug!("Notification closed: {:?}", response);
+                    None
+                }
+                Err(e) => {
+                    error!("Failed to show a notification: {}", e);
+                    None
+                }
+            };
+            let _ = sender.send(action.unwrap_or_else(|| "__closed".to_string()));
+        });
+        Ok(NotificationHandle {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            actions,
+        })
+    }
+}
+
+/// A notification on screen, whose actions can be waited for
+#[derive(Debug)]
+pub struct NotificationHandle {
+    id: u32,
+    actions: Receiver<String>,
+}
+
+impl NotificationHandle {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Waits until an action of the notification is used or it is closed, which is reported
+    /// as the `__closed` action
+    pub fn wait_for_action(self, on_action: impl FnOnce(&str)) {
+        let action = self
+            .actions
+            .recv()
+            .unwrap_or_else(|_| "__closed".to_string());
+        on_action(&action);
+    }
+}
@@ -0,0 +1,97 @@
+//! Messages to a Matrix room through the client-server API
+
+use anyhow::{anyhow, Result};
+use reqwest::blocking::Client;
+use reqwest::Url;
+use serde_json::json;
+
+use super::{escape_html, Message, Notifier};
+use crate::client;
+use crate::config::Settings;
+use crate::state;
+
+/// Posts each notification as a formatted message into a room
+pub struct MatrixNotifier {
+    http: Client,
+    homeserver: Url,
+    room: String,
+    access_token: String,
+    /// Makes the transaction id of each message unique, as the server drops messages that
+    /// reuse one
+    txn_counter: u64,
+}
+
+impl MatrixNotifier {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let homeserver = settings
+            .matrix_homeserver
+            .as_deref()
+            .ok_or_else(|| anyhow!("--matrix-homeserver is required by the matrix notifier"))?;
+        let room = settings
+            .matrix_room
+            .clone()
+            .ok_or_else(|| anyhow!("--matrix-room is required by the matrix notifier"))?;
+        let access_token = settings
+            .matrix_access_token
+            .clone()
+            .ok_or_else(|| anyhow!("--matrix-access-token is required by the matrix notifier"))?;
+        Ok(Self {
+            http: client::build_external(settings)?,
+            homeserver: Url::parse(homeserver)
+                .map_err(|e| anyhow!("Invalid Matrix homeserver URL {}: {}", homeserver, e))?,
+            room,
+            access_token,
+            txn_counter: 0,
+        })
+    }
+
+    fn send_url(&mut self) -> Result<Url> {
+        self.txn_counter += 1;
+        let txn_id = format!("miniflux-notifier-{}-{}", state::now(), self.txn_counter);
+        let mut url = self.homeserver.clone();
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid Matrix homeserver URL {}", self.homeserver))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &self.room,
+                "send",
+                "m.room.message",
+                &txn_id,
+            ]);
+        Ok(url)
+    }
+}
+
+impl Notifier for MatrixNotifier {
+    fn name(&self) -> &'static str {
+        "matrix"
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        let body = format!("{}\n{}\n{}", message.summary, message.body, message.url);
+        let formatted_body = format!(
+            "<a href=\"{}\"><b>{}</b></a><br>{}",
+            escape_html(&message.url),
+            escape_html(&message.summary),
+            escape_html(&message.body).replace('\n', "<br>")
+        );
+
+        let url = self.send_url()?;
+        self.http
+            .put(url)
+            .bearer_auth(&self.access_token)
+            .json(&json!({
+                "msgtype": "m.text",
+                "body": body,
+                "format": "org.matrix.custom.html",
+                "formatted_body": formatted_body,
+            }))
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,768 @@
+//! Sending notifications about entries to the configured backends
+
+use anyhow::Result;
+use clap::ValueEnum;
+use log::error;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::config::Settings;
+use crate::filter::{matches_feed_or_category, Matcher, NotifierFilter};
+use crate::history::History;
+use crate::metrics::Metrics;
+use crate::models::{Entry, FeedDetails};
+use crate::snooze::Snoozes;
+use crate::source::{FeedSource, SourceKind};
+use crate::template;
+
+pub mod desktop;
+#[cfg(feature = "email")]
+pub mod email;
+pub mod exec;
+pub mod gotify;
+#[cfg(target_os = "macos")]
+pub mod macos;
+pub mod matrix;
+pub mod mqtt;
+pub mod ntfy;
+pub mod speech;
+pub mod telegram;
+#[cfg(windows)]
+pub mod toast;
+
+/// The notification backends that can be selected in the settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifierKind {
+    /// Desktop notifications through the notification service on D-Bus, the Notification
+    /// Center on macOS or toasts on Windows
+    Desktop,
+    /// Push notifications through an ntfy topic
+    Ntfy,
+    /// Push notifications through a Gotify server
+    Gotify,
+    /// Messages to a Telegram chat
+    Telegram,
+    /// Messages to a Matrix room
+    Matrix,
+    /// Emails through an SMTP server
+    Email,
+    /// Running a command for each new entry
+    Exec,
+    /// Reading out new entries through speech-dispatcher or another speech synthesizer
+    Speech,
+    /// Publishing new entries as JSON to an MQTT broker
+    Mqtt,
+}
+
+/// How the connection to the SMTP server is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// TLS from the start, usually on port 465
+    Tls,
+    /// Upgrading a plain connection with STARTTLS, usually on port 587
+    Starttls,
+    /// No encryption at all, only for servers on the same machine
+    None,
+}
+
+/// Decides where following the notifications about entries from some feeds or categories
+/// leads
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpenRule {
+    #[serde(default)]
+    pub feeds: Vec<Matcher>,
+    #[serde(default)]
+    pub categories: Vec<Matcher>,
+    /// Whether to open the entry in Miniflux rather than on the original site
+    pub in_miniflux: Option<bool>,
+    /// The command to open the entry with, see `--open-command`
+    pub command: Option<String>,
+}
+
+impl OpenRule {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        matches_feed_or_category(&self.feeds, &self.categories, entry)
+    }
+}
+
+/// Gives the entries from some feeds or categories a priority, with higher ones notified about
+/// before lower ones when several are found at once
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PriorityRule {
+    #[serde(default)]
+    pub feeds: Vec<Matcher>,
+    #[serde(default)]
+    pub categories: Vec<Matcher>,
+    pub priority: i32,
+}
+
+impl PriorityRule {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        matches_feed_or_category(&self.feeds, &self.categories, entry)
+    }
+}
+
+/// The priority of the first rule matching the entry, 0 if none does
+pub fn priority(entry: &Entry, rules: &[PriorityRule]) -> i32 {
+    rules
+        .iter()
+        .find(|rule| rule.matches(entry))
+        .map_or(0, |rule| rule.priority)
+}
+
+/// What a notification is about
+#[derive(Debug, Clone)]
+pub enum Subject {
+    /// A single new entry
+    Entry(Entry),
+    /// Several new entries at once
+    Digest(Vec<Entry>),
+    /// The notifier itself, e.g. the server becoming unreachable
+    Status,
+    /// Miniflux failing to fetch a feed
+    FeedError(FeedDetails),
+    /// The number of unread entries having grown past the threshold
+    UnreadCount(u32),
+}
+
+/// A notification, ready to be handed to a backend
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub summary: String,
+    pub body: String,
+    /// Where following the notification leads
+    pub url: String,
+    pub subject: Subject,
+}
+
+/// Somewhere notifications can be sent
+pub trait Notifier {
+    /// The name of the backend, for log messages
+    fn name(&self) -> &'static str;
+
+    fn send(&mut self, message: Message) -> Result<()>;
+
+    /// Sends the notification once it is due rather than right away, for backends that can
+    /// hold on to it until then
+    fn send_at(&mut self, message: Message, due: Instant) -> Result<()> {
+        let _ = due;
+        self.send(message)
+    }
+
+    /// Called regularly, for backends that hold on to notifications to send them later
+    fn tick(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Sends anything held back, called before shutting down
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends every notification to each of the configured backends.
+///
+/// A backend failing doesn't keep the notification from reaching the others. Each backend can
+/// have a filter of its own, which entries have to pass before they are sent to it.
+#[derive(Default)]
+pub struct Notifiers {
+    notifiers: Vec<(Box<dyn Notifier>, Option<NotifierFilter>)>,
+    /// The name of the server the notifications are about, if it has one
+    server_name: Option<String>,
+    metrics: Option<Arc<Metrics>>,
+    /// Where the entries notified about are recorded
+    history: Option<History>,
+    /// The notifications waiting to be sent, along with when they are due
+    delayed: Vec<(Instant, Message)>,
+}
+
+impl Notifiers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, notifier: Box<dyn Notifier>, filter: Option<NotifierFilter>) {
+        self.notifiers.push((notifier, filter));
+    }
+
+    /// Sets up the backends selected in the settings
+    pub fn from_settings(
+        settings: &Settings,
+        client: &Arc<dyn FeedSource>,
+        snoozes: &Snoozes,
+        history: &History,
+    ) -> Result<Self> {
+        let mut notifiers = Self::new();
+        notifiers.server_name = settings.name.clone();
+        notifiers.metrics = Some(Metrics::for_server(settings));
+        notifiers.history = Some(history.clone());
+        for kind in &settings.notifiers {
+            let notifier: Box<dyn Notifier> = match kind {
+                NotifierKind::Desktop => Box::new(desktop::DesktopNotifier::new(
+                    settings,
+                    Arc::clone(client),
+                    snoozes.clone(),
+                    history.clone(),
+                )?),
+                NotifierKind::Ntfy => Box::new(ntfy::NtfyNotifier::new(settings)?),
+                NotifierKind::Gotify => Box::new(gotify::GotifyNotifier::new(settings)?),
+                NotifierKind::Telegram => Box::new(telegram::TelegramNotifier::new(
+                    settings,
+                    Arc::clone(client),
+                )?),
+                NotifierKind::Matrix => Box::new(matrix::MatrixNotifier::new(settings)?),
+                NotifierKind::Email => email_notifier(settings)?,
+                NotifierKind::Exec => Box::new(exec::ExecNotifier::new(settings)?),
+                NotifierKind::Speech => Box::new(speech::SpeechNotifier::new(settings)?),
+                NotifierKind::Mqtt => Box::new(mqtt::MqttNotifier::new(settings)?),
+            };
+            notifiers.add(notifier, settings.notifier_filters.get(kind).cloned());
+        }
+        Ok(notifiers)
+    }
+}
+
+impl Notifier for Notifiers {
+    fn name(&self) -> &'static str {
+        "all"
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        // The entries some backend managed to notify about, as the others count for nothing
+        let mut notified = HashSet::new();
+        for (notifier, filter) in &mut self.notifiers {
+            let message = match filter {
+                Some(filter) => match filter_message(&message, filter, self.server_name.as_deref())
+                {
+                    Some(message) => message,
+                    None => continue,
+                },
+                None => message.clone(),
+            };
+            let entry_ids = entry_ids(&message.subject);
+            match notifier.send(message) {
+                Ok(()) => {
+                    notified.extend(entry_ids);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_notification(notifier.name());
+                    }
+                }
+                Err(e) => error!("Failed to send {} notification: {:?}", notifier.name(), e),
+            }
+        }
+        if let Some(history) = &self.history {
+            match &message.subject {
+                Subject::Entry(entry) if notified.contains(&entry.id) => {
+                    history.record(entry, &message.url)
+                }
+                Subject::Digest(entries) => {
+                    for entry in entries.iter().filter(|entry| notified.contains(&entry.id)) {
+                        history.record(entry, &entry.url);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn send_at(&mut self, message: Message, due: Instant) -> Result<()> {
+        self.delayed.push((due, message));
+        Ok(())
+    }
+
+    fn tick(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let (due, delayed) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(due, _)| *due <= now);
+        self.delayed = delayed;
+        for (_, message) in due {
+            self.send(message)?;
+        }
+        for (notifier, _) in &mut self.notifiers {
+            if let Err(e) = notifier.tick() {
+                error!("Failed to send {} notification: {:?}", notifier.name(), e);
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        for (_, message) in std::mem::take(&mut self.delayed) {
+            self.send(message)?;
+        }
+        for (notifier, _) in &mut self.notifiers {
+            if let Err(e) = notifier.flush() {
+                error!("Failed to send {} notification: {:?}", notifier.name(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The IDs of the entries the notification is about
+fn entry_ids(subject: &Subject) -> Vec<u64> {
+    match subject {
+        Subject::Entry(entry) => vec![entry.id],
+        Subject::Digest(entries) => entries.iter().map(|entry| entry.id).collect(),
+        Subject::Status | Subject::FeedError(_) | Subject::UnreadCount(_) => vec![],
+    }
+}
+
+/// The part of the message that passes the filter, `None` if nothing does.
+///
+/// Digests are rewritten to only cover the entries that pass.
+fn filter_message(
+    message: &Message,
+    filter: &NotifierFilter,
+    server_name: Option<&str>,
+) -> Option<Message> {
+    match &message.subject {
+        Subject::Entry(entry) => filter.allows(entry).then(|| message.clone()),
+        Subject::Digest(entries) => {
+            let allowed: Vec<Entry> = entries
+                .iter()
+                .filter(|entry| filter.allows(entry))
+                .cloned()
+                .collect();
+            if allowed.is_empty() {
+                return None;
+            }
+            if allowed.len() == entries.len() {
+                return Some(message.clone());
+            }
+            Some(Message {
+                summary: with_server_name(digest_summary(&allowed), server_name),
+                body: digest_body(&allowed),
+                url: message.url.clone(),
+                subject: Subject::Digest(allowed),
+            })
+        }
+        Subject::Status | Subject::FeedError(_) | Subject::UnreadCount(_) => Some(message.clone()),
+    }
+}
+
+#[cfg(feature = "email")]
+fn email_notifier(settings: &Settings) -> Result<Box<dyn Notifier>> {
+    Ok(Box::new(email::EmailNotifier::new(settings)?))
+}
+
+#[cfg(not(feature = "email"))]
+fn email_notifier(_settings: &Settings) -> Result<Box<dyn Notifier>> {
+    Err(anyhow::anyhow!(
+        "Sending notifications by email requires building with the \"email\" feature"
+    ))
+}
+
+/// Sends a notification for each of the entries, or for each of their feeds when grouping by
+/// feed, or a single digest if there are more than the digest threshold.
+///
+/// Beyond the maximum number of notifications, the remaining entries are rolled into a digest.
+/// With a priority delay, the notifications of lower priorities are handed over to be sent
+/// later, as waiting here would hold up everything else.
+pub fn send_notification_batch(
+    notifier: &mut impl Notifier,
+    mut entries: Vec<Entry>,
+    options: &Settings,
+) -> Result<()> {
+    if options
+        .digest_threshold
+        .is_some_and(|threshold| entries.len() > threshold)
+    {
+        return send_digest(notifier, entries, options);
+    }
+
+    // The most important and then the newest entries go first, and are the ones left out of
+    // the digest of the rest
+    let priority = |entry: &Entry| priority(entry, &options.priority_rules);
+    entries.sort_by(|a, b| {
+        priority(b)
+            .cmp(&priority(a))
+            .then(b.published_at.cmp(&a.published_at))
+    });
+
+    let mut notifications: Vec<Vec<Entry>> = match options.group_by_feed {
+        true => group_by_feed(entries),
+        false => entries.into_iter().map(|entry| vec![entry]).collect(),
+    };
+    let rest: Vec<Entry> = match options.max_notifications {
+        Some(max) if notifications.len() > max => {
+            notifications.split_off(max).into_iter().flatten().collect()
+        }
+        _ => vec![],
+    };
+
+    let mut notifier = Deferred {
+        notifier,
+        due: None,
+    };
+    let mut last_priority = None;
+    for mut entries in notifications {
+        let priority = priority(&entries[0]);
+        if let Some(delay) = options.priority_delay {
+            if last_priority.is_some_and(|last_priority| priority < last_priority) {
+                notifier.due = Some(notifier.due.unwrap_or_else(Instant::now) + delay);
+            }
+        }
+        last_priority = Some(priority);
+        match entries.len() {
+            1 => send_entry(&mut notifier, entries.remove(0), options)?,
+            _ => send_feed_group(&mut notifier, entries, options)?,
+        }
+    }
+    if !rest.is_empty() {
+        send_digest(&mut notifier, rest, options)?;
+    }
+    Ok(())
+}
+
+/// Hands the notifications over to be sent once they are due, or right away without a due
+/// time
+struct Deferred<'a, N> {
+    notifier: &'a mut N,
+    due: Option<Instant>,
+}
+
+impl<N: Notifier> Notifier for Deferred<'_, N> {
+    fn name(&self) -> &'static str {
+        self.notifier.name()
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        match self.due {
+            Some(due) => self.notifier.send_at(message, due),
+            None => self.notifier.send(message),
+        }
+    }
+}
+
+fn send_entry(notifier: &mut impl Notifier, entry: Entry, options: &Settings) -> Result<()> {
+    let feed_template = options
+        .feed_templates
+        .iter()
+        .find(|feed_template| feed_template.matches(&entry));
+    let summary_template = feed_template
+        .and_then(|feed_template| feed_template.summary.as_deref())
+        .unwrap_or(&options.summary_template);
+    let body_template = feed_template
+        .and_then(|feed_template| feed_template.body.as_deref())
+        .unwrap_or(&options.body_template);
+
+    let mut summary = template::render(summary_template, &entry, options.excerpt_length);
+    if options.show_category {
+        if let Some(category) = &entry.feed.category {
+            summary = format!("[{}] {}", category.title, summary);
+        }
+    }
+
+    // Placeholders that turn out empty shouldn't leave blank lines behind
+    let mut body = template::render(body_template, &entry, options.excerpt_length)
+        .trim_end()
+        .to_string();
+    if options.show_reading_time {
+        if let Some(reading_time) = template::reading_time(&entry) {
+            body = format!("{}\n{}", body, reading_time)
+                .trim_start()
+                .to_string();
+        }
+    }
+    notifier.send(Message {
+        summary: with_server_name(summary, options.name.as_deref()),
+        body,
+        url: entry_url(&entry, options),
+        subject: Subject::Entry(entry),
+    })
+}
+
+/// Where following the notification about the entry leads, either the entry on its original
+/// site or in Miniflux, where reading it also marks it as read
+fn entry_url(entry: &Entry, options: &Settings) -> String {
+    let in_miniflux = options
+        .open_rules
+        .iter()
+        .find(|rule| rule.matches(entry))
+        .and_then(|rule| rule.in_miniflux)
+        .unwrap_or(options.open_in_miniflux);
+    match in_miniflux && options.source == SourceKind::Miniflux {
+        true => miniflux_entry_url(&options.server, entry.id),
+        false => entry.url.clone(),
+    }
+}
+
+/// The page of an entry in the Miniflux web interface
+fn miniflux_entry_url(server: &str, entry_id: u64) -> String {
+    format!("{}/unread/entry/{}", server.trim_end_matches('/'), entry_id)
+}
+
+/// Splits the entries up by feed, keeping the feeds in the order their first entry appears in
+fn group_by_feed(entries: Vec<Entry>) -> Vec<Vec<Entry>> {
+    let mut groups: Vec<Vec<Entry>> = vec![];
+    for entry in entries {
+        match groups
+            .iter_mut()
+            .find(|group| group[0].feed.id == entry.feed.id)
+        {
+            Some(group) => group.push(entry),
+            None => groups.push(vec![entry]),
+        }
+    }
+    groups
+}
+
+/// Sends a notification listing the titles of several entries from the same feed, leading to
+/// the unread entries of the feed
+fn send_feed_group(
+    notifier: &mut impl Notifier,
+    entries: Vec<Entry>,
+    options: &Settings,
+) -> Result<()> {
+    notifier.send(Message {
+        summary: with_server_name(digest_summary(&entries), options.name.as_deref()),
+        body: digest_body(&entries),
+        url: format!(
+            "{}/feed/{}/entries",
+            options.server.trim_end_matches('/'),
+            entries[0].feed.id
+        ),
+        subject: Subject::Digest(entries),
+    })
+}
+
+/// Sends a single notification summarizing all of the entries, instead of one per entry
+pub fn send_digest(
+    notifier: &mut impl Notifier,
+    entries: Vec<Entry>,
+    options: &Settings,
+) -> Result<()> {
+    notifier.send(Message {
+        summary: with_server_name(digest_summary(&entries), options.name.as_deref()),
+        body: digest_body(&entries),
+        url: format!("{}/unread", options.server.trim_end_matches('/')),
+        subject: Subject::Digest(entries),
+    })
+}
+
+/// Describes a number of entries, e.g. "3 new entries from 2 feeds", or "3 new from Ars
+/// Technica" if they are all from the same feed
+pub fn digest_summary(entries: &[Entry]) -> String {
+    let feeds: HashSet<u32> = entries.iter().map(|entry| entry.feed.id).collect();
+    if feeds.len() == 1 {
+        return format!("{} new from {}", entries.len(), entries[0].feed.title);
+    }
+    format!("{} new entries from {} feeds", entries.len(), feeds.len())
+}
+
+/// Prefixes the summary with the name of the server, if it has one
+fn with_server_name(summary: String, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("{}: {}", name, summary),
+        None => summary,
+    }
+}
+
+/// Lists the titles of the entries, one per line
+fn digest_body(entries: &[Entry]) -> String {
+    entries
+        .iter()
+        .map(|entry| entry.title.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Sends a notification about the notifier itself rather than about an entry
+pub fn send_status_notification(
+    notifier: &mut impl Notifier,
+    summary: &str,
+    body: &str,
+    options: &Settings,
+) -> Result<()> {
+    notifier.send(Message {
+        summary: with_server_name(summary.to_string(), options.name.as_deref()),
+        body: body.to_string(),
+        url: options.server.clone(),
+        subject: Subject::Status,
+    })
+}
+
+/// Sends a warning about Miniflux failing to fetch the feed, leading to its settings
+pub fn send_feed_error_notification(
+    notifier: &mut impl Notifier,
+    feed: FeedDetails,
+    options: &Settings,
+) -> Result<()> {
+    let summary = format!("Failed to fetch {}", feed.title);
+    let body = match feed.parsing_error_count {
+        1 => feed.parsing_error_message.clone(),
+        count => format!("{} ({} times in a row)", feed.parsing_error_message, count),
+    };
+    notifier.send(Message {
+        summary: with_server_name(summary, options.name.as_deref()),
+        body,
+        url: format!(
+            "{}/feed/{}/edit",
+            options.server.trim_end_matches('/'),
+            feed.id
+        ),
+        subject: Subject::FeedError(feed),
+    })
+}
+
+/// Sends a nudge about the number of unread entries, leading to them in Miniflux
+pub fn send_unread_count_notification(
+    notifier: &mut impl Notifier,
+    total: u32,
+    threshold: u32,
+    options: &Settings,
+) -> Result<()> {
+    let summary = format!("You have {}+ unread entries", threshold);
+    notifier.send(Message {
+        summary: with_server_name(summary, options.name.as_deref()),
+        body: format!("{} entries are waiting to be read", total),
+        url: format!("{}/unread", options.server.trim_end_matches('/')),
+        subject: Subject::UnreadCount(total),
+    })
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_settings;
+    use crate::models::test_entry;
+
+    /// Keeps the messages it is sent
+    #[derive(Default)]
+    struct Recorder(Vec<Message>);
+
+    impl Notifier for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn send(&mut self, message: Message) -> Result<()> {
+            self.0.push(message);
+            Ok(())
+        }
+    }
+
+    /// Keeps the messages it is sent where they can still be looked at once it is added to
+    /// the notifiers
+    #[derive(Default, Clone)]
+    struct Shared(std::rc::Rc<std::cell::RefCell<Vec<Message>>>);
+
+    impl Shared {
+        fn urls(&self) -> Vec<String> {
+            self.0
+                .borrow()
+                .iter()
+                .map(|message| message.url.clone())
+                .collect()
+        }
+    }
+
+    impl Notifier for Shared {
+        fn name(&self) -> &'static str {
+            "shared"
+        }
+
+        fn send(&mut self, message: Message) -> Result<()> {
+            self.0.borrow_mut().push(message);
+            Ok(())
+        }
+    }
+
+    /// Fails to send anything
+    struct Failing;
+
+    impl Notifier for Failing {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        fn send(&mut self, _message: Message) -> Result<()> {
+            Err(anyhow::anyhow!("unreachable"))
+        }
+    }
+
+    fn summary(entry: Entry, args: &[&str]) -> String {
+        let mut recorder = Recorder::default();
+        send_entry(&mut recorder, entry, &test_settings(args)).unwrap();
+        recorder.0.remove(0).summary
+    }
+
+    #[test]
+    fn show_category_prefixes_the_summary() {
+        let entry = test_entry(1);
+        let plain = summary(entry.clone(), &[]);
+        assert_eq!(
+            summary(entry, &["--show-category"]),
+            format!("[News] {}", plain)
+        );
+    }
+
+    #[test]
+    fn show_category_without_a_category() {
+        let mut entry = test_entry(1);
+        entry.feed.category = None;
+        assert_eq!(
+            summary(entry.clone(), &["--show-category"]),
+            summary(entry, &[])
+        );
+    }
+
+    #[test]
+    fn lower_priorities_are_sent_later() {
+        let mut settings = test_settings(&["--priority-delay", "1h"]);
+        settings.priority_rules = vec![PriorityRule {
+            feeds: vec![Matcher::Id(2)],
+            categories: vec![],
+            priority: 1,
+        }];
+        let mut important = test_entry(2);
+        important.feed.id = 2;
+        let sent = Shared::default();
+        let mut notifiers = Notifiers::new();
+        notifiers.add(Box::new(sent.clone()), None);
+
+        send_notification_batch(&mut notifiers, vec![test_entry(1), important], &settings).unwrap();
+        assert_eq!(sent.urls(), ["https://example.com/2"]);
+        notifiers.tick().unwrap();
+        assert_eq!(sent.urls(), ["https://example.com/2"]);
+        notifiers.flush().unwrap();
+        assert_eq!(
+            sent.urls(),
+            ["https://example.com/2", "https://example.com/1"]
+        );
+    }
+
+    #[test]
+    fn only_sent_notifications_are_recorded() {
+        let settings = test_settings(&[]);
+        let history = History::new(vec![]);
+        let mut notifiers = Notifiers::new();
+        notifiers.history = Some(history.clone());
+        notifiers.add(Box::new(Failing), None);
+        send_entry(&mut notifiers, test_entry(1), &settings).unwrap();
+        assert!(history.take_changes().is_none());
+
+        notifiers.add(Box::new(Shared::default()), None);
+        notifiers.add(Box::new(Shared::default()), None);
+        send_entry(&mut notifiers, test_entry(2), &settings).unwrap();
+        let recorded = history.take_changes().unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].entry_id, 2);
+    }
+}
@@ -0,0 +1,303 @@
+//! Publishing new entries to an MQTT broker, for home automation such as Home Assistant to
+//! react to them
+
+use anyhow::{anyhow, Context, Result};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use super::{Message, Notifier, Subject};
+use crate::config::Settings;
+use crate::models::Entry;
+
+const DEFAULT_PORT: u16 = 1883;
+/// How long connecting to the broker and each of its answers may take
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the broker keeps the connection without hearing from us, which is never long as
+/// the connection is closed after publishing
+const KEEP_ALIVE: u16 = 60;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PUBREC: u8 = 0x50;
+/// PUBREL with the flags MQTT requires of it
+const PUBREL: u8 = 0x62;
+const PUBCOMP: u8 = 0x70;
+const DISCONNECT: u8 = 0xe0;
+
+/// Numbers the notifiers of a process, for each to connect with a client ID of its own
+static NOTIFIERS: AtomicU32 = AtomicU32::new(0);
+
+/// Publishes every new entry as JSON to a topic, connecting to the broker for each
+/// notification.
+///
+/// This speaks MQTT 3.1.1 over plain TCP, without TLS.
+pub struct MqttNotifier {
+    broker: String,
+    /// May contain `{feed_id}`, replaced with the ID of the entry's feed
+    topic: String,
+    qos: u8,
+    retain: bool,
+    username: Option<String>,
+    password: Option<String>,
+    /// Unique to the notifier, as the broker drops the connection of a client when another
+    /// connects with the same ID, e.g. the notifier of another server
+    client_id: String,
+    /// The ID of the last QoS 1 or 2 message
+    packet_id: u16,
+}
+
+impl MqttNotifier {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let broker = settings
+            .mqtt_broker
+            .as_deref()
+            .ok_or_else(|| anyhow!("--mqtt-broker is required by the mqtt notifier"))?;
+        if broker.starts_with("mqtts://") || broker.starts_with("ssl://") {
+            return Err(anyhow!(
+                "MQTT over TLS isn't supported, use a plain TCP listener"
+            ));
+        }
+        if settings.mqtt_password.is_some() && settings.mqtt_username.is_none() {
+            return Err(anyhow!("--mqtt-password requires --mqtt-username"));
+        }
+        let broker = broker
+            .trim_start_matches("mqtt://")
+            .trim_start_matches("tcp://")
+            .trim_end_matches('/');
+        let broker = match broker.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => broker.to_string(),
+            _ => format!("{}:{}", broker, DEFAULT_PORT),
+        };
+        Ok(Self {
+            broker,
+            topic: settings.mqtt_topic.clone(),
+            qos: settings.mqtt_qos,
+            retain: settings.mqtt_retain,
+            username: settings.mqtt_username.clone(),
+            password: settings.mqtt_password.clone(),
+            client_id: format!(
+                "miniflux-notifier-{}-{}",
+                std::process::id(),
+                NOTIFIERS.fetch_add(1, Ordering::Relaxed)
+            ),
+            packet_id: 0,
+        })
+    }
+
+    fn publish(&mut self, entries: &[&Entry]) -> Result<()> {
+        let mut stream = self
+            .connect()
+            .with_context(|| format!("Failed to connect to the MQTT broker {}", self.broker))?;
+        for entry in entries {
+            let topic = self.topic.replace("{feed_id}", &entry.feed.id.to_string());
+            let payload = serde_json::to_vec(entry)?;
+            self.publish_one(&mut stream, &topic, &payload)
+                .with_context(|| format!("Failed to publish to {}", topic))?;
+        }
+        write_packet(&mut stream, DISCONNECT, &[])?;
+        Ok(())
+    }
+
+    fn connect(&self) -> Result<TcpStream> {
+        let addr = self
+            .broker
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("{} has no address", self.broker))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        // A clean session, as nothing is subscribed to
+        let mut flags = 0x02;
+        let mut body = vec![];
+        put_string(&mut body, "MQTT")?;
+        body.push(4);
+        let flags_at = body.len();
+        body.push(0);
+        body.extend(KEEP_ALIVE.to_be_bytes());
+        put_string(&mut body, &self.client_id)?;
+        if let Some(username) = &self.username {
+            flags |= 0x80;
+            put_string(&mut body, username)?;
+        }
+        if let Some(password) = &self.password {
+            flags |= 0x40;
+            put_string(&mut body, password)?;
+        }
+        body[flags_at] = flags;
+        write_packet(&mut stream, CONNECT, &body)?;
+
+        let connack = read_packet(&mut stream, CONNACK)?;
+        match connack.get(1) {
+            Some(0) => Ok(stream),
+            Some(4) => Err(anyhow!("The broker rejected the username or password")),
+            Some(5) => Err(anyhow!("The broker requires a username and password")),
+            Some(code) => Err(anyhow!(
+                "The broker refused the connection with code {}",
+                code
+            )),
+            None => Err(anyhow!("The broker sent a malformed CONNACK")),
+        }
+    }
+
+    fn publish_one(&mut self, stream: &mut TcpStream, topic: &str, payload: &[u8]) -> Result<()> {
+        let mut body = vec![];
+        put_string(&mut body, topic)?;
+        let packet_id = match self.qos {
+            0 => None,
+            _ => {
+                self.packet_id = self.packet_id.checked_add(1).unwrap_or(1);
+                body.extend(self.packet_id.to_be_bytes());
+                Some(self.packet_id.to_be_bytes())
+            }
+        };
+        body.extend(payload);
+        write_packet(
+            stream,
+            PUBLISH | (self.qos << 1) | u8::from(self.retain),
+            &body,
+        )?;
+
+        match (self.qos, packet_id) {
+            (1, Some(packet_id)) => {
+                check_packet_id(&read_packet(stream, PUBACK)?, packet_id)?;
+            }
+            (2, Some(packet_id)) => {
+                check_packet_id(&read_packet(stream, PUBREC)?, packet_id)?;
+                write_packet(stream, PUBREL, &packet_id)?;
+                check_packet_id(&read_packet(stream, PUBCOMP)?, packet_id)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for MqttNotifier {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        match &message.subject {
+            Subject::Entry(entry) => self.publish(&[entry]),
+            Subject::Digest(entries) => self.publish(&entries.iter().collect::<Vec<_>>()),
+            Subject::Status | Subject::FeedError(_) | Subject::UnreadCount(_) => Ok(()),
+        }
+    }
+}
+
+/// Appends a string prefixed with its length, as MQTT encodes them
+fn put_string(buf: &mut Vec<u8>, s: &str) -> Result<()> {
+    let len = u16::try_from(s.len()).map_err(|_| {
+        anyhow!(
+            "MQTT strings are at most {} bytes long, got {}",
+            u16::MAX,
+            s.len()
+        )
+    })?;
+    buf.extend(len.to_be_bytes());
+    buf.extend(s.as_bytes());
+    Ok(())
+}
+
+/// Makes sure that the broker acknowledged the message just sent rather than another
+fn check_packet_id(ack: &[u8], packet_id: [u8; 2]) -> Result<()> {
+    match ack.get(..2) {
+        Some(id) if id == packet_id => Ok(()),
+        Some(id) => Err(anyhow!(
+            "The broker acknowledged message {} rather than {}",
+            u16::from_be_bytes([id[0], id[1]]),
+            u16::from_be_bytes(packet_id)
+        )),
+        None => Err(anyhow!("The broker sent a malformed acknowledgement")),
+    }
+}
+
+fn write_packet(stream: &mut impl Write, header: u8, body: &[u8]) -> Result<()> {
+    let mut packet = vec![header];
+    // The remaining length takes 7 bits per byte, the high bit telling whether more follow
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        match len {
+            0 => {
+                packet.push(byte);
+                break;
+            }
+            _ => packet.push(byte | 0x80),
+        }
+    }
+    packet.extend(body);
+    stream.write_all(&packet)?;
+    Ok(())
+}
+
+/// Reads the next packet, which has to be of the given type, and returns its body
+fn read_packet(stream: &mut impl Read, expected: u8) -> Result<Vec<u8>> {
+    let mut byte = [0];
+    stream.read_exact(&mut byte)?;
+    let header = byte[0];
+    let mut len = 0;
+    for shift in (0..4).map(|i| i * 7) {
+        stream.read_exact(&mut byte)?;
+        len |= usize::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body)?;
+    if header & 0xf0 != expected & 0xf0 {
+        return Err(anyhow!(
+            "Expected packet type {:#x} from the broker, got {:#x}",
+            expected >> 4,
+            header >> 4
+        ));
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_settings;
+
+    #[test]
+    fn notifiers_have_client_ids_of_their_own() {
+        let settings = test_settings(&["--mqtt-broker", "localhost"]);
+        let first = MqttNotifier::new(&settings).unwrap();
+        let second = MqttNotifier::new(&settings).unwrap();
+        assert_ne!(first.client_id, second.client_id);
+    }
+
+    #[test]
+    fn overlong_strings_are_rejected() {
+        let mut buf = vec![];
+        put_string(&mut buf, &"a".repeat(usize::from(u16::MAX))).unwrap();
+        assert_eq!(buf[..2], [0xff, 0xff]);
+        assert!(put_string(&mut vec![], &"a".repeat(usize::from(u16::MAX) + 1)).is_err());
+    }
+
+    #[test]
+    fn acknowledgements_must_be_for_the_message_sent() {
+        let mut packets = vec![];
+        write_packet(&mut packets, PUBACK, &7u16.to_be_bytes()).unwrap();
+        write_packet(&mut packets, PUBACK, &8u16.to_be_bytes()).unwrap();
+        write_packet(&mut packets, PUBACK, &[]).unwrap();
+        let mut packets = packets.as_slice();
+        for expected in [Ok(()), Err(()), Err(())] {
+            let ack = read_packet(&mut packets, PUBACK).unwrap();
+            assert_eq!(
+                check_packet_id(&ack, 7u16.to_be_bytes()).map_err(|_| ()),
+                expected
+            );
+        }
+    }
+}
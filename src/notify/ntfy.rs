@@ -0,0 +1,56 @@
+//! Push notifications through [ntfy](https://ntfy.sh)
+
+use anyhow::{anyhow, Result};
+use reqwest::blocking::Client;
+
+use super::{Message, Notifier};
+use crate::client;
+use crate::config::Settings;
+
+/// Publishes notifications to a topic on an ntfy server, which forwards them to every phone
+/// or browser subscribed to it
+pub struct NtfyNotifier {
+    http: Client,
+    server: String,
+    topic: String,
+    priority: u8,
+    token: Option<String>,
+}
+
+impl NtfyNotifier {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let topic = settings
+            .ntfy_topic
+            .clone()
+            .ok_or_else(|| anyhow!("--ntfy-topic is required by the ntfy notifier"))?;
+        Ok(Self {
+            http: client::build_external(settings)?,
+            server: settings.ntfy_server.trim_end_matches('/').to_string(),
+            topic,
+            priority: settings.ntfy_priority,
+            token: settings.ntfy_token.clone(),
+        })
+    }
+}
+
+impl Notifier for NtfyNotifier {
+    fn name(&self) -> &'static str {
+        "ntfy"
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        // Publishing as JSON rather than through headers allows for titles that aren't ASCII
+        let mut request = self.http.post(&self.server).json(&serde_json::json!({
+            "topic": self.topic,
+            "title": message.summary,
+            "message": message.body,
+            "click": message.url,
+            "priority": self.priority,
+        }));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send()?.error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+//! Reading out new entries through speech-dispatcher or another speech synthesizer
+
+use anyhow::Result;
+use log::{error, warn};
+use std::collections::VecDeque;
+use std::process::Command;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use super::{Message, Notifier, Subject};
+use crate::config::Settings;
+
+/// Reads out the text when given in place of `{text}`
+const DEFAULT_COMMAND: &str = "spd-say {text}";
+
+/// Reads out a line such as "New entry from <feed>: <title>" for every new entry.
+///
+/// The lines are read out one after another by a thread of their own, so that synthesizers
+/// which don't queue what they are given (e.g. espeak) don't talk over themselves.
+pub struct SpeechNotifier {
+    command: String,
+    queue: Arc<Queue>,
+}
+
+/// The lines waiting to be read out
+#[derive(Default)]
+struct Queue {
+    lines: Mutex<Lines>,
+    added: Condvar,
+}
+
+#[derive(Default)]
+struct Lines {
+    waiting: VecDeque<String>,
+    /// Whether the notifier is gone, for the thread to stop
+    closed: bool,
+}
+
+impl SpeechNotifier {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let command = settings
+            .speech_command
+            .clone()
+            .unwrap_or(DEFAULT_COMMAND.to_string());
+        let queue = Arc::new(Queue::default());
+        thread::spawn({
+            let command = command.clone();
+            let queue = Arc::clone(&queue);
+            move || {
+                while let Some(line) = queue.next() {
+                    say(&command, &[line]);
+                }
+            }
+        });
+        Ok(Self { command, queue })
+    }
+
+    fn say(&self, line: String) -> Result<()> {
+        self.queue.lines.lock().unwrap().waiting.push_back(line);
+        self.queue.added.notify_one();
+        Ok(())
+    }
+}
+
+impl Queue {
+    /// Waits for the next line, `None` once the notifier is gone
+    fn next(&self) -> Option<String> {
+        let mut lines = self.lines.lock().unwrap();
+        loop {
+            if lines.closed {
+                return None;
+            }
+            if let Some(line) = lines.waiting.pop_front() {
+                return Some(line);
+            }
+            lines = self.added.wait(lines).unwrap();
+        }
+    }
+}
+
+impl Drop for SpeechNotifier {
+    fn drop(&mut self) {
+        self.queue.lines.lock().unwrap().closed = true;
+        self.queue.added.notify_one();
+    }
+}
+
+/// Runs the command once for each line, with the line in place of `{text}`, waiting for it
+/// to finish
+fn say(command: &str, lines: &[String]) {
+    match shell(command, lines).status() {
+        Ok(status) if !status.success() => warn!("`{}` exited with {}", command, status),
+        Ok(_) => {}
+        Err(e) => error!("Failed to run `{}`: {}", command, e),
+    }
+}
+
+/// A shell running the command for each of the lines in turn, which are passed as arguments
+/// rather than quoted into the script
+fn shell(command: &str, lines: &[String]) -> Command {
+    let script = (1..=lines.len())
+        .map(|i| command.replace("{text}", &format!("\"${{{}}}\"", i)))
+        .collect::<Vec<_>>()
+        .join("; ");
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(script).arg("sh").args(lines);
+    shell
+}
+
+impl Notifier for SpeechNotifier {
+    fn name(&self) -> &'static str {
+        "speech"
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        match &message.subject {
+            Subject::Entry(entry) => self.say(format!(
+                "New entry from {}: {}",
+                entry.feed.title, entry.title
+            )),
+            Subject::Digest(entries) => self.say(format!("{} new entries", entries.len())),
+            Subject::Status | Subject::FeedError(_) | Subject::UnreadCount(_) => Ok(()),
+        }
+    }
+
+    /// Hands the lines still waiting to a shell of their own rather than waiting for them to be
+    /// read out, which would hold up reloading or shutting down. They may overlap the line
+    /// being read out at the time.
+    fn flush(&mut self) -> Result<()> {
+        let lines: Vec<String> = self.queue.lines.lock().unwrap().waiting.drain(..).collect();
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let command = self.command.clone();
+        match shell(&command, &lines).spawn() {
+            // Waited for so as not to leave a zombie behind after a reload
+            Ok(mut child) => {
+                thread::spawn(move || match child.wait() {
+                    Ok(status) if !status.success() => {
+                        warn!("`{}` exited with {}", command, status)
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to wait for `{}`: {}", command, e),
+                });
+            }
+            Err(e) => error!("Failed to run `{}`: {}", command, e),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_settings;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn each_line_is_its_own_argument() {
+        let lines = (1..=10).map(|i| format!("line {}", i)).collect::<Vec<_>>();
+        let output = shell("printf '%s\\n' {text}", &lines).output().unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            lines.join("\n") + "\n"
+        );
+    }
+
+    #[test]
+    fn flush_doesnt_wait_for_the_lines() {
+        let settings = test_settings(&["--speech-command", "sleep 2 || {text}"]);
+        let mut notifier = SpeechNotifier::new(&settings).unwrap();
+        notifier.say("one".to_string()).unwrap();
+        notifier.say("two".to_string()).unwrap();
+        notifier.say("three".to_string()).unwrap();
+        let started = Instant::now();
+        notifier.flush().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(notifier.queue.lines.lock().unwrap().waiting.is_empty());
+    }
+}
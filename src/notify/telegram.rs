@@ -0,0 +1,352 @@
+//! Messages to a Telegram chat through the Bot API
+
+use anyhow::{anyhow, Result};
+use log::{debug, error, info};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::{Message, Notifier, Subject};
+use crate::client;
+use crate::config::Settings;
+use crate::source::FeedSource;
+
+/// How long a request for updates waits for one to arrive
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait before asking for updates again after that failed
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Telegram rejects messages longer than 4096 characters, which leaves some room for the
+/// summary and the escaping
+const MAX_BODY_LEN: usize = 3000;
+
+/// The thread polling each bot for updates by its API URL, shared by the notifiers of every
+/// server using the bot as Telegram only answers one request for updates at a time
+static POLLERS: Mutex<BTreeMap<String, Poller>> = Mutex::new(BTreeMap::new());
+
+/// The ID of the next notifier registering with a poller
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The servers whose entries the buttons of a bot's messages mark as read
+#[derive(Default)]
+struct Poller {
+    /// By their number in the callback data, kept even once their notifier is gone so that
+    /// the numbers stay the same
+    servers: Vec<PollerServer>,
+    /// Set once no notifier uses the bot anymore, for the thread to stop after its current
+    /// request, and cleared again if one does before that
+    stop: bool,
+}
+
+struct PollerServer {
+    server: String,
+    /// The ID of the notifier it belongs to, along with the client of the server, `None`
+    /// once the notifier is gone
+    client: Option<(u64, Arc<dyn FeedSource>)>,
+}
+
+/// Sends each notification as a message to a chat, with buttons to open the entry and to mark
+/// it as read.
+///
+/// Pressing "Mark read" is picked up by a background thread that polls the bot for updates,
+/// one for every bot however many notifiers use it.
+pub struct TelegramNotifier {
+    http: Client,
+    api: String,
+    chat_id: String,
+    /// The number of the server in the callback data of the bot
+    server: usize,
+    /// Identifies the notifier to the bot's poller, which a notifier replacing it on reload
+    /// gets a new one of
+    id: u64,
+}
+
+impl TelegramNotifier {
+    pub fn new(settings: &Settings, client: Arc<dyn FeedSource>) -> Result<Self> {
+        let token = settings
+            .telegram_token
+            .as_deref()
+            .ok_or_else(|| anyhow!("--telegram-token is required by the telegram notifier"))?;
+        let chat_id = settings
+            .telegram_chat_id
+            .clone()
+            .ok_or_else(|| anyhow!("--telegram-chat-id is required by the telegram notifier"))?;
+        let http = client::build_external(settings)?;
+        let api = format!("https://api.telegram.org/bot{}", token);
+        let server = settings.name.as_deref().unwrap_or(&settings.server);
+        let (server, id, start) = register(&api, server, client);
+        if start {
+            let http = http.clone();
+            let api = api.clone();
+            thread::spawn(move || handle_callbacks(&http, &api));
+        }
+        Ok(Self {
+            http,
+            api,
+            chat_id,
+            server,
+            id,
+        })
+    }
+}
+
+impl Drop for TelegramNotifier {
+    fn drop(&mut self) {
+        unregister(&self.api, self.server, self.id);
+    }
+}
+
+/// Hands the button presses on the bot's messages about the server to the client. Returns the
+/// number of the server in the callback data, the ID of the registration and whether the bot's
+/// poller has to be started as it isn't running yet.
+fn register(api: &str, server: &str, client: Arc<dyn FeedSource>) -> (usize, u64, bool) {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    let mut pollers = POLLERS.lock().unwrap();
+    let running = pollers.contains_key(api);
+    let poller = pollers.entry(api.to_string()).or_default();
+    poller.stop = false;
+    let number = match poller.servers.iter().position(|s| s.server == server) {
+        Some(number) => number,
+        None => {
+            poller.servers.push(PollerServer {
+                server: server.to_string(),
+                client: None,
+            });
+            poller.servers.len() - 1
+        }
+    };
+    poller.servers[number].client = Some((id, client));
+    (number, id, !running)
+}
+
+/// Stops handing the button presses to the notifier with the ID, stopping the bot's poller if
+/// no notifier uses it anymore
+fn unregister(api: &str, server: usize, id: u64) {
+    let mut pollers = POLLERS.lock().unwrap();
+    let Some(poller) = pollers.get_mut(api) else {
+        return;
+    };
+    let server = &mut poller.servers[server];
+    // A notifier replacing this one on reload may have taken over already
+    if server
+        .client
+        .as_ref()
+        .is_some_and(|(current, _)| *current == id)
+    {
+        server.client = None;
+    }
+    poller.stop = poller.servers.iter().all(|server| server.client.is_none());
+}
+
+/// The client of the server with the number, `None` if its notifier is gone
+fn client_for(api: &str, server: usize) -> Option<Arc<dyn FeedSource>> {
+    let pollers = POLLERS.lock().unwrap();
+    let server = pollers.get(api)?.servers.get(server)?;
+    server.client.as_ref().map(|(_, client)| Arc::clone(client))
+}
+
+/// Removes the poller of the bot if no notifier uses it anymore, returning whether it did
+fn should_stop(api: &str) -> bool {
+    let mut pollers = POLLERS.lock().unwrap();
+    if !pollers.get(api).is_some_and(|poller| poller.stop) {
+        return false;
+    }
+    pollers.remove(api);
+    true
+}
+
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &'static str {
+        "telegram"
+    }
+
+    fn send(&mut self, message: Message) -> Result<()> {
+        let body: String = message.body.chars().take(MAX_BODY_LEN).collect();
+        let text = format!(
+            "*{}*\n{}",
+            escape_markdown(&message.summary),
+            escape_markdown(&body)
+        );
+        let open = json!({ "text": "Open", "url": message.url });
+        let buttons = match &message.subject {
+            Subject::Entry(entry) => json!([[
+                open,
+                {
+                    "text": "Mark read",
+                    "callback_data": format!("read:{}:{}", self.server, entry.id),
+                },
+            ]]),
+            Subject::Digest(_) | Subject::FeedError(_) | Subject::UnreadCount(_) => {
+                json!([[open]])
+            }
+            Subject::Status => json!([]),
+        };
+
+        self.http
+            .post(format!("{}/sendMessage", self.api))
+            .json(&json!({
+                "chat_id": self.chat_id,
+                "text": text,
+                "parse_mode": "MarkdownV2",
+                "reply_markup": { "inline_keyboard": buttons },
+            }))
+            .send()
+            .and_then(|response| response.error_for_status())
+            // The URL contains the bot token
+            .map_err(reqwest::Error::without_url)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Updates {
+    result: Vec<Update>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Update {
+    update_id: i64,
+    callback_query: Option<CallbackQuery>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackQuery {
+    id: String,
+    data: Option<String>,
+}
+
+/// Long-polls the bot for button presses and marks the entries as read, until no notifier
+/// uses the bot anymore
+fn handle_callbacks(http: &Client, api: &str) {
+    let mut offset = 0;
+    while !should_stop(api) {
+        let updates = http
+            .post(format!("{}/getUpdates", api))
+            .timeout(LONG_POLL_TIMEOUT * 2)
+            .json(&json!({
+                "offset": offset,
+                "timeout": LONG_POLL_TIMEOUT.as_secs(),
+                "allowed_updates": ["callback_query"],
+            }))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json::<Updates>())
+            .map_err(reqwest::Error::without_url);
+        let updates = match updates {
+            Ok(updates) => updates,
+            Err(e) => {
+                error!("Failed to get updates from Telegram: {}", e);
+                thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+
+        for update in updates.result {
+            offset = offset.max(update.update_id + 1);
+            let Some(query) = update.callback_query else {
+                continue;
+            };
+            let Some(data) = query
+                .data
+                .as_deref()
+                .and_then(|data| data.strip_prefix("read:"))
+            else {
+                debug!("Ignoring unknown Telegram callback {:?}", query.data);
+                continue;
+            };
+            // Messages sent before servers were numbered are about the first one
+            let (server, id) = data.split_once(':').unwrap_or(("0", data));
+            let (Ok(server), Ok(id)) = (server.parse::<usize>(), id.parse::<u64>()) else {
+                continue;
+            };
+            let answer = match client_for(api, server) {
+                Some(client) => match client.mark_read(&[id]) {
+                    Ok(()) => {
+                        info!("Marked entry {} as read from Telegram", id);
+                        "Marked as read".to_string()
+                    }
+                    Err(e) => {
+                        error!("Failed to mark the entry as read: {}", e);
+                        format!("Failed to mark as read: {}", e)
+                    }
+                },
+                None => "The server isn't watched anymore".to_string(),
+            };
+            let answered = http
+                .post(format!("{}/answerCallbackQuery", api))
+                .json(&json!({ "callback_query_id": query.id, "text": answer }))
+                .send()
+                .and_then(|response| response.error_for_status())
+                .map_err(reqwest::Error::without_url);
+            if let Err(e) = answered {
+                error!("Failed to answer the Telegram callback: {}", e);
+            }
+        }
+    }
+}
+
+/// Escapes the characters that have a meaning in Telegram's MarkdownV2
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_settings;
+    use crate::source;
+
+    fn client() -> Arc<dyn FeedSource> {
+        source::from_settings(&test_settings(&[])).unwrap()
+    }
+
+    #[test]
+    fn one_poller_per_bot() {
+        let api = "https://api.telegram.org/bot-test-shared";
+        let (first, first_id, start) = register(api, "first", client());
+        assert!(start);
+        let (second, second_id, start) = register(api, "second", client());
+        assert!(!start);
+        assert_ne!(first, second);
+
+        // Reloading replaces the notifier before the old one is dropped
+        let (reloaded, reloaded_id, start) = register(api, "first", client());
+        assert!(!start);
+        assert_eq!(reloaded, first);
+        unregister(api, first, first_id);
+        assert!(client_for(api, first).is_some());
+        assert!(!should_stop(api));
+
+        unregister(api, first, reloaded_id);
+        assert!(client_for(api, first).is_none());
+        assert!(!should_stop(api));
+        unregister(api, second, second_id);
+        assert!(should_stop(api));
+        assert!(!POLLERS.lock().unwrap().contains_key(api));
+    }
+
+    #[test]
+    fn registering_again_keeps_a_stopping_poller() {
+        let api = "https://api.telegram.org/bot-test-restart";
+        let (server, id, _) = register(api, "server", client());
+        unregister(api, server, id);
+        let (_, _, start) = register(api, "server", client());
+        // The thread is still running and carries on rather than a second one being started
+        assert!(!start);
+        assert!(!should_stop(api));
+    }
+}
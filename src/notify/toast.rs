@@ -0,0 +1,196 @@
+//! Toast notifications on Windows, standing in for the parts of notify-rust that only exist
+//! with a notification service on D-Bus: buttons for the actions of a notification, and
+//! waiting for one of them to be used
+//!
+//! Windows only shows the toasts of applications it knows, so the notifier registers an
+//! AppUserModelID for itself under the current user before showing the first one.
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use notify_rust::{Hint, Timeout, Urgency};
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Once;
+use tauri_winrt_notification::{Duration, IconCrop, Scenario, Sound, Toast};
+
+/// The AppUserModelID the toasts are shown under
+const APP_ID: &str = "MinifluxNotifier";
+
+/// How the notifier is called in the notification settings and on its toasts
+const DISPLAY_NAME: &str = "Miniflux Notifier";
+
+/// The id of the next toast shown, as Windows doesn't give toasts ids of their own
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+static REGISTER: Once = Once::new();
+
+/// A toast, built the way notify-rust builds notifications
+#[derive(Debug, Default)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    icon: Option<PathBuf>,
+    /// The ids and labels of the buttons
+    actions: Vec<(String, String)>,
+    timeout: Timeout,
+    sound: Option<Sound>,
+    urgency: Option<Urgency>,
+}
+
+impl Notification {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn summary(&mut self, summary: &str) -> &mut Self {
+        self.summary = summary.to_string();
+        self
+    }
+
+    pub fn body(&mut self, body: &str) -> &mut Self {
+        self.body = body.to_string();
+        self
+    }
+
+    /// Adds a button, of which Windows shows up to five
+    pub fn action(&mut self, id: &str, label: &str) -> &mut Self {
+        self.actions.push((id.to_string(), label.to_string()));
+        self
+    }
+
+    /// Shows the image file next to the text
+    pub fn icon(&mut self, path: &str) -> &mut Self {
+        self.icon = Some(PathBuf::from(path));
+        self
+    }
+
+    /// Plays one of the sounds of Windows, e.g. "Mail" or "Reminder", rather than one from the
+    /// freedesktop sound theme
+    pub fn sound_name(&mut self, name: &str) -> &mut Self {
+        match Sound::from_str(name) {
+            Ok(sound) => self.sound = Some(sound),
+            Err(_) => warn!(
+                "{:?} isn't a sound of Windows, playing the default one",
+                name
+            ),
+        }
+        self
+    }
+
+    pub fn timeout(&mut self, timeout: impl Into<Timeout>) -> &mut Self {
+        self.timeout = timeout.into();
+        self
+    }
+
+    /// Critical toasts stay on screen until they're dismissed
+    pub fn urgency(&mut self, urgency: Urgency) -> &mut Self {
+        self.urgency = Some(urgency);
+        self
+    }
+
+    /// Toasts stay in the notification center until they're used anyway
+    pub fn hint(&mut self, _hint: Hint) -> &mut Self {
+        self
+    }
+
+    /// Replacing an earlier toast isn't supported, so this shows a new one instead
+    pub fn id(&mut self, _id: u32) -> &mut Self {
+        self
+    }
+
+    pub fn show(&self) -> Result<NotificationHandle> {
+        REGISTER.call_once(register_app_id);
+        let mut toast = Toast::new(APP_ID)
+            .title(&self.summary)
+            .text1(&self.body)
+            .duration(match self.timeout {
+                // Long toasts stay on screen for about 25 seconds
+                Timeout::Never => Duration::Long,
+                Timeout::Milliseconds(ms) if ms >= 25_000 => Duration::Long,
+                _ => Duration::Short,
+            });
+        if let Some(sound) = self.sound {
+            toast = toast.sound(Some(sound));
+        }
+        if matches!(self.urgency, Some(Urgency::Critical)) {
+            toast = toast.scenario(Scenario::Reminder);
+        }
+        if let Some(icon) = &self.icon {
+            toast = toast.icon(icon, IconCrop::Square, "");
+        }
+        for (id, label) in &self.actions {
+            toast = toast.add_button(label, id);
+        }
+
+        let (sender, actions) = mpsc::channel();
+        let dismissed = sender.clone();
+        // Clicking the toast itself does what its first button does, like the default action
+        // of a notification on Linux
+        let default_action = self.actions.first().map(|(id, _)| id.clone());
+        toast = toast
+            .on_activated(move |action| {
+                if let Some(action) = action.or_else(|| default_action.clone()) {
+                    let _ = sender.send(action);
+                }
+                Ok(())
+            })
+            .on_dismissed(move |reason| {
+                debug!("Toast dismissed: {:?}", reason);
+                let _ = dismissed.send("__closed".to_string());
+                Ok(())
+            });
+        toast
+            .show()
+            .map_err(|e| anyhow!("Failed to show a toast: {}", e))?;
+        Ok(NotificationHandle {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            actions,
+        })
+    }
+}
+
+/// A toast on screen, whose actions can be waited for
+#[derive(Debug)]
+pub struct NotificationHandle {
+    id: u32,
+    actions: Receiver<String>,
+}
+
+impl NotificationHandle {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Waits until a button of the toast is clicked or the toast is dismissed, which is
+    /// reported as the `__closed` action
+    pub fn wait_for_action(self, on_action: impl FnOnce(&str)) {
+        // Windows lets go of the callbacks once the toast is gone for good
+        let action = self
+            .actions
+            .recv()
+            .unwrap_or_else(|_| "__closed".to_string());
+        on_action(&action);
+    }
+}
+
+/// Registers the AppUserModelID of the notifier for the current user, so that its toasts are
+/// shown under its own name rather than not at all
+fn register_app_id() {
+    let key = format!(r"HKCU\Software\Classes\AppUserModelId\{}", APP_ID);
+    let result = Command::new("reg")
+        .args(["add", &key, "/v", "DisplayName", "/t", "REG_SZ"])
+        .args(["/d", DISPLAY_NAME, "/f"])
+        .output();
+    match result {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => warn!(
+            "Failed to register the AppUserModelID {}: {}",
+            APP_ID,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => warn!("Failed to register the AppUserModelID {}: {}", APP_ID, e),
+    }
+}
@@ -0,0 +1,42 @@
+//! Telling whether the computer is running on battery, so that a laptop can poll less often
+//! and let its radio sleep
+//!
+//! UPower is asked for the state. Without it, the computer is never considered to be on
+//! battery.
+
+use std::time::Duration;
+
+use crate::cached_check::CachedCheck;
+
+static CHECK: CachedCheck = CachedCheck::new(Duration::from_secs(1), check);
+
+/// Whether the computer is running on battery
+pub fn on_battery() -> bool {
+    CHECK.get()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn check() -> bool {
+    use zbus::zvariant::OwnedValue;
+
+    let Some(connection) = crate::session::system_bus() else {
+        return false;
+    };
+    connection
+        .call_method(
+            Some("org.freedesktop.UPower"),
+            "/org/freedesktop/UPower",
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &("org.freedesktop.UPower", "OnBattery"),
+        )
+        .and_then(|reply| reply.body::<OwnedValue>())
+        .ok()
+        .and_then(|value| bool::try_from(value).ok())
+        .unwrap_or(false)
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn check() -> bool {
+    false
+}
@@ -0,0 +1,155 @@
+//! Rules that mute entries based on regular expressions
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+use crate::models::Entry;
+
+/// Mutes the entries matching its patterns
+///
+/// A rule can have patterns for several fields of an entry, in which case all of them have to
+/// match for the entry to be muted.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawMuteRule")]
+pub struct MuteRule {
+    /// Matched against the title, the author and the URL, any of which may match
+    any: Option<Regex>,
+    title: Option<Regex>,
+    author: Option<Regex>,
+    url: Option<Regex>,
+}
+
+impl MuteRule {
+    /// A rule muting entries with a title, author or URL matching `pattern`
+    pub fn any(pattern: Regex) -> Self {
+        Self {
+            any: Some(pattern),
+            title: None,
+            author: None,
+            url: None,
+        }
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let matches = |pattern: &Option<Regex>, text: &str| match pattern {
+            Some(pattern) => pattern.is_match(text),
+            None => true,
+        };
+        let any_matches = match &self.any {
+            Some(pattern) => [&entry.title, &entry.author, &entry.url]
+                .iter()
+                .any(|text| pattern.is_match(text)),
+            None => true,
+        };
+
+        any_matches
+            && matches(&self.title, &entry.title)
+            && matches(&self.author, &entry.author)
+            && matches(&self.url, &entry.url)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMuteRule {
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    any: Option<Regex>,
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    title: Option<Regex>,
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    author: Option<Regex>,
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    url: Option<Regex>,
+}
+
+impl TryFrom<RawMuteRule> for MuteRule {
+    type Error = &'static str;
+
+    fn try_from(raw: RawMuteRule) -> Result<Self, Self::Error> {
+        if raw.any.is_none() && raw.title.is_none() && raw.author.is_none() && raw.url.is_none() {
+            return Err("a mute rule needs at least one of any, title, author or url");
+        }
+        Ok(Self {
+            any: raw.any,
+            title: raw.title,
+            author: raw.author,
+            url: raw.url,
+        })
+    }
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Regex>, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_entry;
+
+    fn rule(toml: &str) -> MuteRule {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn entry(title: &str, author: &str, url: &str) -> Entry {
+        Entry {
+            title: title.to_string(),
+            author: author.to_string(),
+            url: url.to_string(),
+            ..test_entry(1)
+        }
+    }
+
+    #[test]
+    fn mute_rules() {
+        let sponsored = entry("Sponsored: a new phone", "Ads", "https://example.com/ad");
+        let regular = entry("A new phone", "Jo", "https://example.com/phones/1");
+        // The rule, and whether it mutes the sponsored and the regular entry
+        let cases = [
+            // A plain word is found anywhere in the text
+            (r#"any = "phone""#, [true, true]),
+            (r#"any = "Sponsored""#, [true, false]),
+            // Patterns are case sensitive unless they say otherwise
+            (r#"any = "sponsored""#, [false, false]),
+            (r#"any = "(?i)sponsored""#, [true, false]),
+            // Anchors make the pattern a regular expression rather than a word to look for
+            (r#"title = "^A new""#, [false, true]),
+            (r#"url = "/phones/\\d+$""#, [false, true]),
+            // `any` also looks at the author and URL, the others only at their own field
+            (r#"any = "^Ads$""#, [true, false]),
+            (r#"title = "^Ads$""#, [false, false]),
+            (r#"author = "^Ads$""#, [true, false]),
+            // Every pattern of a rule has to match
+            ("title = \"phone\"\nauthor = \"Jo\"", [false, true]),
+            ("title = \"phone\"\nurl = \"/ad$\"", [true, false]),
+        ];
+        for (toml, expected) in cases {
+            let rule = rule(toml);
+            assert_eq!(
+                [rule.matches(&sponsored), rule.matches(&regular)],
+                expected,
+                "{}",
+                toml
+            );
+        }
+    }
+
+    #[test]
+    fn command_line_patterns_look_at_every_field() {
+        let rule = MuteRule::any(Regex::new("example\\.com/ad").unwrap());
+        assert!(rule.matches(&entry("Title", "", "https://example.com/ad")));
+        assert!(!rule.matches(&entry("Title", "", "https://example.com/1")));
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        for toml in ["", r#"title = "(""#, r#"content = "phone""#] {
+            assert!(toml::from_str::<MuteRule>(toml).is_err(), "{}", toml);
+        }
+    }
+}
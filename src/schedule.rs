@@ -0,0 +1,190 @@
+//! Quiet hours, during which notifications are held back
+
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// A daily window of time, optionally limited to some days of the week
+///
+/// Written as `22:00-08:00` or `Mon-Fri 22:00-08:00`, where the days are a comma separated
+/// list of days (`Sat,Sun`) or ranges of days (`Mon-Fri`). A window that ends before it starts
+/// runs past midnight, in which case the days refer to the day the window starts on.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct QuietHours {
+    /// Indexed by the number of days since Monday
+    days: [bool; 7],
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, now: NaiveDateTime) -> bool {
+        let time = now.time();
+        let today = self.days[now.weekday().num_days_from_monday() as usize];
+        if self.start <= self.end {
+            return today && self.start <= time && time < self.end;
+        }
+        let yesterday = (now - Duration::days(1)).weekday();
+        (today && time >= self.start)
+            || (self.days[yesterday.num_days_from_monday() as usize] && time < self.end)
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (days, times) = match s.trim().rsplit_once(' ') {
+            Some((days, times)) => (parse_days(days.trim())?, times),
+            None => ([true; 7], s.trim()),
+        };
+        let (start, end) = times
+            .split_once('-')
+            .ok_or_else(|| format!("expected a time range like 22:00-08:00, got {:?}", times))?;
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|_| format!("invalid time {:?}, expected HH:MM", time))
+        };
+        Ok(Self {
+            days,
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        })
+    }
+}
+
+impl TryFrom<String> for QuietHours {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+fn parse_days(s: &str) -> Result<[bool; 7], String> {
+    let parse_day = |day: &str| {
+        day.trim()
+            .parse::<Weekday>()
+            .map(|day| day.num_days_from_monday() as usize)
+            .map_err(|_| format!("invalid day {:?}", day))
+    };
+
+    let mut days = [false; 7];
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse_day(first)?, parse_day(last)?);
+                let mut day = first;
+                loop {
+                    days[day] = true;
+                    if day == last {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            }
+            None => days[parse_day(part)?] = true,
+        }
+    }
+    Ok(days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// A time on a day of June 2024, whose 3rd was a Monday
+    fn at(day: u32, time: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, day)
+            .unwrap()
+            .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap())
+    }
+
+    fn quiet(spec: &str, times: &[(u32, &str)]) -> Vec<bool> {
+        let hours: QuietHours = spec.parse().unwrap();
+        times
+            .iter()
+            .map(|(day, time)| hours.contains(at(*day, time)))
+            .collect()
+    }
+
+    #[test]
+    fn windows_across_midnight() {
+        assert_eq!(
+            quiet(
+                "22:00-08:00",
+                &[
+                    (4, "21:59"),
+                    (4, "22:00"),
+                    (4, "23:59"),
+                    (5, "00:00"),
+                    (5, "07:59"),
+                    (5, "08:00")
+                ]
+            ),
+            [false, true, true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn windows_within_a_day() {
+        assert_eq!(
+            quiet(
+                "09:00-17:00",
+                &[(4, "08:59"), (4, "09:00"), (4, "16:59"), (4, "17:00")]
+            ),
+            [false, true, true, false]
+        );
+    }
+
+    #[test]
+    fn weekdays_refer_to_the_night_the_window_starts() {
+        // Friday night into Saturday morning, and Sunday night into Monday morning
+        assert_eq!(
+            quiet(
+                "Mon-Fri 22:00-08:00",
+                &[
+                    (7, "23:00"),
+                    (8, "07:00"),
+                    (8, "23:00"),
+                    (9, "07:00"),
+                    (9, "23:00"),
+                    (10, "07:00")
+                ]
+            ),
+            [true, true, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn lists_and_wrapping_ranges_of_days() {
+        // Monday to Sunday, 2024-06-03 to 2024-06-09
+        let week: Vec<(u32, &str)> = (3..=9).map(|day| (day, "12:00")).collect();
+        assert_eq!(
+            quiet("Sat,Sun 00:00-23:59", &week),
+            [false, false, false, false, false, true, true]
+        );
+        assert_eq!(
+            quiet("Fri-Mon 00:00-23:59", &week),
+            [true, false, false, false, true, true, true]
+        );
+    }
+
+    #[test]
+    fn malformed_specs_are_rejected() {
+        for spec in [
+            "",
+            "22:00",
+            "22:00-",
+            "25:00-08:00",
+            "22:00-08:60",
+            "Funday 22:00-08:00",
+            "Mon- 22:00-08:00",
+            "Mon-Fri",
+        ] {
+            assert!(spec.parse::<QuietHours>().is_err(), "{:?}", spec);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Polling some feeds or categories on timers of their own, e.g. the news every minute while
+//! everything else is only polled every half hour
+//!
+//! Each feed or category with a timer of its own is fetched with a request narrowed down to
+//! it. The entries it covers are left out when polling all feeds, so that they are only
+//! notified about on its own timer, whether that is sooner or later than the rest.
+
+use log::{debug, error, info};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use crate::config::{Settings, MIN_POLL_INTERVAL};
+use crate::diff::find_new_entries;
+use crate::filter::Matcher;
+use crate::models::{Entries, Entry};
+use crate::source::{Error, FeedSource, Scope, SourceKind};
+use crate::state::StateFile;
+
+/// Gives the feeds and categories it matches a poll interval of their own
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PollRule {
+    #[serde(default)]
+    pub feeds: Vec<Matcher>,
+    #[serde(default)]
+    pub categories: Vec<Matcher>,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+}
+
+/// The timers of the feeds and categories polled on their own
+#[derive(Debug)]
+pub struct ScopedPolls {
+    rules: Vec<PollRule>,
+    /// What the rules match on the server, `None` until the feeds and categories could be
+    /// listed
+    polls: Option<Vec<ScopedPoll>>,
+}
+
+#[derive(Debug)]
+struct ScopedPoll {
+    scope: Scope,
+    interval: Duration,
+    last_poll: Option<Instant>,
+    /// The newest entry seen in the scope, for only fetching newer ones with `--incremental`
+    newest_published_at: Option<i64>,
+}
+
+impl ScopedPolls {
+    /// `None` without any rules, as well as for sources other than Miniflux
+    pub fn new(settings: &Settings) -> Option<Self> {
+        if settings.poll_rules.is_empty() {
+            return None;
+        }
+        if settings.source != SourceKind::Miniflux {
+            error!("Polling feeds and categories on their own is only supported with Miniflux");
+            return None;
+        }
+        Some(Self {
+            rules: settings.poll_rules.clone(),
+            polls: None,
+        })
+    }
+
+    /// How long until the next feed or category is due, `None` before knowing what they are
+    pub fn until_next(&self) -> Option<Duration> {
+        self.polls
+            .iter()
+            .flatten()
+            .map(|poll| match poll.last_poll {
+                Some(last_poll) => poll.interval.saturating_sub(last_poll.elapsed()),
+                None => Duration::ZERO,
+            })
+            .min()
+    }
+
+    /// Leaves out the entries fetched when polling all feeds that are up to a feed or category
+    /// with a timer of its own to notify about. The ones already seen stay, so that they are
+    /// still remembered as seen afterwards.
+    ///
+    /// Nothing is left out without a previous poll to compare with, as the first poll only
+    /// takes note of what is already there.
+    pub fn leave_out(
+        &self,
+        unread_entries: &mut Entries,
+        published_after: Option<i64>,
+        state: &StateFile,
+    ) {
+        let Some(polls) = &self.polls else {
+            return;
+        };
+        let covered = |entry: &Entry| polls.iter().any(|poll| poll.scope.contains(entry));
+        if published_after.is_some() {
+            unread_entries.entries.retain(|entry| !covered(entry));
+        } else if let Some(last_entries) = &state.state.last_entries {
+            let seen: HashSet<u64> = last_entries.iter().map(|entry| entry.id).collect();
+            unread_entries
+                .entries
+                .retain(|entry| !covered(entry) || seen.contains(&entry.id));
+        }
+    }
+
+    /// Polls the feeds and categories that are due, returning the new entries found in them
+    pub fn poll_due(
+        &mut self,
+        client: &dyn FeedSource,
+        settings: &Settings,
+        state: &mut StateFile,
+    ) -> Vec<Entry> {
+        if self.polls.is_none() {
+            match self.resolve(client) {
+                Ok(polls) => self.polls = Some(polls),
+                Err(e) => {
+                    error!("Failed to list the feeds and categories to poll: {}", e);
+                    return vec![];
+                }
+            }
+        }
+
+        let mut new_entries = vec![];
+        // A feed can be in a category polled on its own as well
+        let mut found_ids = HashSet::new();
+        for poll in self.polls.iter_mut().flatten() {
+            if poll
+                .last_poll
+                .is_some_and(|last_poll| last_poll.elapsed() < poll.interval)
+            {
+                continue;
+            }
+            poll.last_poll = Some(Instant::now());
+
+            let published_after = match settings.incremental {
+                true => poll.newest_published_at.or(state.state.newest_published_at),
+                false => None,
+            };
+            let unread_entries = match client.scoped_unread_entries(poll.scope, published_after) {
+                Ok(Some(unread_entries)) => unread_entries,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("Failed to get the unread entries of {}: {}", poll.scope, e);
+                    continue;
+                }
+            };
+            if unread_entries.not_modified {
+                debug!("The unread entries of {} haven't changed", poll.scope);
+                continue;
+            }
+            poll.newest_published_at = unread_entries
+                .entries
+                .iter()
+                .map(|entry| entry.published_at.timestamp())
+                .chain(poll.newest_published_at)
+                .max();
+
+            // As with polling all feeds, the first poll only takes note of what is there
+            let mut found = match (published_after, &state.state.last_entries) {
+                (Some(_), _) => unread_entries.entries,
+                (None, Some(last_entries)) if !settings.incremental => {
+                    find_new_entries(last_entries, &unread_entries.entries)
+                }
+                (None, _) => vec![],
+            };
+            found.retain(|entry| found_ids.insert(entry.id));
+            if !found.is_empty() {
+                info!("New entries in {}: {}", poll.scope, found.len());
+                if !settings.incremental {
+                    state.add_last_entries(&found);
+                }
+                new_entries.extend(found);
+            }
+        }
+        new_entries
+    }
+
+    /// Works out the feeds and categories that the rules match, with the shortest interval
+    /// winning for the ones matched more than once
+    fn resolve(&self, client: &dyn FeedSource) -> Result<Vec<ScopedPoll>, Error> {
+        let feeds = client.feeds()?.unwrap_or_default();
+        let categories = client.categories()?.unwrap_or_default();
+        let mut polls: Vec<ScopedPoll> = vec![];
+        for rule in &self.rules {
+            let interval = rule.interval.max(MIN_POLL_INTERVAL);
+            let scopes = feeds
+                .iter()
+                .filter(|feed| rule.feeds.iter().any(|m| m.matches(feed.id, &feed.title)))
+                .map(|feed| Scope::Feed(feed.id))
+                .chain(
+                    categories
+                        .iter()
+                        .filter(|category| {
+                            rule.categories
+                                .iter()
+                                .any(|m| m.matches(category.id, &category.title))
+                        })
+                        .map(|category| Scope::Category(category.id)),
+                );
+            for scope in scopes {
+                match polls.iter_mut().find(|poll| poll.scope == scope) {
+                    Some(poll) => poll.interval = poll.interval.min(interval),
+                    None => polls.push(ScopedPoll {
+                        scope,
+                        interval,
+                        last_poll: None,
+                        newest_published_at: None,
+                    }),
+                }
+            }
+        }
+        for poll in &polls {
+            info!(
+                "Polling {} every {}",
+                poll.scope,
+                humantime::format_duration(poll.interval)
+            );
+        }
+        Ok(polls)
+    }
+}
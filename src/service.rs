@@ -0,0 +1,199 @@
+//! Installing the notifier as a service that starts with the user's session
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Args as ClapArgs, ValueEnum};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The service managers a service can be written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ServiceKind {
+    /// A systemd user unit
+    Systemd,
+    /// A launchd agent, for macOS
+    Launchd,
+}
+
+/// The options of the `install-service` subcommand
+#[derive(ClapArgs, Debug, Clone)]
+pub struct InstallServiceArgs {
+    /// The service manager to write the service for [default: launchd on macOS, systemd
+    /// elsewhere]
+    #[clap(long, value_enum)]
+    kind: Option<ServiceKind>,
+
+    /// Where to write the service [default: ~/.config/systemd/user/miniflux-notifier.service
+    /// or ~/Library/LaunchAgents/org.miniflux-notifier.plist]
+    #[clap(long)]
+    output: Option<PathBuf>,
+
+    /// Overwrite the service if it already exists
+    #[clap(long)]
+    force: bool,
+}
+
+/// Writes a service running the current executable with the given config file, and prints
+/// how to enable it
+pub fn install(args: &InstallServiceArgs, config: Option<&Path>) -> Result<()> {
+    let kind = args.kind.unwrap_or(match cfg!(target_os = "macos") {
+        true => ServiceKind::Launchd,
+        false => ServiceKind::Systemd,
+    });
+    let path = match &args.output {
+        Some(path) => path.clone(),
+        None => default_path(kind)?,
+    };
+    if path.exists() && !args.force {
+        return Err(anyhow!(
+            "{} already exists, pass --force to overwrite it",
+            path.display()
+        ));
+    }
+
+    let exe = env::current_exe().context("Failed to find the path of the executable")?;
+    let config = config
+        .map(|config| {
+            config
+                .canonicalize()
+                .with_context(|| format!("Failed to find config file {}", config.display()))
+        })
+        .transpose()?;
+    let mut command = vec![exe.to_string_lossy().into_owned()];
+    if let Some(config) = &config {
+        command.push("--config".to_string());
+        command.push(config.to_string_lossy().into_owned());
+    }
+
+    let contents = match kind {
+        ServiceKind::Systemd => systemd_unit(&command),
+        ServiceKind::Launchd => launchd_plist(&command, &launchd_log_path()?),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    println!("Wrote {}", path.display());
+    match kind {
+        ServiceKind::Systemd => println!(
+            "Enable it with: systemctl --user daemon-reload && systemctl --user enable --now {}",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ),
+        ServiceKind::Launchd => println!("Enable it with: launchctl load -w {}", path.display()),
+    }
+    Ok(())
+}
+
+fn default_path(kind: ServiceKind) -> Result<PathBuf> {
+    let path = match kind {
+        ServiceKind::Systemd => {
+            dirs::config_dir().map(|dir| dir.join("systemd/user/miniflux-notifier.service"))
+        }
+        ServiceKind::Launchd => {
+            dirs::home_dir().map(|dir| dir.join("Library/LaunchAgents/org.miniflux-notifier.plist"))
+        }
+    };
+    path.ok_or_else(|| anyhow!("Could not determine where to write the service, use --output"))
+}
+
+/// A unit of `Type=notify`, so that systemd knows when the server has been reached and
+/// restarts the notifier if it stops pinging the watchdog. `systemctl --user reload` reloads
+/// the config file.
+fn systemd_unit(command: &[String]) -> String {
+    let exec_start: Vec<String> = command.iter().map(|arg| systemd_quote(arg)).collect();
+    format!(
+        "[Unit]
+Description=Desktop notifications for new Miniflux entries
+After=graphical-session.target network-online.target
+PartOf=graphical-session.target
+
+[Service]
+Type=notify
+ExecStart={}
+ExecReload=/bin/kill -HUP $MAINPID
+Restart=on-failure
+RestartSec=30s
+WatchdogSec=5min
+
+[Install]
+WantedBy=graphical-session.target
+",
+        exec_start.join(" ")
+    )
+}
+
+/// Quotes an argument of `ExecStart=` if it needs to be, escaping the characters systemd
+/// would otherwise interpret
+fn systemd_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | '$' | '%' | ';'))
+    {
+        return arg.to_string();
+    }
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "$$")
+        .replace('%', "%%");
+    format!("\"{}\"", escaped)
+}
+
+/// Where the agent's log is written, as launchd doesn't keep it otherwise
+fn launchd_log_path() -> Result<PathBuf> {
+    dirs::home_dir()
+        .map(|dir| dir.join("Library/Logs/miniflux-notifier.log"))
+        .ok_or_else(|| anyhow!("Could not determine where the log of the agent should go"))
+}
+
+/// An agent started on login and restarted whenever it exits with an error. It is only
+/// loaded into the graphical session, where the Notification Center can be reached, and runs
+/// as an interactive process so that macOS doesn't throttle its timers.
+fn launchd_plist(command: &[String], log: &Path) -> String {
+    let arguments: String = command
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>org.miniflux-notifier</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ThrottleInterval</key>
+    <integer>30</integer>
+    <key>LimitLoadToSessionType</key>
+    <string>Aqua</string>
+    <key>ProcessType</key>
+    <string>Interactive</string>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+        arguments,
+        log = xml_escape(&log.to_string_lossy())
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,132 @@
+//! Telling whether the user is away from the desktop, so that notifications can wait for them
+//! to come back instead of expiring unseen
+//!
+//! The session counts as locked if the screensaver of the desktop is active, or logind has
+//! marked the graphical session as locked or idle.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::cached_check::CachedCheck;
+
+static CHECK: CachedCheck = CachedCheck::new(Duration::from_secs(1), check);
+
+/// Whether the session is locked or idle
+pub fn is_locked() -> bool {
+    CHECK.get()
+}
+
+// Kept between checks, and connected again after failing
+#[cfg(all(unix, not(target_os = "macos")))]
+static SESSION_BUS: Mutex<Option<zbus::blocking::Connection>> = Mutex::new(None);
+#[cfg(all(unix, not(target_os = "macos")))]
+static SYSTEM_BUS: Mutex<Option<zbus::blocking::Connection>> = Mutex::new(None);
+
+/// A connection to the session bus, shared by everything asking the desktop about the user
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn session_bus() -> Option<zbus::blocking::Connection> {
+    connect(&SESSION_BUS, zbus::blocking::Connection::session)
+}
+
+/// A connection to the system bus, shared like the one to the session bus
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn system_bus() -> Option<zbus::blocking::Connection> {
+    connect(&SYSTEM_BUS, zbus::blocking::Connection::system)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn connect(
+    bus: &Mutex<Option<zbus::blocking::Connection>>,
+    connect: fn() -> zbus::Result<zbus::blocking::Connection>,
+) -> Option<zbus::blocking::Connection> {
+    let mut bus = bus.lock().unwrap();
+    if bus.is_none() {
+        *bus = connect()
+            .inspect_err(|e| log::debug!("Failed to connect to D-Bus: {}", e))
+            .ok();
+    }
+    bus.clone()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn check() -> bool {
+    use log::debug;
+    use zbus::blocking::Connection;
+    use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+
+    const SCREENSAVERS: [(&str, &str); 2] = [
+        (
+            "org.freedesktop.ScreenSaver",
+            "/org/freedesktop/ScreenSaver",
+        ),
+        ("org.gnome.ScreenSaver", "/org/gnome/ScreenSaver"),
+    ];
+
+    let screensaver_active = |connection: &Connection| {
+        SCREENSAVERS.iter().any(|(name, path)| {
+            connection
+                .call_method(Some(*name), *path, Some(*name), "GetActive", &())
+                .and_then(|reply| reply.body::<bool>())
+                .is_ok_and(|active| active)
+        })
+    };
+    if session_bus().is_some_and(|c| screensaver_active(&c)) {
+        return true;
+    }
+
+    let property = |connection: &Connection, path: &str, interface: &str, name: &str| {
+        connection
+            .call_method(
+                Some("org.freedesktop.login1"),
+                path,
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &(interface, name),
+            )
+            .and_then(|reply| reply.body::<OwnedValue>())
+    };
+    let session_locked = |connection: &Connection| -> zbus::Result<bool> {
+        // The graphical session of the user, as the notifier may be running as a service
+        // outside of it
+        let display = property(
+            connection,
+            "/org/freedesktop/login1/user/self",
+            "org.freedesktop.login1.User",
+            "Display",
+        )?;
+        let (_, session): (String, OwnedObjectPath) = display.try_into()?;
+        if session.as_str() == "/" {
+            return Ok(false);
+        }
+        for hint in ["LockedHint", "IdleHint"] {
+            let value = property(
+                connection,
+                session.as_str(),
+                "org.freedesktop.login1.Session",
+                hint,
+            )?;
+            if bool::try_from(value)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    };
+    let Some(system_bus) = system_bus() else {
+        return false;
+    };
+    match session_locked(&system_bus) {
+        Ok(locked) => locked,
+        Err(e) => {
+            debug!("Failed to ask logind whether the session is locked: {}", e);
+            if matches!(e, zbus::Error::InputOutput(_)) {
+                *SYSTEM_BUS.lock().unwrap() = None;
+            }
+            false
+        }
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn check() -> bool {
+    false
+}
@@ -0,0 +1,77 @@
+//! Entries snoozed from their notification, to be notified about again later
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+use crate::models::Entry;
+
+/// An entry whose notification is to be shown again once it is due
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnoozedEntry {
+    pub entry: Entry,
+    pub summary: String,
+    pub body: String,
+    pub url: String,
+    /// When to notify about the entry again, in seconds since the unix epoch
+    pub due: u64,
+}
+
+/// The snoozed entries, shared between the threads handling the actions of notifications and
+/// the one showing them again.
+///
+/// Cloning gives another handle to the same entries.
+#[derive(Debug, Clone, Default)]
+pub struct Snoozes {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: Vec<SnoozedEntry>,
+    /// Whether the entries changed since they were last persisted
+    changed: bool,
+}
+
+impl Snoozes {
+    pub fn new(entries: Vec<SnoozedEntry>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                entries,
+                changed: false,
+            })),
+        }
+    }
+
+    pub fn add(&self, snoozed: SnoozedEntry) {
+        let mut inner = self.inner.lock().unwrap();
+        // Snoozing an entry again only postpones it
+        inner.entries.retain(|s| s.entry.id != snoozed.entry.id);
+        inner.entries.push(snoozed);
+        inner.changed = true;
+    }
+
+    /// Removes the entries that are due at `now` and returns them, the longest overdue first
+    pub fn take_due(&self, now: u64) -> Vec<SnoozedEntry> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.entries.iter().any(|s| s.due <= now) {
+            return vec![];
+        }
+        let (mut due, rest) = std::mem::take(&mut inner.entries)
+            .into_iter()
+            .partition::<Vec<_>, _>(|s| s.due <= now);
+        inner.entries = rest;
+        inner.changed = true;
+        due.sort_by_key(|s| s.due);
+        due
+    }
+
+    /// All of the snoozed entries, if they changed since the last call
+    pub fn take_changes(&self) -> Option<Vec<SnoozedEntry>> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.changed {
+            return None;
+        }
+        inner.changed = false;
+        Some(inner.entries.clone())
+    }
+}
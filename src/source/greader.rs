@@ -0,0 +1,362 @@
+//! A client for the Google Reader API, as implemented by FreshRSS, The Old Reader and others
+//!
+//! The server URL is the base of the API, e.g. `https://freshrss.example.org/api/greader.php`
+//! for FreshRSS.
+
+use chrono::DateTime;
+use log::warn;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use serde::Deserialize;
+use std::sync::Mutex;
+
+use super::{send, stable_id, Error, FeedSource};
+use crate::config::Settings;
+use crate::models::{Category, Entries, Entry, Feed, User};
+
+const READING_LIST: &str = "user/-/state/com.google/reading-list";
+const READ: &str = "user/-/state/com.google/read";
+const STARRED: &str = "user/-/state/com.google/starred";
+
+/// The prefix of item ids in their long form, followed by the id as 16 hex digits
+const ITEM_ID_PREFIX: &str = "tag:google.com,2005:reader/item/";
+
+/// A server speaking the Google Reader API and the credentials to log in with.
+///
+/// The API doesn't take the credentials with every request, instead they are exchanged for a
+/// token when the first request is made, and again whenever the token is rejected.
+#[derive(Debug)]
+pub struct GoogleReader {
+    http: Client,
+    server: String,
+    username: String,
+    password: String,
+    page_size: u32,
+    token: Mutex<Option<HeaderValue>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserInfo {
+    user_id: String,
+    user_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Stream {
+    items: Vec<Item>,
+    continuation: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Item {
+    id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    author: String,
+    published: i64,
+    #[serde(default)]
+    canonical: Vec<Link>,
+    #[serde(default)]
+    alternate: Vec<Link>,
+    origin: Origin,
+    #[serde(default)]
+    categories: Vec<String>,
+    /// Some servers send the content as the summary instead
+    #[serde(default)]
+    content: Option<Content>,
+    #[serde(default)]
+    summary: Option<Content>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Link {
+    href: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Content {
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Origin {
+    stream_id: String,
+    #[serde(default)]
+    title: String,
+}
+
+impl GoogleReader {
+    pub fn new(http: Client, settings: &Settings, username: &str, password: &str) -> Self {
+        Self {
+            http,
+            server: settings.server.trim_end_matches('/').to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            page_size: settings.page_size,
+            token: Mutex::new(None),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/reader/api/0/{}", self.server, path)
+    }
+
+    /// Exchanges the credentials for a token
+    fn login(&self) -> Result<HeaderValue, Error> {
+        let response = send(
+            self.http
+                .post(format!("{}/accounts/ClientLogin", self.server))
+                .form(&[("Email", &self.username), ("Passwd", &self.password)]),
+        )?;
+        let status = response.status();
+        let body = response.text()?;
+        let token = body
+            .lines()
+            .find_map(|line| line.strip_prefix("Auth="))
+            .ok_or(Error::Auth(status))?;
+        let mut value = HeaderValue::from_str(&format!("GoogleLogin auth={}", token.trim()))
+            .map_err(|_| Error::Auth(status))?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+
+    /// Sends an authenticated request, logging in first if there is no token yet
+    fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let token = {
+            let mut token = self.token.lock().unwrap();
+            match &*token {
+                Some(token) => token.clone(),
+                None => token.insert(self.login()?).clone(),
+            }
+        };
+        let result = send(request.header(AUTHORIZATION, token));
+        if let Err(Error::Auth(_)) = result {
+            // The token may have expired, so log in again on the next request
+            *self.token.lock().unwrap() = None;
+        }
+        result
+    }
+
+    /// Gets the token that has to accompany every request that changes something
+    fn action_token(&self) -> Result<String, Error> {
+        Ok(self
+            .send(self.http.get(self.url("token")))?
+            .text()?
+            .trim()
+            .to_string())
+    }
+
+    /// Adds and removes a tag from the items
+    fn edit_tag(&self, ids: &[u64], add: Option<&str>, remove: Option<&str>) -> Result<(), Error> {
+        let mut form: Vec<(&str, String)> = ids.iter().map(|&id| ("i", item_id(id))).collect();
+        if let Some(add) = add {
+            form.push(("a", add.to_string()));
+        }
+        if let Some(remove) = remove {
+            form.push(("r", remove.to_string()));
+        }
+        form.push(("T", self.action_token()?));
+        self.send(self.http.post(self.url("edit-tag")).form(&form))?;
+        Ok(())
+    }
+}
+
+impl FeedSource for GoogleReader {
+    /// The API has no healthcheck, so any response at all counts as the server being up
+    fn healthcheck(&self) -> Result<(), Error> {
+        self.http.get(&self.server).send()?;
+        Ok(())
+    }
+
+    fn me(&self) -> Result<User, Error> {
+        let user: UserInfo = self
+            .send(
+                self.http
+                    .get(self.url("user-info"))
+                    .query(&[("output", "json")]),
+            )?
+            .json()?;
+        Ok(User {
+            id: user
+                .user_id
+                .parse()
+                .unwrap_or_else(|_| stable_id(&user.user_id)),
+            username: user.user_name,
+        })
+    }
+
+    /// Follows the continuations of the stream one page at a time
+    fn unread_entries(&self, published_after: Option<i64>) -> Result<Entries, Error> {
+        let mut query = vec![
+            ("output", "json".to_string()),
+            ("xt", READ.to_string()),
+            ("n", self.page_size.to_string()),
+        ];
+        if let Some(published_after) = published_after {
+            query.push(("ot", published_after.to_string()));
+        }
+
+        let mut entries = vec![];
+        let mut continuation = None;
+        loop {
+            let mut request = self
+                .http
+                .get(self.url(&format!("stream/contents/{}", READING_LIST)))
+                .query(&query);
+            if let Some(continuation) = &continuation {
+                request = request.query(&[("c", continuation)]);
+            }
+            let page: Stream = self.send(request)?.json()?;
+            entries.extend(page.items.into_iter().filter_map(|item| {
+                let entry = item.to_entry();
+                if entry.is_none() {
+                    warn!("Skipping entry with unknown id {:?}", item.id);
+                }
+                entry
+            }));
+
+            match page.continuation {
+                Some(next) if !next.is_empty() => continuation = Some(next),
+                _ => break,
+            }
+        }
+
+        // `ot` includes the entries published at that second, unlike `published_after`
+        if let Some(published_after) = published_after {
+            entries.retain(|entry| entry.published_at.timestamp() > published_after);
+        }
+
+        Ok(Entries {
+            total: entries.len() as u32,
+            entries,
+            not_modified: false,
+        })
+    }
+
+    fn mark_read(&self, ids: &[u64]) -> Result<(), Error> {
+        self.edit_tag(ids, Some(READ), None)
+    }
+
+    /// The API can only add or remove the star, so this looks up whether the entry has one first
+    fn toggle_bookmark(&self, id: u64) -> Result<(), Error> {
+        let stream: Stream = self
+            .send(
+                self.http
+                    .post(self.url("stream/items/contents"))
+                    .query(&[("output", "json")])
+                    .form(&[("i", item_id(id))]),
+            )?
+            .json()?;
+        let starred = stream.items.iter().any(|item| {
+            item.categories
+                .iter()
+                .any(|category| is_state(category, STARRED))
+        });
+        match starred {
+            true => self.edit_tag(&[id], None, Some(STARRED)),
+            false => self.edit_tag(&[id], Some(STARRED), None),
+        }
+    }
+}
+
+impl Item {
+    /// Maps the item into an entry, `None` if its id can't be understood
+    fn to_entry(&self) -> Option<Entry> {
+        let id = parse_item_id(&self.id)?;
+        let url = self
+            .canonical
+            .first()
+            .or(self.alternate.first())
+            .map(|link| link.href.clone())
+            .unwrap_or_default();
+        let category = self.categories.iter().find_map(|category| {
+            let (_, label) = category.split_once("/label/")?;
+            Some(Category {
+                id: stable_id(category),
+                title: label.to_string(),
+            })
+        });
+        Some(Entry {
+            id,
+            title: self.title.clone(),
+            author: self.author.clone(),
+            // Item ids are never reused, which is all the hash is needed for
+            hash: self.id.clone(),
+            feed: Feed {
+                id: stable_id(&self.origin.stream_id),
+                title: self.origin.title.clone(),
+                category,
+            },
+            url,
+            published_at: DateTime::from_timestamp(self.published, 0).unwrap_or_default(),
+            created_at: None,
+            reading_time: None,
+            content: self
+                .content
+                .as_ref()
+                .or(self.summary.as_ref())
+                .map(|content| content.content.clone())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Parses an item id, which is either in its long form or a plain decimal number
+fn parse_item_id(id: &str) -> Option<u64> {
+    match id.strip_prefix(ITEM_ID_PREFIX) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => id.parse().ok(),
+    }
+}
+
+fn item_id(id: u64) -> String {
+    format!("{}{:016x}", ITEM_ID_PREFIX, id)
+}
+
+/// Whether the category is the given state, for any user rather than only `-`
+fn is_state(category: &str, state: &str) -> bool {
+    let state = state.trim_start_matches("user/-/");
+    category
+        .strip_prefix("user/")
+        .and_then(|category| category.split_once('/'))
+        .is_some_and(|(_user, category)| category == state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn item_ids_in_either_form() {
+        assert_eq!(
+            parse_item_id("tag:google.com,2005:reader/item/00000000000004d2"),
+            Some(1234)
+        );
+        assert_eq!(
+            parse_item_id("tag:google.com,2005:reader/item/4d2"),
+            Some(1234)
+        );
+        assert_eq!(parse_item_id("1234"), Some(1234));
+        assert_eq!(parse_item_id(&item_id(u64::MAX)), Some(u64::MAX));
+        assert_eq!(parse_item_id("tag:google.com,2005:reader/item/xyz"), None);
+        assert_eq!(parse_item_id("-1"), None);
+        assert_eq!(parse_item_id(""), None);
+    }
+
+    #[test]
+    fn states_for_any_user() {
+        assert!(is_state("user/-/state/com.google/starred", STARRED));
+        assert!(is_state("user/1234/state/com.google/starred", STARRED));
+        assert!(is_state("user/1234/state/com.google/read", READ));
+        assert!(!is_state("user/1234/state/com.google/read", STARRED));
+        assert!(!is_state("user/1234/state/com.google/reading-list", READ));
+        assert!(!is_state(
+            "user/1234/label/state/com.google/starred",
+            STARRED
+        ));
+        assert!(!is_state("state/com.google/starred", STARRED));
+    }
+}
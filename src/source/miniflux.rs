@@ -0,0 +1,357 @@
+//! A client for the parts of the Miniflux API used by the notifier
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::{send, send_via, Error, FeedSource, Scope};
+use crate::config::Settings;
+use crate::models::{Category, Entries, FeedDetails, FeedIcon, User};
+use crate::unix_socket::UnixSocket;
+
+/// A Miniflux server and the credentials to access it with.
+///
+/// Cloning is cheap and shares the underlying connection pool.
+#[derive(Debug, Clone)]
+pub struct Miniflux {
+    http: Client,
+    server: String,
+    entries_path: String,
+    healthcheck_path: String,
+    page_size: u32,
+    /// Where the requests are sent instead of the server, if it listens on a Unix socket
+    socket: Option<UnixSocket>,
+    /// The unread entries last fetched, of all feeds or of a scope, for asking the server
+    /// whether they have changed
+    cache: Arc<Mutex<HashMap<Option<Scope>, CachedEntries>>>,
+}
+
+/// Unread entries along with what the server said identifies their version
+#[derive(Debug)]
+struct CachedEntries {
+    url: String,
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+    entries: Entries,
+}
+
+impl Miniflux {
+    pub fn new(http: Client, settings: &Settings) -> Self {
+        Self {
+            http,
+            server: settings.server.trim_end_matches('/').to_string(),
+            entries_path: settings.entries_path.clone(),
+            healthcheck_path: settings.healthcheck_path.clone(),
+            page_size: settings.page_size,
+            socket: None,
+            cache: Arc::default(),
+        }
+    }
+
+    /// Sends the requests to the Unix socket the server listens on rather than to its URL
+    pub fn via(mut self, socket: UnixSocket) -> Self {
+        self.socket = Some(socket);
+        self
+    }
+
+    fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        match &self.socket {
+            Some(socket) => send_via(request, |request| socket.send(request)),
+            None => send(request),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.server, path)
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, Error> {
+        Ok(self.send(self.http.get(url))?.json()?)
+    }
+
+    /// Follows the pagination of the API one page at a time
+    ///
+    /// The first page is requested conditionally if the server sent an ETag or Last-Modified
+    /// header before. New entries show up on the first page and entries being read change the
+    /// total on it, so if the first page is unchanged, so are the others.
+    fn fetch_unread(
+        &self,
+        scope: Option<Scope>,
+        published_after: Option<i64>,
+    ) -> Result<Entries, Error> {
+        let path = match scope {
+            Some(Scope::Feed(id)) => feed_entries_path(&self.entries_path, id),
+            _ => self.entries_path.clone(),
+        };
+        let mut url = format!(
+            "{}?status=unread&direction=desc&limit={}",
+            self.url(&path),
+            self.page_size
+        );
+        if let Some(Scope::Category(id)) = scope {
+            url.push_str(&format!("&category_id={}", id));
+        }
+        if let Some(published_after) = published_after {
+            url.push_str(&format!("&published_after={}", published_after));
+        }
+
+        // The lock is only held while looking at the cache, not while waiting on the server
+        let validators = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(&scope)
+            .filter(|cached| cached.url == url)
+            .map(|cached| (cached.etag.clone(), cached.last_modified.clone()));
+        let first_page = format!("{}&offset=0", url);
+        let mut request = self.http.get(&first_page);
+        if let Some((etag, last_modified)) = validators {
+            if let Some(etag) = etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let mut response = self.send(request)?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            let cache = self.cache.lock().unwrap();
+            if let Some(cached) = cache.get(&scope).filter(|cached| cached.url == url) {
+                return Ok(Entries {
+                    not_modified: true,
+                    ..cached.entries.clone()
+                });
+            }
+            drop(cache);
+            // Nothing was cached to stand in for the body, so the entries are asked for again
+            // without conditions
+            response = self.send(self.http.get(&first_page))?;
+        }
+        let etag = response.headers().get(ETAG).cloned();
+        let last_modified = response.headers().get(LAST_MODIFIED).cloned();
+
+        let mut entries: Entries = response.json()?;
+        let mut page_len = entries.entries.len();
+        while page_len >= self.page_size as usize && entries.entries.len() < entries.total as usize
+        {
+            let page: Entries =
+                self.get_json(&format!("{}&offset={}", url, entries.entries.len()))?;
+            page_len = page.entries.len();
+            entries.total = page.total;
+            entries.entries.extend(page.entries);
+        }
+
+        if etag.is_some() || last_modified.is_some() {
+            let cached = CachedEntries {
+                url,
+                etag,
+                last_modified,
+                entries: entries.clone(),
+            };
+            self.cache.lock().unwrap().insert(scope, cached);
+        } else {
+            self.cache.lock().unwrap().remove(&scope);
+        }
+        Ok(entries)
+    }
+}
+
+impl FeedSource for Miniflux {
+    fn healthcheck(&self) -> Result<(), Error> {
+        self.send(self.http.get(self.url(&self.healthcheck_path)))?;
+        Ok(())
+    }
+
+    fn me(&self) -> Result<User, Error> {
+        self.get_json(&self.url("/v1/me"))
+    }
+
+    fn unread_entries(&self, published_after: Option<i64>) -> Result<Entries, Error> {
+        self.fetch_unread(None, published_after)
+    }
+
+    fn mark_read(&self, ids: &[u64]) -> Result<(), Error> {
+        self.send(
+            self.http
+                .put(self.url(&self.entries_path))
+                .json(&serde_json::json!({ "entry_ids": ids, "status": "read" })),
+        )?;
+        Ok(())
+    }
+
+    fn toggle_bookmark(&self, id: u64) -> Result<(), Error> {
+        self.send(
+            self.http
+                .put(self.url(&format!("{}/{}/bookmark", self.entries_path, id))),
+        )?;
+        Ok(())
+    }
+
+    fn fetch_content(&self, id: u64) -> Result<bool, Error> {
+        self.send(self.http.get(self.url(&format!(
+            "{}/{}/fetch-content?update_content=true",
+            self.entries_path, id
+        ))))?;
+        Ok(true)
+    }
+
+    fn save_entry(&self, id: u64) -> Result<bool, Error> {
+        self.send(
+            self.http
+                .post(self.url(&format!("{}/{}/save", self.entries_path, id))),
+        )?;
+        Ok(true)
+    }
+
+    fn feed_icon(&self, feed_id: u32) -> Result<Option<FeedIcon>, Error> {
+        match self.get_json(&self.url(&format!("/v1/feeds/{}/icon", feed_id))) {
+            Ok(icon) => Ok(Some(icon)),
+            Err(Error::Status(StatusCode::NOT_FOUND)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn feeds(&self) -> Result<Option<Vec<FeedDetails>>, Error> {
+        Ok(Some(self.get_json(&self.url("/v1/feeds"))?))
+    }
+
+    fn categories(&self) -> Result<Option<Vec<Category>>, Error> {
+        Ok(Some(self.get_json(&self.url("/v1/categories"))?))
+    }
+
+    fn scoped_unread_entries(
+        &self,
+        scope: Scope,
+        published_after: Option<i64>,
+    ) -> Result<Option<Entries>, Error> {
+        self.fetch_unread(Some(scope), published_after).map(Some)
+    }
+}
+
+/// The entries endpoint of a single feed, which sits under the same base as the endpoint of
+/// all entries, e.g. "/v1/feeds/3/entries" for "/v1/entries"
+fn feed_entries_path(entries_path: &str, feed_id: u32) -> String {
+    let (base, endpoint) = entries_path.rsplit_once('/').unwrap_or(("", entries_path));
+    format!("{}/feeds/{}/{}", base, feed_id, endpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_settings;
+
+    fn client(args: &[&str]) -> Miniflux {
+        Miniflux::new(Client::new(), &test_settings(args))
+    }
+
+    #[test]
+    fn default_paths() {
+        let client = client(&[]);
+        assert_eq!(
+            client.url(&client.entries_path),
+            "http://localhost/v1/entries"
+        );
+        assert_eq!(
+            client.url(&client.healthcheck_path),
+            "http://localhost/healthcheck"
+        );
+        assert_eq!(
+            feed_entries_path(&client.entries_path, 3),
+            "/v1/feeds/3/entries"
+        );
+    }
+
+    #[test]
+    fn overridden_paths() {
+        let client = client(&[
+            "--entries-path",
+            "/proxy/v1/entries/",
+            "--healthcheck-path",
+            "/proxy/healthcheck",
+        ]);
+        assert_eq!(
+            client.url(&client.entries_path),
+            "http://localhost/proxy/v1/entries"
+        );
+        assert_eq!(
+            client.url(&client.healthcheck_path),
+            "http://localhost/proxy/healthcheck"
+        );
+        assert_eq!(
+            feed_entries_path(&client.entries_path, 3),
+            "/proxy/v1/feeds/3/entries"
+        );
+    }
+
+    /// Answers a request over a Unix socket with each of the responses, returning the
+    /// requests that were received
+    #[cfg(unix)]
+    fn serve(
+        name: &str,
+        responses: &'static [&'static str],
+    ) -> (Miniflux, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixListener;
+
+        let dir =
+            std::env::temp_dir().join(format!("miniflux-notifier-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("miniflux.sock");
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![0; 4096];
+                let len = stream.read(&mut request).unwrap();
+                requests.push(String::from_utf8_lossy(&request[..len]).to_string());
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            std::fs::remove_dir_all(dir).unwrap();
+            requests
+        });
+        let socket =
+            UnixSocket::new(&path, Default::default(), std::time::Duration::from_secs(5)).unwrap();
+        (client(&[]).via(socket), server)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unchanged_entries_come_from_the_cache() {
+        let (client, server) = serve(
+            "cached",
+            &[
+                "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 27\r\n\r\n\
+                 {\"total\": 0, \"entries\": []}",
+                "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n",
+            ],
+        );
+        assert!(!client.fetch_unread(None, None).unwrap().not_modified);
+        assert!(client.fetch_unread(None, None).unwrap().not_modified);
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].to_lowercase().contains("if-none-match"));
+        assert!(requests[1]
+            .to_lowercase()
+            .contains("if-none-match: \"v1\"\r\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn not_modified_without_a_cache_asks_again() {
+        let (client, server) = serve(
+            "uncached",
+            &[
+                "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Length: 27\r\n\r\n{\"total\": 0, \"entries\": []}",
+            ],
+        );
+        let entries = client.fetch_unread(None, None).unwrap();
+        assert!(!entries.not_modified);
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,338 @@
+//! Where entries come from: a Miniflux server, or any other server speaking an API the
+//! notifier knows
+//!
+//! Every source maps the entries it fetches into [`models::Entry`](crate::models::Entry), so
+//! that everything after fetching works the same regardless of the source.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::client;
+use crate::config::{Auth, Settings};
+use crate::models::{Category, Entries, Entry, FeedDetails, FeedIcon, User};
+use crate::telemetry::{self, Span, SpanKind};
+use crate::unix_socket::UnixSocket;
+
+pub mod greader;
+pub mod miniflux;
+pub mod ttrss;
+
+/// The kinds of servers entries can be fetched from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// The Miniflux API
+    Miniflux,
+    /// The Google Reader API, as implemented by FreshRSS and The Old Reader among others
+    Greader,
+    /// The API of Tiny Tiny RSS
+    Ttrss,
+}
+
+/// A server the unread entries are fetched from, which can also change their status.
+///
+/// Sources are shared with the threads handling notification actions, so they have to be
+/// usable from several threads at once.
+pub trait FeedSource: Send + Sync {
+    /// Checks that the server is up, which doesn't need any credentials
+    fn healthcheck(&self) -> Result<(), Error>;
+
+    /// Gets the user the credentials belong to, which fails if they are invalid
+    fn me(&self) -> Result<User, Error>;
+
+    /// Gets all unread entries, newest first
+    ///
+    /// With `published_after` (seconds since the unix epoch) only entries published after
+    /// that time are returned.
+    fn unread_entries(&self, published_after: Option<i64>) -> Result<Entries, Error>;
+
+    /// Marks the entries as read
+    fn mark_read(&self, ids: &[u64]) -> Result<(), Error>;
+
+    /// Toggles whether an entry is starred
+    fn toggle_bookmark(&self, id: u64) -> Result<(), Error>;
+
+    /// Has the server fetch the full content of an entry from its original site, for feeds that
+    /// only carry a summary. `false` if the source can't.
+    fn fetch_content(&self, _id: u64) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    /// Saves an entry to the third-party services, such as Wallabag or Pocket, set up on the
+    /// server. `false` if the source can't.
+    fn save_entry(&self, _id: u64) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    /// Gets the icon of a feed, `None` if it has none or the source doesn't provide icons
+    fn feed_icon(&self, _feed_id: u32) -> Result<Option<FeedIcon>, Error> {
+        Ok(None)
+    }
+
+    /// Gets all feeds along with whether fetching them fails, `None` if the source doesn't
+    /// tell
+    fn feeds(&self) -> Result<Option<Vec<FeedDetails>>, Error> {
+        Ok(None)
+    }
+
+    /// Gets all categories, `None` if the source doesn't provide them
+    fn categories(&self) -> Result<Option<Vec<Category>>, Error> {
+        Ok(None)
+    }
+
+    /// Gets the unread entries of a single feed or category like
+    /// [`unread_entries`](Self::unread_entries), `None` if the source can't narrow them down
+    fn scoped_unread_entries(
+        &self,
+        _scope: Scope,
+        _published_after: Option<i64>,
+    ) -> Result<Option<Entries>, Error> {
+        Ok(None)
+    }
+}
+
+/// A feed or category whose unread entries are fetched on their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    Feed(u32),
+    Category(u32),
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scope::Feed(id) => write!(f, "feed {}", id),
+            Scope::Category(id) => write!(f, "category {}", id),
+        }
+    }
+}
+
+impl Scope {
+    pub fn contains(&self, entry: &Entry) -> bool {
+        match self {
+            Scope::Feed(id) => entry.feed.id == *id,
+            Scope::Category(id) => entry
+                .feed
+                .category
+                .as_ref()
+                .is_some_and(|category| category.id == *id),
+        }
+    }
+}
+
+/// Sets up the source selected in the settings
+pub fn from_settings(settings: &Settings) -> Result<Arc<dyn FeedSource>> {
+    Ok(match settings.source {
+        SourceKind::Miniflux => {
+            let mut miniflux = miniflux::Miniflux::new(client::build(settings)?, settings);
+            if let Some(path) = &settings.unix_socket {
+                let headers = client::server_headers(settings)?;
+                miniflux = miniflux.via(UnixSocket::new(path, headers, settings.timeout)?);
+            }
+            Arc::new(miniflux)
+        }
+        _ if settings.unix_socket.is_some() => {
+            return Err(anyhow!("--unix-socket is only supported with Miniflux"))
+        }
+        SourceKind::Greader => {
+            let (username, password) = login_credentials(settings, "greader")?;
+            Arc::new(greader::GoogleReader::new(
+                client::build_login(settings)?,
+                settings,
+                username,
+                password,
+            ))
+        }
+        SourceKind::Ttrss => {
+            let (username, password) = login_credentials(settings, "ttrss")?;
+            Arc::new(ttrss::TinyTinyRss::new(
+                client::build_login(settings)?,
+                settings,
+                username,
+                password,
+            ))
+        }
+    })
+}
+
+/// The username and password for sources that log in with them rather than an API key
+fn login_credentials<'a>(settings: &'a Settings, source: &str) -> Result<(&'a str, &'a str)> {
+    match &settings.auth {
+        Auth::Basic { username, password } => Ok((username, password)),
+        Auth::ApiKey(_) => Err(anyhow!(
+            "The {} source needs a --username and --password instead of an API key",
+            source
+        )),
+    }
+}
+
+/// The ways a request to a source can fail
+#[derive(Debug)]
+pub enum Error {
+    /// The server rejected the credentials
+    Auth(StatusCode),
+    /// The server answered with any other error status
+    Status(StatusCode),
+    /// The server is overloaded or rate limiting the notifier, possibly saying how long to
+    /// wait before trying again
+    Throttled {
+        status: StatusCode,
+        retry_after: Option<Duration>,
+    },
+    /// The server could not be reached, or the connection broke off
+    Network(reqwest::Error),
+    /// The Unix socket of the server could not be reached, or the server's answer through it
+    /// was cut off or malformed
+    Socket(io::Error),
+    /// The server answered with something that isn't what the API should return
+    Decode(reqwest::Error),
+    /// The server answered with an error of the API itself, for APIs that don't use statuses
+    Api(String),
+}
+
+impl Error {
+    pub fn is_auth(&self) -> bool {
+        matches!(self, Error::Auth(_))
+    }
+
+    /// Whether the error is likely to go away by itself, i.e. a network error or a server
+    /// error
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Network(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            Error::Status(status) => status.is_server_error(),
+            Error::Throttled { .. } => true,
+            Error::Socket(e) => e.kind() != io::ErrorKind::InvalidData,
+            Error::Auth(_) | Error::Decode(_) | Error::Api(_) => false,
+        }
+    }
+
+    /// How long the server asked to wait before trying again
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::Throttled { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Auth(status) => write!(f, "the server rejected the credentials ({})", status),
+            Error::Status(status) | Error::Throttled { status, .. } => {
+                write!(f, "the server responded with {}", status)
+            }
+            Error::Network(e) => write!(f, "failed to reach the server: {}", e),
+            Error::Socket(e) => write!(f, "failed to reach the server's socket: {}", e),
+            Error::Decode(e) => write!(f, "failed to decode the server's response: {}", e),
+            Error::Api(error) => write!(f, "the server responded with the error {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network(e) | Error::Decode(e) => Some(e),
+            Error::Socket(e) => Some(e),
+            Error::Auth(_) | Error::Status(_) | Error::Throttled { .. } | Error::Api(_) => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            Error::Decode(e)
+        } else {
+            Error::Network(e)
+        }
+    }
+}
+
+/// Sends the request, turning error statuses into errors
+pub(crate) fn send(request: RequestBuilder) -> Result<Response, Error> {
+    send_via(request, |request| request.send().map_err(Error::from))
+}
+
+/// Sends the request like [`send`], but through `transport` rather than the HTTP client
+pub(crate) fn send_via(
+    request: RequestBuilder,
+    transport: impl FnOnce(RequestBuilder) -> Result<Response, Error>,
+) -> Result<Response, Error> {
+    let mut span = telemetry::enabled().then(|| request_span(&request));
+    let result = transport(request).and_then(|response| {
+        if let Some(span) = &mut span {
+            span.set_attribute("http.response.status_code", response.status().as_u16());
+        }
+        match response.status() {
+            status if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN => {
+                Err(Error::Auth(status))
+            }
+            status
+                if status == StatusCode::TOO_MANY_REQUESTS
+                    || status == StatusCode::SERVICE_UNAVAILABLE =>
+            {
+                Err(Error::Throttled {
+                    status,
+                    retry_after: retry_after(&response),
+                })
+            }
+            status if status.is_client_error() || status.is_server_error() => {
+                Err(Error::Status(status))
+            }
+            _ => Ok(response),
+        }
+    });
+    if let (Some(span), Err(e)) = (&mut span, &result) {
+        span.set_error(e);
+    }
+    result
+}
+
+/// Reads the Retry-After header, which is either a number of seconds or a date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means right away
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// A span for a request to the server, named after its method
+fn request_span(request: &RequestBuilder) -> Span {
+    let Some(Ok(request)) = request.try_clone().map(RequestBuilder::build) else {
+        return telemetry::span("HTTP", SpanKind::Client);
+    };
+    let mut span = telemetry::span(format!("HTTP {}", request.method()), SpanKind::Client);
+    span.set_attribute("http.request.method", request.method().as_str());
+    // The query can hold credentials, such as the API key of Tiny Tiny RSS
+    let mut url = request.url().clone();
+    url.set_query(None);
+    span.set_attribute("url.full", url.as_str());
+    span
+}
+
+/// Derives a numeric id from a textual one, for sources that identify feeds and categories by
+/// name. The same text always gives the same id.
+pub fn stable_id(text: &str) -> u32 {
+    let digest = Sha256::digest(text.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
@@ -0,0 +1,389 @@
+//! A client for the JSON API of Tiny Tiny RSS
+//!
+//! The server URL is where tt-rss is installed, e.g. `https://example.org/tt-rss`, the API
+//! itself is below it at `/api/`. The API has to be enabled in the preferences of the user.
+
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use super::{send, Error, FeedSource};
+use crate::config::Settings;
+use crate::models::{Category, Entries, Entry, Feed, User};
+
+/// The virtual feed containing the articles of all feeds
+const ALL_ARTICLES: i64 = -4;
+
+/// The virtual category containing all feeds, without the virtual ones
+const ALL_FEEDS: i64 = -3;
+
+/// The most headlines the API returns at once
+const MAX_PAGE_SIZE: u32 = 200;
+
+/// A Tiny Tiny RSS installation and the credentials to log in with.
+///
+/// Requests are made within a session, which is started by logging in when the first request
+/// is made, and again whenever the session has expired.
+#[derive(Debug)]
+pub struct TinyTinyRss {
+    http: Client,
+    api: String,
+    username: String,
+    password: String,
+    page_size: u32,
+    session_id: Mutex<Option<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T> {
+    status: i32,
+    content: Content<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Content<T> {
+    Error { error: String },
+    Ok(T),
+}
+
+#[derive(Debug, Deserialize)]
+struct Session {
+    session_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Headline {
+    id: u64,
+    #[serde(default)]
+    guid: String,
+    title: String,
+    link: String,
+    #[serde(default)]
+    author: String,
+    updated: i64,
+    #[serde(deserialize_with = "number_or_string")]
+    feed_id: u32,
+    #[serde(default)]
+    feed_title: String,
+    #[serde(default)]
+    content: String,
+}
+
+impl Headline {
+    fn into_entry(self, categories: &HashMap<u32, Category>) -> Entry {
+        Entry {
+            id: self.id,
+            title: self.title,
+            author: self.author,
+            hash: match self.guid.is_empty() {
+                true => self.id.to_string(),
+                false => self.guid,
+            },
+            feed: Feed {
+                id: self.feed_id,
+                title: self.feed_title,
+                category: categories.get(&self.feed_id).cloned(),
+            },
+            url: self.link,
+            published_at: chrono::DateTime::from_timestamp(self.updated, 0).unwrap_or_default(),
+            created_at: None,
+            reading_time: None,
+            content: self.content,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TtrssFeed {
+    #[serde(deserialize_with = "number_or_string")]
+    id: u32,
+    #[serde(deserialize_with = "number_or_string")]
+    cat_id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct TtrssCategory {
+    #[serde(deserialize_with = "number_or_string")]
+    id: u32,
+    title: String,
+}
+
+impl TinyTinyRss {
+    pub fn new(http: Client, settings: &Settings, username: &str, password: &str) -> Self {
+        Self {
+            http,
+            api: format!("{}/api/", settings.server.trim_end_matches('/')),
+            username: username.to_string(),
+            password: password.to_string(),
+            page_size: settings.page_size.min(MAX_PAGE_SIZE),
+            session_id: Mutex::new(None),
+        }
+    }
+
+    /// Sends a request to the API, which answers every request with 200 and reports errors in
+    /// the payload instead
+    fn request<T: DeserializeOwned>(&self, body: &Value) -> Result<T, Error> {
+        let response: ApiResponse<T> = send(self.http.post(&self.api).json(body))?.json()?;
+        match response.content {
+            Content::Ok(content) if response.status == 0 => Ok(content),
+            Content::Error { error } if error == "LOGIN_ERROR" || error == "NOT_LOGGED_IN" => {
+                Err(Error::Auth(StatusCode::UNAUTHORIZED))
+            }
+            Content::Error { error } => Err(Error::Api(error)),
+            Content::Ok(_) => Err(Error::Api(format!("status {}", response.status))),
+        }
+    }
+
+    /// Calls an operation of the API within the session, logging in first if there is no
+    /// session yet
+    fn call<T: DeserializeOwned>(&self, op: &str, mut params: Value) -> Result<T, Error> {
+        let session_id = {
+            let mut session_id = self.session_id.lock().unwrap();
+            match &*session_id {
+                Some(session_id) => session_id.clone(),
+                None => {
+                    let session: Session = self.request(&json!({
+                        "op": "login",
+                        "user": self.username,
+                        "password": self.password,
+                    }))?;
+                    session_id.insert(session.session_id).clone()
+                }
+            }
+        };
+        params["op"] = op.into();
+        params["sid"] = session_id.into();
+        let result = self.request(&params);
+        if let Err(Error::Auth(_)) = result {
+            // The session may have expired, so log in again on the next request
+            *self.session_id.lock().unwrap() = None;
+        }
+        result
+    }
+
+    /// Maps the id of each feed to its category, as the headlines only carry the feed
+    fn categories_by_feed(&self) -> Result<HashMap<u32, Category>, Error> {
+        let categories: Vec<TtrssCategory> = self.call("getCategories", json!({}))?;
+        let categories: HashMap<u32, String> = categories
+            .into_iter()
+            .map(|category| (category.id, category.title))
+            .collect();
+        let feeds: Vec<TtrssFeed> = self.call("getFeeds", json!({ "cat_id": ALL_FEEDS }))?;
+        Ok(feeds
+            .into_iter()
+            // Category 0 holds the feeds without a category
+            .filter(|feed| feed.cat_id != 0)
+            .filter_map(|feed| {
+                let title = categories.get(&feed.cat_id)?.clone();
+                Some((
+                    feed.id,
+                    Category {
+                        id: feed.cat_id,
+                        title,
+                    },
+                ))
+            })
+            .collect())
+    }
+
+    /// The parameters of `getHeadlines` for the page of unread headlines after the first `skip`
+    fn headlines_params(&self, skip: usize) -> Value {
+        json!({
+            "feed_id": ALL_ARTICLES,
+            "view_mode": "unread",
+            "limit": self.page_size,
+            "skip": skip,
+            "show_content": true,
+        })
+    }
+
+    /// Sets a field of the articles, with mode 0 clearing it, 1 setting it and 2 toggling it
+    fn update_articles(&self, ids: &[u64], field: u8, mode: u8) -> Result<(), Error> {
+        let ids: Vec<String> = ids.iter().map(u64::to_string).collect();
+        self.call::<IgnoredAny>(
+            "updateArticle",
+            json!({ "article_ids": ids.join(","), "field": field, "mode": mode }),
+        )?;
+        Ok(())
+    }
+}
+
+impl FeedSource for TinyTinyRss {
+    fn healthcheck(&self) -> Result<(), Error> {
+        self.request::<IgnoredAny>(&json!({ "op": "isLoggedIn" }))?;
+        Ok(())
+    }
+
+    /// The API doesn't say who is logged in, so this only checks that logging in works
+    fn me(&self) -> Result<User, Error> {
+        self.call::<IgnoredAny>("getApiLevel", json!({}))?;
+        Ok(User {
+            id: 0,
+            username: self.username.clone(),
+        })
+    }
+
+    /// Pages through the headlines of all feeds. The API can't filter by time, so entries
+    /// published too early are dropped afterwards.
+    fn unread_entries(&self, published_after: Option<i64>) -> Result<Entries, Error> {
+        let categories = self.categories_by_feed()?;
+
+        let mut headlines: Vec<Headline> = vec![];
+        loop {
+            let page: Vec<Headline> =
+                self.call("getHeadlines", self.headlines_params(headlines.len()))?;
+            // A short page is the last one, as the page size is within what the API returns
+            let page_len = page.len();
+            headlines.extend(page);
+            if page_len < self.page_size as usize {
+                break;
+            }
+        }
+
+        let entries: Vec<Entry> = headlines
+            .into_iter()
+            .filter(|headline| published_after.is_none_or(|after| headline.updated > after))
+            .map(|headline| headline.into_entry(&categories))
+            .collect();
+        Ok(Entries {
+            total: entries.len() as u32,
+            entries,
+            not_modified: false,
+        })
+    }
+
+    fn mark_read(&self, ids: &[u64]) -> Result<(), Error> {
+        // Field 2 is whether the article is unread
+        self.update_articles(ids, 2, 0)
+    }
+
+    fn toggle_bookmark(&self, id: u64) -> Result<(), Error> {
+        // Field 0 is whether the article is starred
+        self.update_articles(&[id], 0, 2)
+    }
+}
+
+/// Deserializes a number that older versions of tt-rss send as a string
+fn number_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString<T> {
+        Number(T),
+        String(String),
+    }
+
+    match NumberOrString::<T>::deserialize(deserializer)? {
+        NumberOrString::Number(number) => Ok(number),
+        NumberOrString::String(string) => string
+            .parse()
+            .map_err(|_| serde::de::Error::custom(format!("invalid number {:?}", string))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_settings;
+
+    fn client(args: &[&str]) -> TinyTinyRss {
+        let mut settings = test_settings(args);
+        settings.server = "https://example.org/tt-rss/".to_string();
+        TinyTinyRss::new(Client::new(), &settings, "user", "password")
+    }
+
+    #[test]
+    fn pages_are_within_what_the_api_returns() {
+        let capped = client(&["--page-size", "500"]);
+        assert_eq!(capped.api, "https://example.org/tt-rss/api/");
+        assert_eq!(capped.page_size, MAX_PAGE_SIZE);
+        assert_eq!(
+            capped.headlines_params(400),
+            json!({
+                "feed_id": -4,
+                "view_mode": "unread",
+                "limit": 200,
+                "skip": 400,
+                "show_content": true,
+            })
+        );
+        assert_eq!(
+            client(&["--page-size", "50"]).headlines_params(0)["limit"],
+            50
+        );
+    }
+
+    #[test]
+    fn headlines_become_entries() {
+        // Older versions send the ids of feeds as strings
+        let response: ApiResponse<Vec<Headline>> = serde_json::from_value(json!({
+            "seq": 0,
+            "status": 0,
+            "content": [
+                {
+                    "id": 7,
+                    "guid": "SHA1:abc",
+                    "title": "Title",
+                    "link": "https://example.com/7",
+                    "author": "Jo",
+                    "updated": 1700000000,
+                    "feed_id": "3",
+                    "feed_title": "Feed",
+                    "content": "<p>Content</p>",
+                },
+                {
+                    "id": 8,
+                    "title": "Uncategorized",
+                    "link": "https://example.com/8",
+                    "updated": 1700000001,
+                    "feed_id": 4,
+                },
+            ],
+        }))
+        .unwrap();
+        let Content::Ok(headlines) = response.content else {
+            panic!("expected headlines");
+        };
+        let categories = HashMap::from([(
+            3,
+            Category {
+                id: 2,
+                title: "News".to_string(),
+            },
+        )]);
+        let entries: Vec<Entry> = headlines
+            .into_iter()
+            .map(|headline| headline.into_entry(&categories))
+            .collect();
+
+        assert_eq!(entries[0].hash, "SHA1:abc");
+        assert_eq!(entries[0].feed.id, 3);
+        assert_eq!(entries[0].feed.category.as_ref().unwrap().title, "News");
+        assert_eq!(entries[0].published_at.timestamp(), 1700000000);
+        assert_eq!(entries[0].content, "<p>Content</p>");
+        // Without a GUID the id stands in for it
+        assert_eq!(entries[1].hash, "8");
+        assert!(entries[1].feed.category.is_none());
+        assert_eq!(entries[1].author, "");
+    }
+
+    #[test]
+    fn api_errors() {
+        let response: ApiResponse<Vec<Headline>> = serde_json::from_value(json!({
+            "seq": 0,
+            "status": 1,
+            "content": { "error": "NOT_LOGGED_IN" },
+        }))
+        .unwrap();
+        assert!(matches!(response.content, Content::Error { error } if error == "NOT_LOGGED_IN"));
+    }
+}
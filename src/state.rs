@@ -0,0 +1,458 @@
+//! State that is persisted to disk so that it survives restarts of the notifier
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::Settings;
+use crate::history::{History, NotifiedEntry};
+use crate::models::Entry;
+use crate::snooze::{SnoozedEntry, Snoozes};
+
+/// Everything that is persisted between runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    /// The entries that were unread as of the last poll, `None` if there hasn't been one yet
+    ///
+    /// Keeping these around means entries that arrive while the notifier isn't running are
+    /// still notified about on the next start.
+    pub last_entries: Option<Vec<SeenEntry>>,
+    pub seen_urls: SeenUrls,
+    /// New entries held back during quiet hours
+    pub queued_entries: Vec<Entry>,
+    /// When the newest entry seen so far was published, in seconds since the unix epoch
+    pub newest_published_at: Option<i64>,
+    pub snoozed_entries: Vec<SnoozedEntry>,
+    /// The feeds that Miniflux failing to fetch has been notified about, until they recover
+    pub failing_feeds: Vec<u32>,
+    /// Whether the number of unread entries being past the threshold has been notified
+    /// about, until it drops below it again
+    pub unread_nudged: bool,
+    /// The entries notified about, oldest first
+    pub history: Vec<NotifiedEntry>,
+}
+
+/// The parts of an entry needed to recognize it on a later poll
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeenEntry {
+    pub id: u64,
+    pub hash: String,
+}
+
+impl From<&Entry> for SeenEntry {
+    fn from(entry: &Entry) -> Self {
+        Self {
+            id: entry.id,
+            hash: entry.hash.clone(),
+        }
+    }
+}
+
+impl State {
+    /// Load the state from `path`, starting out empty if the file does not exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read state file {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse state file {}", path.display()))
+    }
+
+    /// Write the state to `path`, creating any missing parent directories
+    ///
+    /// The file is written to a temporary path first and then renamed so that a crash
+    /// mid-write can't leave a truncated state file behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create state directory {}", parent.display())
+            })?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string(self)?)
+            .with_context(|| format!("Failed to write state file {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to write state file {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// The URLs and titles of entries we have already sent a notification for.
+///
+/// When a feed is removed and re-added, Miniflux assigns new ids and hashes to the same
+/// articles, so the cache diff alone would notify about all of them again. The URL of an
+/// article is the one thing that stays stable across a re-subscription, and along with the
+/// title it also gives away the same article reposted by several feeds.
+///
+/// URLs are kept in the order they were last seen so that the oldest ones can be dropped
+/// first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(from = "StoredSeenUrls")]
+pub struct SeenUrls {
+    urls: VecDeque<SeenUrl>,
+    /// When each of the URLs was last seen, for looking them up without going through all
+    #[serde(skip)]
+    index: HashMap<String, u64>,
+}
+
+/// The seen URLs as written to the state file, without the index
+#[derive(Deserialize)]
+struct StoredSeenUrls {
+    urls: VecDeque<SeenUrl>,
+}
+
+impl From<StoredSeenUrls> for SeenUrls {
+    fn from(stored: StoredSeenUrls) -> Self {
+        let mut seen_urls = SeenUrls::default();
+        for seen in stored.urls {
+            seen_urls.insert(&seen.url, &seen.title, seen.seen_at);
+        }
+        seen_urls
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeenUrl {
+    pub url: String,
+    /// The title of the entry, empty for URLs remembered before titles were
+    #[serde(default)]
+    pub title: String,
+    /// Seconds since the unix epoch
+    pub seen_at: u64,
+}
+
+impl SeenUrls {
+    pub fn contains(&self, url: &str) -> bool {
+        self.index.contains_key(url)
+    }
+
+    /// When the URL was last seen, in seconds since the unix epoch
+    pub fn seen_at(&self, url: &str) -> Option<u64> {
+        self.index.get(url).copied()
+    }
+
+    /// Remembers the URL as seen at `now`, along with the title it was last seen with
+    pub fn insert(&mut self, url: &str, title: &str, now: u64) {
+        if self.index.insert(url.to_string(), now).is_some() {
+            if let Some(i) = self.urls.iter().position(|seen| seen.url == url) {
+                self.urls.remove(i);
+            }
+        }
+        self.urls.push_back(SeenUrl {
+            url: url.to_string(),
+            title: title.to_string(),
+            seen_at: now,
+        });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SeenUrl> {
+        self.urls.iter()
+    }
+
+    /// Forget URLs that are older than `max_age`, then the oldest URLs until at most `limit`
+    /// remain
+    pub fn prune(&mut self, limit: usize, max_age: Duration, now: u64) {
+        let cutoff = now.saturating_sub(max_age.as_secs());
+        let index = &mut self.index;
+        self.urls.retain(|seen| {
+            let keep = seen.seen_at >= cutoff;
+            if !keep {
+                index.remove(&seen.url);
+            }
+            keep
+        });
+        while self.urls.len() > limit {
+            if let Some(seen) = self.urls.pop_front() {
+                self.index.remove(&seen.url);
+            }
+        }
+    }
+}
+
+/// [`State`] backed by a state file.
+///
+/// Rewriting the whole file on every poll causes a lot of disk churn for long-running
+/// instances, so changes are only written back once `flush_interval` has passed since the
+/// last write. Every write also compacts the seen URLs, which makes sure URLs older than the
+/// maximum age are dropped even when nothing new is being notified about.
+///
+/// Without a path the state is only kept in memory.
+#[derive(Debug)]
+pub struct StateFile {
+    pub state: State,
+    /// Kept in sync with `state.snoozed_entries` whenever the state is written
+    snoozes: Snoozes,
+    /// Kept in sync with `state.history` whenever the state is written
+    history: History,
+    path: Option<PathBuf>,
+    flush_interval: Duration,
+    limit: usize,
+    max_age: Duration,
+    /// When the state was last written, in seconds since the unix epoch
+    last_flush: u64,
+    dirty: bool,
+}
+
+impl StateFile {
+    pub fn open(
+        path: Option<PathBuf>,
+        flush_interval: Duration,
+        limit: usize,
+        max_age: Duration,
+    ) -> Result<Self> {
+        let state = match &path {
+            Some(path) => State::load(path)?,
+            None => State::default(),
+        };
+        Ok(Self {
+            snoozes: Snoozes::new(state.snoozed_entries.clone()),
+            history: History::new(state.history.clone()),
+            state,
+            path,
+            flush_interval,
+            limit,
+            max_age,
+            last_flush: now(),
+            dirty: false,
+        })
+    }
+
+    /// Stops writing the state back to the state file, keeping any changes in memory only
+    pub fn keep_in_memory(&mut self) {
+        self.path = None;
+    }
+
+    /// Replaces the entries remembered from the last poll
+    pub fn set_last_entries(&mut self, entries: &[Entry]) {
+        self.state.last_entries = Some(entries.iter().map(SeenEntry::from).collect());
+        self.dirty = true;
+    }
+
+    /// Adds to the entries remembered from the last poll, for entries found by polling only
+    /// some of the feeds
+    pub fn add_last_entries(&mut self, entries: &[Entry]) {
+        if let Some(last_entries) = &mut self.state.last_entries {
+            last_entries.extend(entries.iter().map(SeenEntry::from));
+            self.dirty = true;
+        }
+    }
+
+    pub fn update_newest_published_at(&mut self, entries: &[Entry]) {
+        let newest = entries
+            .iter()
+            .map(|entry| entry.published_at.timestamp())
+            .chain(self.state.newest_published_at)
+            .max();
+        if newest != self.state.newest_published_at {
+            self.state.newest_published_at = newest;
+            self.dirty = true;
+        }
+    }
+
+    pub fn queue_entries(&mut self, entries: Vec<Entry>) {
+        if !entries.is_empty() {
+            self.state.queued_entries.extend(entries);
+            self.dirty = true;
+        }
+    }
+
+    pub fn set_failing_feeds(&mut self, feeds: Vec<u32>) {
+        if feeds != self.state.failing_feeds {
+            self.state.failing_feeds = feeds;
+            self.dirty = true;
+        }
+    }
+
+    pub fn set_unread_nudged(&mut self, nudged: bool) {
+        if nudged != self.state.unread_nudged {
+            self.state.unread_nudged = nudged;
+            self.dirty = true;
+        }
+    }
+
+    pub fn snoozes(&self) -> &Snoozes {
+        &self.snoozes
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn take_queued_entries(&mut self) -> Vec<Entry> {
+        self.dirty = true;
+        std::mem::take(&mut self.state.queued_entries)
+    }
+
+    /// Drops seen URLs that are too old or in excess of the limit
+    pub fn compact(&mut self, now: u64) {
+        let len = self.state.seen_urls.urls.len();
+        self.state.seen_urls.prune(self.limit, self.max_age, now);
+        if self.state.seen_urls.urls.len() != len {
+            self.dirty = true;
+        }
+    }
+
+    /// Marks the state as changed so it is written on the next flush
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Compacts and writes the state if the flush interval has passed since the last write
+    pub fn flush_if_due(&mut self, now: u64) -> Result<()> {
+        // A clock set back counts as the interval having passed, rather than holding off
+        // writes until it catches up
+        let elapsed = now.checked_sub(self.last_flush);
+        if elapsed.is_some_and(|elapsed| elapsed < self.flush_interval.as_secs()) {
+            return Ok(());
+        }
+        self.flush(now)
+    }
+
+    /// Compacts the state and writes it if anything changed since the last write
+    pub fn flush(&mut self, now: u64) -> Result<()> {
+        self.compact(now);
+        if let Some(snoozed_entries) = self.snoozes.take_changes() {
+            self.state.snoozed_entries = snoozed_entries;
+            self.dirty = true;
+        }
+        if let Some(history) = self.history.take_changes() {
+            self.state.history = history;
+            self.dirty = true;
+        }
+        self.last_flush = now;
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+        self.state.save(path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// The directory state files are stored in when no explicit path is given
+pub fn default_state_dir() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join("miniflux-notifier"))
+}
+
+/// The state file of the server, `--state-file` or one in the default state directory named
+/// after the server
+pub fn path_for(settings: &Settings) -> Option<PathBuf> {
+    settings.state_file.clone().or_else(|| {
+        let file_name = match &settings.name {
+            Some(name) => format!("state-{}.json", name),
+            None => "state.json".to_string(),
+        };
+        default_state_dir().map(|dir| dir.join(file_name))
+    })
+}
+
+/// The current time in seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    #[test]
+    fn prune_drops_urls_older_than_the_max_age() {
+        let mut seen_urls = SeenUrls::default();
+        seen_urls.insert("https://example.com/old", "Old", 1000);
+        seen_urls.insert("https://example.com/new", "New", 1000 + 2 * DAY);
+        seen_urls.prune(100, Duration::from_secs(DAY), 1000 + 2 * DAY + 60);
+        assert!(!seen_urls.contains("https://example.com/old"));
+        assert!(seen_urls.contains("https://example.com/new"));
+    }
+
+    #[test]
+    fn seeing_a_url_again_refreshes_it() {
+        let mut seen_urls = SeenUrls::default();
+        seen_urls.insert("https://example.com/1", "First", 1000);
+        seen_urls.insert("https://example.com/2", "Second", 1001);
+        seen_urls.insert("https://example.com/1", "First again", 1000 + 2 * DAY);
+        assert_eq!(
+            seen_urls.seen_at("https://example.com/1"),
+            Some(1000 + 2 * DAY)
+        );
+        seen_urls.prune(100, Duration::from_secs(DAY), 1000 + 2 * DAY);
+        let urls: Vec<(&str, &str)> = seen_urls
+            .iter()
+            .map(|seen| (seen.url.as_str(), seen.title.as_str()))
+            .collect();
+        assert_eq!(urls, [("https://example.com/1", "First again")]);
+        assert!(!seen_urls.contains("https://example.com/2"));
+    }
+
+    #[test]
+    fn the_index_is_rebuilt_when_loading() {
+        let mut seen_urls = SeenUrls::default();
+        seen_urls.insert("https://example.com/1", "First", 1000);
+        let json = serde_json::to_string(&seen_urls).unwrap();
+        assert_eq!(
+            json,
+            r#"{"urls":[{"url":"https://example.com/1","title":"First","seen_at":1000}]}"#
+        );
+        let loaded: SeenUrls = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.seen_at("https://example.com/1"), Some(1000));
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_urls_within_the_limit() {
+        let mut seen_urls = SeenUrls::default();
+        for i in 0..5 {
+            seen_urls.insert(&format!("https://example.com/{}", i), "", 1000 + i);
+        }
+        seen_urls.prune(2, Duration::from_secs(DAY), 1010);
+        let urls: Vec<&str> = seen_urls.iter().map(|seen| seen.url.as_str()).collect();
+        assert_eq!(urls, ["https://example.com/3", "https://example.com/4"]);
+    }
+
+    #[test]
+    fn flushes_are_coalesced() {
+        let path = std::env::temp_dir().join(format!(
+            "miniflux-notifier-test-{}-flush.json",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let mut state = StateFile::open(
+            Some(path.clone()),
+            Duration::from_secs(60),
+            100,
+            Duration::from_secs(DAY),
+        )
+        .unwrap();
+        state.flush(1000).unwrap();
+        assert!(!path.exists(), "nothing changed yet");
+
+        state.set_unread_nudged(true);
+        state.flush_if_due(1030).unwrap();
+        assert!(!path.exists(), "written before the flush interval passed");
+        state.flush_if_due(1060).unwrap();
+        assert!(State::load(&path).unwrap().unread_nudged);
+
+        // Shutting down writes whatever changed regardless of the interval
+        state.set_unread_nudged(false);
+        state.flush_if_due(1070).unwrap();
+        assert!(State::load(&path).unwrap().unread_nudged);
+        state.flush(1071).unwrap();
+        assert!(!State::load(&path).unwrap().unread_nudged);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+//! Printing the number of unread entries to stdout after each poll, in the format a status bar
+//! such as Waybar or i3blocks reads from a program it keeps running
+//!
+//! The numbers are kept for the tray icon and the launcher badge as well.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use log::error;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Mutex;
+
+use crate::config::Settings;
+use crate::models::Entries;
+
+/// What the unread entries of a server are listed under if their feed has no category
+const UNCATEGORIZED: &str = "Uncategorized";
+
+/// The unread entries of each server by category, shared by every server so that the bar
+/// shows them all together
+static UNREAD: Mutex<BTreeMap<String, Unread>> = Mutex::new(BTreeMap::new());
+
+/// The format of the lines written for a status bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusFormat {
+    /// A JSON object per line for a custom module with `"return-type": "json"`, with the
+    /// number of unread entries as the text and the categories in the tooltip
+    Waybar,
+    /// A line of text per update for a block with `interval=persist`
+    I3blocks,
+}
+
+#[derive(Debug, Default)]
+struct Unread {
+    total: u32,
+    /// The unread entries of each category, most first
+    categories: Vec<(String, u32)>,
+}
+
+/// Records the unread entries of the server and writes the line for the status bar, if there
+/// is one
+pub fn update(settings: &Settings, unread_entries: &Entries) {
+    if settings.status_format.is_none() && !settings.tray && settings.launcher_badge.is_none() {
+        return;
+    }
+    let mut counts: BTreeMap<&str, u32> = BTreeMap::new();
+    for entry in &unread_entries.entries {
+        let category = entry.feed.category.as_ref();
+        *counts
+            .entry(category.map_or(UNCATEGORIZED, |category| category.title.as_str()))
+            .or_default() += 1;
+    }
+    let mut categories: Vec<(String, u32)> = counts
+        .into_iter()
+        .map(|(title, count)| (title.to_string(), count))
+        .collect();
+    categories.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    let mut unread = UNREAD.lock().unwrap();
+    unread.insert(
+        settings.name.clone().unwrap_or(settings.server.clone()),
+        Unread {
+            total: unread_entries.total,
+            categories,
+        },
+    );
+    let line = match settings.status_format {
+        Some(StatusFormat::Waybar) => waybar_line(&unread),
+        Some(StatusFormat::I3blocks) => i3blocks_line(&unread),
+        None => return,
+    };
+    if let Err(e) = write_line(&line) {
+        error!("Failed to write the status line: {:?}", e);
+    }
+}
+
+/// The number of unread entries on all servers, along with a line for each category, `None`
+/// until a server has been polled
+pub fn unread() -> Option<(u32, Vec<String>)> {
+    let unread = UNREAD.lock().unwrap();
+    if unread.is_empty() {
+        return None;
+    }
+    Some((total(&unread), category_lines(&unread)))
+}
+
+fn total(unread: &BTreeMap<String, Unread>) -> u32 {
+    unread.values().map(|unread| unread.total).sum()
+}
+
+/// A line such as "News: 7" for each category, prefixed with the server if there are several
+fn category_lines(unread: &BTreeMap<String, Unread>) -> Vec<String> {
+    let several = unread.len() > 1;
+    unread
+        .iter()
+        .flat_map(|(server, unread)| {
+            unread
+                .categories
+                .iter()
+                .map(move |(category, count)| match several {
+                    true => format!("{} / {}: {}", server, category, count),
+                    false => format!("{}: {}", category, count),
+                })
+        })
+        .collect()
+}
+
+fn waybar_line(unread: &BTreeMap<String, Unread>) -> String {
+    let total = total(unread);
+    let tooltip = category_lines(unread).join("\n");
+    serde_json::json!({
+        "text": total.to_string(),
+        "tooltip": tooltip,
+        "class": if total > 0 { "unread" } else { "empty" },
+    })
+    .to_string()
+}
+
+fn i3blocks_line(unread: &BTreeMap<String, Unread>) -> String {
+    let total = total(unread);
+    let categories = unread
+        .values()
+        .flat_map(|unread| &unread.categories)
+        .map(|(category, count)| format!("{} {}", category, count))
+        .collect::<Vec<_>>();
+    match categories.is_empty() {
+        true => total.to_string(),
+        false => format!("{} ({})", total, categories.join(", ")),
+    }
+}
+
+/// Writes the line to stdout, flushing it right away for the bar to pick it up even though
+/// stdout is a pipe
+fn write_line(line: &str) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", line)?;
+    stdout.flush()?;
+    Ok(())
+}
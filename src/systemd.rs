@@ -0,0 +1,81 @@
+//! Telling systemd how the notifier is doing, for services of `Type=notify`
+//!
+//! Everything here does nothing unless the notifier was started by systemd with
+//! `NOTIFY_SOCKET` set.
+
+use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// When the watchdog was last pinged
+static LAST_WATCHDOG_PING: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Tells systemd that startup is complete
+pub fn ready() {
+    notify("READY=1");
+}
+
+/// Shows `status` as the status of the service in `systemctl status`
+pub fn status(status: &str) {
+    notify(&format!("STATUS={}", status));
+}
+
+/// Tells systemd that shutting down has begun
+pub fn stopping() {
+    notify("STOPPING=1");
+}
+
+/// Pings the watchdog if it is enabled and half its timeout has passed since the last ping,
+/// which is what systemd recommends. Called regularly from the main loop, so that a hung
+/// notifier gets restarted.
+pub fn watchdog() {
+    let Some(timeout) = watchdog_timeout() else {
+        return;
+    };
+    let mut last_ping = LAST_WATCHDOG_PING.lock().unwrap();
+    if last_ping.is_some_and(|last_ping| last_ping.elapsed() < timeout / 2) {
+        return;
+    }
+    notify("WATCHDOG=1");
+    *last_ping = Some(Instant::now());
+}
+
+/// The watchdog timeout systemd expects pings within, `None` if the watchdog is disabled
+fn watchdog_timeout() -> Option<Duration> {
+    // WATCHDOG_PID is only set when the watchdog is meant for a specific process
+    if let Some(pid) = env::var_os("WATCHDOG_PID") {
+        if pid.to_str() != Some(&std::process::id().to_string()) {
+            return None;
+        }
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    Some(Duration::from_micros(usec)).filter(|timeout| !timeout.is_zero())
+}
+
+#[cfg(target_os = "linux")]
+fn notify(state: &str) {
+    use log::debug;
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let result = (|| {
+        let socket = UnixDatagram::unbound()?;
+        // A leading '@' stands for a socket in the abstract namespace
+        match path.to_str().and_then(|path| path.strip_prefix('@')) {
+            Some(name) => socket.send_to_addr(
+                state.as_bytes(),
+                &SocketAddr::from_abstract_name(name.as_bytes())?,
+            ),
+            None => socket.send_to(state.as_bytes(), &path),
+        }
+    })();
+    if let Err(e) = result {
+        debug!("Failed to notify systemd of {:?}: {}", state, e);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn notify(_state: &str) {}
@@ -0,0 +1,279 @@
+//! Traces of polls and requests to the server, exported to an OpenTelemetry collector
+//!
+//! Spans are only recorded once exporting has been set up with [`init`], which requires the
+//! "otel" feature. They are sent in batches as OTLP over HTTP, encoded as JSON.
+
+use anyhow::Result;
+use rand::RngExt;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Whether spans are being recorded
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The spans that have ended and are waiting to be exported
+static FINISHED: Mutex<Vec<SpanData>> = Mutex::new(Vec::new());
+
+/// The most spans held on to, in case the collector is unreachable for a long time
+const MAX_FINISHED: usize = 10_000;
+
+thread_local! {
+    /// The spans that haven't ended yet on this thread, innermost last, as the parents of
+    /// any span started
+    static ACTIVE: RefCell<Vec<SpanContext>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SpanContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+}
+
+/// What the span stands for, in the terms of OpenTelemetry
+#[derive(Debug, Clone, Copy)]
+pub enum SpanKind {
+    Internal = 1,
+    Client = 3,
+}
+
+/// A span that lasts until it is dropped, and that is the parent of the spans started on the
+/// same thread in the meantime
+#[derive(Debug)]
+pub struct Span {
+    /// `None` if spans aren't being recorded
+    data: Option<SpanData>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(not(feature = "otel"), allow(dead_code))]
+struct SpanData {
+    context: SpanContext,
+    parent_span_id: Option<[u8; 8]>,
+    name: String,
+    kind: SpanKind,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(&'static str, Value)>,
+    error: Option<String>,
+}
+
+/// Whether spans are being recorded, for skipping work that only goes into spans
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Starts a span, as a child of the innermost span on this thread if there is one
+pub fn span(name: impl Into<String>, kind: SpanKind) -> Span {
+    if !enabled() {
+        return Span { data: None };
+    }
+    let mut rng = rand::rng();
+    let parent = ACTIVE.with(|active| active.borrow().last().copied());
+    let context = SpanContext {
+        trace_id: parent.map_or_else(|| rng.random(), |parent| parent.trace_id),
+        span_id: rng.random(),
+    };
+    ACTIVE.with(|active| active.borrow_mut().push(context));
+    Span {
+        data: Some(SpanData {
+            context,
+            parent_span_id: parent.map(|parent| parent.span_id),
+            name: name.into(),
+            kind,
+            start: SystemTime::now(),
+            end: SystemTime::now(),
+            attributes: vec![],
+            error: None,
+        }),
+    }
+}
+
+impl Span {
+    pub fn set_attribute(&mut self, key: &'static str, value: impl Into<Value>) {
+        if let Some(data) = &mut self.data {
+            data.attributes.push((key, value.into()));
+        }
+    }
+
+    /// Marks the span as failed
+    pub fn set_error(&mut self, error: impl ToString) {
+        if let Some(data) = &mut self.data {
+            data.error = Some(error.to_string());
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some(mut data) = self.data.take() else {
+            return;
+        };
+        data.end = SystemTime::now();
+        ACTIVE.with(|active| {
+            let mut active = active.borrow_mut();
+            if let Some(i) = active
+                .iter()
+                .rposition(|context| context.span_id == data.context.span_id)
+            {
+                active.remove(i);
+            }
+        });
+        let mut finished = FINISHED.lock().unwrap();
+        if finished.len() < MAX_FINISHED {
+            finished.push(data);
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+mod exporter {
+    use anyhow::Result;
+    use log::{info, warn};
+    use reqwest::blocking::Client;
+    use std::sync::atomic::Ordering;
+    use std::sync::{Mutex, OnceLock};
+    use std::thread;
+
+    use serde_json::{json, Value};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::{SpanData, ENABLED, FINISHED};
+
+    /// How often the finished spans are exported
+    const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Where spans are exported to, set once exporting has been set up
+    static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+
+    struct Exporter {
+        http: Client,
+        url: String,
+        /// Held while exporting, so that a flush doesn't race the background thread
+        lock: Mutex<()>,
+    }
+
+    pub fn init(endpoint: &str) -> Result<()> {
+        let exporter = Exporter {
+            http: Client::builder().timeout(Duration::from_secs(10)).build()?,
+            url: format!("{}/v1/traces", endpoint.trim_end_matches('/')),
+            lock: Mutex::new(()),
+        };
+        info!("Exporting traces to {}", exporter.url);
+        if EXPORTER.set(exporter).is_err() {
+            return Ok(());
+        }
+        ENABLED.store(true, Ordering::Relaxed);
+        thread::spawn(|| loop {
+            thread::sleep(EXPORT_INTERVAL);
+            flush();
+        });
+        Ok(())
+    }
+
+    pub fn flush() {
+        let Some(exporter) = EXPORTER.get() else {
+            return;
+        };
+        let _lock = exporter.lock.lock().unwrap();
+        let spans = std::mem::take(&mut *FINISHED.lock().unwrap());
+        if spans.is_empty() {
+            return;
+        }
+        let result = exporter
+            .http
+            .post(&exporter.url)
+            .json(&export_request(&spans))
+            .send()
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            warn!("Failed to export {} spans: {}", spans.len(), e);
+        }
+    }
+
+    /// Encodes the spans as an OTLP export request
+    fn export_request(spans: &[SpanData]) -> Value {
+        let spans: Vec<Value> = spans
+            .iter()
+            .map(|span| {
+                let mut encoded = json!({
+                    "traceId": hex::encode(span.context.trace_id),
+                    "spanId": hex::encode(span.context.span_id),
+                    "name": span.name,
+                    "kind": span.kind as u8,
+                    "startTimeUnixNano": unix_nanos(span.start),
+                    "endTimeUnixNano": unix_nanos(span.end),
+                    "attributes": span
+                        .attributes
+                        .iter()
+                        .map(|(key, value)| attribute(key, value))
+                        .collect::<Vec<_>>(),
+                    "status": match &span.error {
+                        Some(error) => json!({ "code": 2, "message": error }),
+                        None => json!({ "code": 0 }),
+                    },
+                });
+                if let Some(parent_span_id) = span.parent_span_id {
+                    encoded["parentSpanId"] = hex::encode(parent_span_id).into();
+                }
+                encoded
+            })
+            .collect();
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [attribute("service.name", &"miniflux-notifier".into())],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "miniflux-notifier", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+
+    fn attribute(key: &str, value: &Value) -> Value {
+        let value = match value {
+            Value::Bool(value) => json!({ "boolValue": value }),
+            Value::Number(value) if value.is_i64() || value.is_u64() => {
+                // 64 bit integers are encoded as strings
+                json!({ "intValue": value.to_string() })
+            }
+            Value::Number(value) => json!({ "doubleValue": value }),
+            Value::String(value) => json!({ "stringValue": value }),
+            value => json!({ "stringValue": value.to_string() }),
+        };
+        json!({ "key": key, "value": value })
+    }
+
+    fn unix_nanos(time: SystemTime) -> String {
+        time.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0)
+            .to_string()
+    }
+}
+
+/// Exports the spans to the OTLP/HTTP endpoint of a collector, e.g. `http://localhost:4318`
+#[cfg(feature = "otel")]
+pub fn init(endpoint: &str) -> Result<()> {
+    exporter::init(endpoint)
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn init(_endpoint: &str) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Exporting traces requires building with the \"otel\" feature"
+    ))
+}
+
+/// Exports the spans that have ended so far, called before shutting down
+#[cfg(feature = "otel")]
+pub fn flush() {
+    exporter::flush()
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn flush() {}
@@ -0,0 +1,281 @@
+//! Rendering of the user-configurable notification text
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::borrow::Cow;
+
+use crate::filter::{matches_feed_or_category, Matcher};
+use crate::models::Entry;
+
+/// Templates for the notifications about entries from some feeds or categories, in place of
+/// the global ones
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeedTemplate {
+    #[serde(default)]
+    pub feeds: Vec<Matcher>,
+    #[serde(default)]
+    pub categories: Vec<Matcher>,
+    pub summary: Option<String>,
+    pub body: Option<String>,
+}
+
+impl FeedTemplate {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        matches_feed_or_category(&self.feeds, &self.categories, entry)
+    }
+}
+
+/// Renders `template`, replacing each `{placeholder}` with the corresponding field of `entry`.
+///
+/// The supported placeholders are:
+/// - `{source}`: the author of the entry, or the feed title if the entry has no author
+/// - `{feed}`: the feed title
+/// - `{author}`: the author of the entry
+/// - `{title}`: the entry title
+/// - `{category}`: the title of the feed's category, empty if it has none
+/// - `{url}`: the URL of the entry
+/// - `{published_relative}`: how long ago the entry was published, e.g. "8 min ago"
+/// - `{created_relative}`: how long ago the server fetched the entry, empty if the source
+///   doesn't tell
+/// - `{reading_time}`: the estimated reading time, e.g. "~6 min read", empty if the source
+///   doesn't estimate it
+/// - `{excerpt}`: the start of the entry's content as plain text, at most `excerpt_length`
+///   characters
+///
+/// Unknown placeholders are left untouched.
+pub fn render(template: &str, entry: &Entry, excerpt_length: usize) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        match placeholder(&rest[1..end], entry, excerpt_length) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+fn placeholder<'a>(name: &str, entry: &'a Entry, excerpt_length: usize) -> Option<Cow<'a, str>> {
+    let value = match name {
+        "source" => match entry.author.is_empty() {
+            true => &entry.feed.title,
+            false => &entry.author,
+        },
+        "feed" => &entry.feed.title,
+        "author" => &entry.author,
+        "title" => &entry.title,
+        "category" => entry
+            .feed
+            .category
+            .as_ref()
+            .map(|category| category.title.as_str())
+            .unwrap_or(""),
+        "url" => &entry.url,
+        "published_relative" => return Some(relative_time(entry.published_at).into()),
+        "created_relative" => {
+            return Some(
+                entry
+                    .created_at
+                    .map(relative_time)
+                    .unwrap_or_default()
+                    .into(),
+            )
+        }
+        "reading_time" => return Some(reading_time(entry).unwrap_or_default().into()),
+        "excerpt" => return Some(excerpt(&entry.content, excerpt_length).into()),
+        _ => return None,
+    };
+    Some(value.into())
+}
+
+/// Describes how long ago `time` was, in the largest unit that fits
+fn relative_time(time: DateTime<Utc>) -> String {
+    let minutes = (Utc::now() - time).num_minutes();
+    match minutes {
+        ..=0 => "just now".to_string(),
+        1..=59 => format!("{} min ago", minutes),
+        60..=1439 => format!("{} h ago", minutes / 60),
+        _ => format!("{} d ago", minutes / (60 * 24)),
+    }
+}
+
+/// Describes the estimated reading time of the entry, `None` if there is no estimate. Miniflux
+/// estimates 0 minutes when estimating is turned off.
+pub fn reading_time(entry: &Entry) -> Option<String> {
+    entry
+        .reading_time
+        .filter(|&minutes| minutes > 0)
+        .map(|minutes| format!("~{} min read", minutes))
+}
+
+/// Tags that separate words, unlike inline tags such as `<b>` which can appear within a word
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dt",
+    "figcaption",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "img",
+    "li",
+    "p",
+    "pre",
+    "section",
+    "td",
+    "th",
+    "tr",
+];
+
+/// Turns the HTML content of an entry into plain text of at most `max_len` characters.
+///
+/// Longer text is cut off at a word boundary and ends with an ellipsis. A `max_len` of 0
+/// gives an empty excerpt.
+pub fn excerpt(html: &str, max_len: usize) -> String {
+    if max_len == 0 {
+        return String::new();
+    }
+    let text = strip_html(html);
+    if text.chars().count() <= max_len {
+        return text;
+    }
+
+    // Leave room for the ellipsis
+    let cut: String = text.chars().take(max_len - 1).collect();
+    let cut = match cut.rfind(' ') {
+        Some(end) if end > 0 => &cut[..end],
+        _ => &cut,
+    };
+    format!(
+        "{}…",
+        cut.trim_end_matches(|c: char| c.is_whitespace() || c == ',')
+    )
+}
+
+/// Drops the tags along with the contents of scripts and styles, decodes the entities and
+/// collapses all whitespace into single spaces
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..start]));
+        rest = &rest[start + 1..];
+        let end = rest.find('>').unwrap_or(rest.len());
+        let closing = rest.starts_with('/');
+        let name: String = rest[..end]
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        rest = rest.get(end + 1..).unwrap_or("");
+
+        if !closing && (name == "script" || name == "style") {
+            let close = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(close) => &rest[close..],
+                None => "",
+            };
+        } else if BLOCK_TAGS.contains(&name.as_str()) {
+            text.push(' ');
+        }
+    }
+    text.push_str(&decode_entities(rest));
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        _ => {
+            let code = match name.strip_prefix("#x").or(name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            return char::from_u32(code);
+        }
+    };
+    Some(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_entry;
+
+    #[test]
+    fn category_placeholder() {
+        let entry = test_entry(1);
+        assert_eq!(
+            render("[{category}] {title}", &entry, 100),
+            "[News] Entry 1"
+        );
+    }
+
+    #[test]
+    fn category_placeholder_without_a_category() {
+        let mut entry = test_entry(1);
+        entry.feed.category = None;
+        assert_eq!(render("{category}|{title}", &entry, 100), "|Entry 1");
+    }
+}
@@ -0,0 +1,107 @@
+//! The `test-notify` subcommand, which sends a notification about a made-up entry so that the
+//! notifiers can be checked without waiting for a real one
+//!
+//! The actions of the notification work as usual, except that marking the entry as read or
+//! starring it only prints what would have been done to the server.
+
+use anyhow::Result;
+use chrono::Utc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::Settings;
+use crate::history::History;
+use crate::models::{Category, Entries, Entry, Feed, User};
+use crate::notify::{desktop, send_notification_batch, Notifier, Notifiers};
+use crate::snooze::Snoozes;
+use crate::source::{Error, FeedSource};
+
+/// How long to wait for an action on the notification before exiting
+const ACTION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Sends the test notification through the notifiers of each server and waits for an action on
+/// any desktop notifications
+pub fn run(servers: &[Settings]) -> Result<()> {
+    let client: Arc<dyn FeedSource> = Arc::new(TestSource);
+    for settings in servers {
+        let mut notifiers =
+            Notifiers::from_settings(settings, &client, &Snoozes::default(), &History::default())?;
+        send_notification_batch(&mut notifiers, vec![test_entry(settings)], settings)?;
+        notifiers.flush()?;
+        println!(
+            "Sent a test notification through {}",
+            settings
+                .notifiers
+                .iter()
+                .map(|kind| format!("{:?}", kind).to_lowercase())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    if desktop::pending_actions() > 0 {
+        println!("Waiting for an action on the notification, press Ctrl+C to stop");
+        let started = Instant::now();
+        while desktop::pending_actions() > 0 && started.elapsed() < ACTION_TIMEOUT {
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+    Ok(())
+}
+
+fn test_entry(settings: &Settings) -> Entry {
+    Entry {
+        id: 0,
+        title: "Test notification".to_string(),
+        author: "miniflux-notifier".to_string(),
+        hash: String::new(),
+        feed: Feed {
+            id: 0,
+            title: "Miniflux Notifier".to_string(),
+            category: Some(Category {
+                id: 0,
+                title: "Test".to_string(),
+            }),
+        },
+        url: settings.server.clone(),
+        published_at: Utc::now(),
+        created_at: Some(Utc::now()),
+        reading_time: Some(1),
+        content: "<p>If you can read this, notifications are set up right.</p>".to_string(),
+    }
+}
+
+/// A source only knowing the test entry, so that the actions on it don't reach the server
+struct TestSource;
+
+impl FeedSource for TestSource {
+    fn healthcheck(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn me(&self) -> Result<User, Error> {
+        Ok(User {
+            id: 0,
+            username: "test".to_string(),
+        })
+    }
+
+    fn unread_entries(&self, _published_after: Option<i64>) -> Result<Entries, Error> {
+        Ok(Entries {
+            total: 0,
+            entries: vec![],
+            not_modified: false,
+        })
+    }
+
+    fn mark_read(&self, _ids: &[u64]) -> Result<(), Error> {
+        println!("Would have marked the entry as read");
+        Ok(())
+    }
+
+    fn toggle_bookmark(&self, _id: u64) -> Result<(), Error> {
+        println!("Would have starred the entry");
+        Ok(())
+    }
+}
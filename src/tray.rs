@@ -0,0 +1,415 @@
+//! A tray icon showing the number of unread entries, with a menu to pause polling, mute the
+//! notifications, poll right away and open Miniflux
+//!
+//! The icon is a StatusNotifierItem with its menu exported over `com.canonical.dbusmenu`, as
+//! shown by KDE, the tray of Waybar and GNOME with the AppIndicator extension. It acts on the
+//! same state as the commands of the control socket.
+
+use anyhow::Result;
+
+use crate::config::Settings;
+
+/// Shows the tray icon until the process exits, leading to the servers in the settings
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn start(servers: &[Settings]) -> Result<()> {
+    sni::start(servers)
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub fn start(_servers: &[Settings]) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "The tray icon is only supported on Linux and BSD"
+    ))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod sni {
+    use anyhow::{Context, Result};
+    use log::{debug, error, warn};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use zbus::blocking::{Connection, ConnectionBuilder};
+    use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+    use zbus::{dbus_interface, SignalContext};
+
+    use crate::config::Settings;
+    use crate::control::{self, Request};
+    use crate::status_bar;
+
+    const ITEM_PATH: &str = "/StatusNotifierItem";
+    const MENU_PATH: &str = "/MenuBar";
+    /// How often the icon is brought up to date with the unread entries and the state
+    const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+    /// How long muting from the menu lasts
+    const MUTE_DURATION: Duration = Duration::from_secs(60 * 60);
+
+    const PAUSE: i32 = 1;
+    const MUTE: i32 = 2;
+    const POLL_NOW: i32 = 3;
+    const SEPARATOR: i32 = 4;
+    /// The items opening each server follow, in the order of the servers
+    const OPEN_SERVER: i32 = 10;
+
+    /// Bumped whenever the labels of the menu change, for the tray to ask for them again
+    static REVISION: AtomicU32 = AtomicU32::new(1);
+
+    pub fn start(servers: &[Settings]) -> Result<()> {
+        let servers: Vec<(String, String)> = servers
+            .iter()
+            .map(|settings| {
+                let name = settings.name.clone().unwrap_or(settings.server.clone());
+                (name, settings.server.trim_end_matches('/').to_string())
+            })
+            .collect();
+        let name = format!("org.kde.StatusNotifierItem-{}-1", std::process::id());
+        let connection = ConnectionBuilder::session()?
+            .name(name.as_str())?
+            .serve_at(ITEM_PATH, Item(servers.clone()))?
+            .serve_at(MENU_PATH, Menu(servers))?
+            .build()
+            .context("Failed to export the tray icon on the session bus")?;
+        thread::spawn(move || update(connection, name));
+        Ok(())
+    }
+
+    /// Registers the icon with the tray and keeps it up to date
+    fn update(connection: Connection, name: String) {
+        let mut registered = false;
+        let mut warned = false;
+        let mut last = None;
+        loop {
+            if !registered {
+                match register(&connection, &name) {
+                    Ok(()) => registered = true,
+                    // Trays can start after the notifier, so this is tried again until one does
+                    Err(e) if !warned => {
+                        warn!("No tray to show the icon in yet: {}", e);
+                        warned = true;
+                    }
+                    Err(e) => debug!("No tray to show the icon in yet: {}", e),
+                }
+            }
+            let current = (
+                status_bar::unread().map(|(total, _)| total),
+                control::is_paused(),
+                control::is_muted(),
+            );
+            if last != Some(current) {
+                if last.is_some_and(|(_, paused, muted)| (paused, muted) != (current.1, current.2))
+                {
+                    REVISION.fetch_add(1, Ordering::SeqCst);
+                }
+                if let Err(e) = signal_changes(&connection) {
+                    debug!("Failed to update the tray icon: {}", e);
+                }
+                last = Some(current);
+            }
+            thread::sleep(UPDATE_INTERVAL);
+        }
+    }
+
+    /// Tells the tray about the icon, which fails if there is no tray yet
+    fn register(connection: &Connection, name: &str) -> zbus::Result<()> {
+        connection.call_method(
+            Some("org.kde.StatusNotifierWatcher"),
+            "/StatusNotifierWatcher",
+            Some("org.kde.StatusNotifierWatcher"),
+            "RegisterStatusNotifierItem",
+            &(name,),
+        )?;
+        Ok(())
+    }
+
+    fn signal_changes(connection: &Connection) -> zbus::Result<()> {
+        let item = connection.object_server().interface::<_, Item>(ITEM_PATH)?;
+        let ctxt = item.signal_context();
+        zbus::block_on(async {
+            Item::new_title(ctxt).await?;
+            Item::new_tool_tip(ctxt).await?;
+            Item::new_icon(ctxt).await?;
+            Item::new_status(ctxt, status()).await
+        })?;
+        let menu = connection.object_server().interface::<_, Menu>(MENU_PATH)?;
+        zbus::block_on(Menu::layout_updated(
+            menu.signal_context(),
+            REVISION.load(Ordering::SeqCst),
+            0,
+        ))
+    }
+
+    fn title() -> String {
+        match status_bar::unread() {
+            Some((1, _)) => "1 unread entry".to_string(),
+            Some((total, _)) => format!("{} unread entries", total),
+            None => "Miniflux Notifier".to_string(),
+        }
+    }
+
+    /// Draws attention to the icon while there are unread entries, unless it is being held back
+    fn status() -> &'static str {
+        if held_back() {
+            return "Active";
+        }
+        match status_bar::unread() {
+            Some((total, _)) if total > 0 => "NeedsAttention",
+            _ => "Active",
+        }
+    }
+
+    fn held_back() -> bool {
+        control::is_paused() || control::is_muted()
+    }
+
+    fn open(url: &str) {
+        if let Err(e) = open::that_detached(url) {
+            error!("Failed to open {}: {}", url, e);
+        }
+    }
+
+    /// An icon name, the images of an icon by their width and height, a title and a text
+    type ToolTip = (String, Vec<(i32, i32, Vec<u8>)>, String, String);
+
+    /// The icon itself, with the servers it leads to by their names and URLs
+    struct Item(Vec<(String, String)>);
+
+    #[dbus_interface(name = "org.kde.StatusNotifierItem")]
+    impl Item {
+        #[dbus_interface(property)]
+        fn category(&self) -> &str {
+            "Communications"
+        }
+
+        #[dbus_interface(property)]
+        fn id(&self) -> &str {
+            "miniflux-notifier"
+        }
+
+        #[dbus_interface(property)]
+        fn title(&self) -> String {
+            title()
+        }
+
+        #[dbus_interface(property)]
+        fn status(&self) -> &str {
+            status()
+        }
+
+        #[dbus_interface(property)]
+        fn icon_name(&self) -> &str {
+            match held_back() {
+                true => "notifications-disabled",
+                false => "mail-read",
+            }
+        }
+
+        #[dbus_interface(property)]
+        fn attention_icon_name(&self) -> &str {
+            "mail-unread"
+        }
+
+        #[dbus_interface(property)]
+        fn tool_tip(&self) -> ToolTip {
+            let mut lines = status_bar::unread()
+                .map(|(_, lines)| lines)
+                .unwrap_or_default();
+            if control::is_paused() {
+                lines.push("Polling is paused".to_string());
+            }
+            if control::is_muted() {
+                lines.push("Muted".to_string());
+            }
+            (String::new(), vec![], title(), lines.join("\n"))
+        }
+
+        #[dbus_interface(property)]
+        fn item_is_menu(&self) -> bool {
+            false
+        }
+
+        #[dbus_interface(property)]
+        fn menu(&self) -> ObjectPath<'_> {
+            ObjectPath::from_static_str_unchecked(MENU_PATH)
+        }
+
+        /// Clicking the icon opens the unread entries of the first server
+        fn activate(&self, _x: i32, _y: i32) {
+            if let Some((_, url)) = self.0.first() {
+                open(&format!("{}/unread", url));
+            }
+        }
+
+        fn secondary_activate(&self, _x: i32, _y: i32) {}
+
+        fn scroll(&self, _delta: i32, _orientation: &str) {}
+
+        #[dbus_interface(signal)]
+        async fn new_title(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+        #[dbus_interface(signal)]
+        async fn new_icon(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+        #[dbus_interface(signal)]
+        async fn new_tool_tip(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+        #[dbus_interface(signal)]
+        async fn new_status(ctxt: &SignalContext<'_>, status: &str) -> zbus::Result<()>;
+    }
+
+    /// The properties and children of a menu item, as dbusmenu lays them out
+    type Layout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+    /// The menu of the icon, with the servers it can open by their names and URLs
+    struct Menu(Vec<(String, String)>);
+
+    impl Menu {
+        fn items(&self) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+            let label = |label: &str| HashMap::from([("label".to_string(), value(label))]);
+            let mut items = vec![
+                (
+                    PAUSE,
+                    label(match control::is_paused() {
+                        true => "Resume polling",
+                        false => "Pause polling",
+                    }),
+                ),
+                (
+                    MUTE,
+                    label(match control::is_muted() {
+                        true => "Unmute",
+                        false => "Mute for an hour",
+                    }),
+                ),
+                (POLL_NOW, label("Poll now")),
+                (
+                    SEPARATOR,
+                    HashMap::from([("type".to_string(), value("separator"))]),
+                ),
+            ];
+            for (i, (name, _)) in self.0.iter().enumerate() {
+                let text = match self.0.len() {
+                    1 => "Open Miniflux".to_string(),
+                    _ => format!("Open {}", name),
+                };
+                items.push((OPEN_SERVER + i as i32, label(&text)));
+            }
+            items
+        }
+
+        fn clicked(&self, id: i32) {
+            match id {
+                PAUSE if control::is_paused() => control::handle(Request::Resume),
+                PAUSE => control::handle(Request::Pause),
+                MUTE if control::is_muted() => control::handle(Request::Unmute),
+                MUTE => control::handle(Request::Mute(MUTE_DURATION)),
+                POLL_NOW => control::handle(Request::PollNow),
+                _ => {
+                    if let Some((_, url)) = usize::try_from(id - OPEN_SERVER)
+                        .ok()
+                        .and_then(|i| self.0.get(i))
+                    {
+                        open(&format!("{}/unread", url));
+                    }
+                    return;
+                }
+            };
+            REVISION.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[dbus_interface(name = "com.canonical.dbusmenu")]
+    impl Menu {
+        #[dbus_interface(property)]
+        fn version(&self) -> u32 {
+            3
+        }
+
+        #[dbus_interface(property)]
+        fn text_direction(&self) -> &str {
+            "ltr"
+        }
+
+        #[dbus_interface(property)]
+        fn status(&self) -> &str {
+            "normal"
+        }
+
+        #[dbus_interface(property)]
+        fn icon_theme_path(&self) -> Vec<String> {
+            vec![]
+        }
+
+        /// The menu is flat, so the whole of it is laid out whatever part is asked for
+        fn get_layout(
+            &self,
+            _parent_id: i32,
+            _recursion_depth: i32,
+            _property_names: Vec<String>,
+        ) -> (u32, Layout) {
+            let children = self
+                .items()
+                .into_iter()
+                .map(|(id, properties)| {
+                    let item: Layout = (id, properties, vec![]);
+                    OwnedValue::from(Value::from(item))
+                })
+                .collect();
+            let root = HashMap::from([("children-display".to_string(), value("submenu"))]);
+            (REVISION.load(Ordering::SeqCst), (0, root, children))
+        }
+
+        fn get_group_properties(
+            &self,
+            ids: Vec<i32>,
+            _property_names: Vec<String>,
+        ) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+            self.items()
+                .into_iter()
+                .filter(|(id, _)| ids.is_empty() || ids.contains(id))
+                .collect()
+        }
+
+        fn get_property(&self, id: i32, name: &str) -> OwnedValue {
+            self.items()
+                .into_iter()
+                .find(|(item, _)| *item == id)
+                .and_then(|(_, mut properties)| properties.remove(name))
+                .unwrap_or_else(|| value(""))
+        }
+
+        fn event(&self, id: i32, event_id: &str, _data: OwnedValue, _timestamp: u32) {
+            if event_id == "clicked" {
+                self.clicked(id);
+            }
+        }
+
+        fn event_group(&self, events: Vec<(i32, String, OwnedValue, u32)>) -> Vec<i32> {
+            for (id, event_id, _, _) in events {
+                if event_id == "clicked" {
+                    self.clicked(id);
+                }
+            }
+            vec![]
+        }
+
+        fn about_to_show(&self, _id: i32) -> bool {
+            false
+        }
+
+        fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+            (vec![], vec![])
+        }
+
+        #[dbus_interface(signal)]
+        async fn layout_updated(
+            ctxt: &SignalContext<'_>,
+            revision: u32,
+            parent: i32,
+        ) -> zbus::Result<()>;
+    }
+
+    fn value(s: &str) -> OwnedValue {
+        Value::from(s).into()
+    }
+}
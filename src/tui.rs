@@ -0,0 +1,263 @@
+//! The `tui` subcommand, a terminal browser of the unread entries for sessions without desktop
+//! notifications
+//!
+//! The terminal is driven with `stty` and ANSI escape sequences, so this only works in Unix
+//! terminals.
+
+use anyhow::{anyhow, Context, Result};
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use crate::config::Settings;
+use crate::models::Entry;
+use crate::source::{self, FeedSource};
+
+const HELP: &str = "j/k: move  o: open  r: mark as read  s: star  q: quit";
+
+/// Lists the unread entries of each server grouped by feed, until quitting
+pub fn run(servers: &[Settings]) -> Result<()> {
+    let mut clients = vec![];
+    let mut items = vec![];
+    for (server, settings) in servers.iter().enumerate() {
+        let client = source::from_settings(settings)?;
+        let mut entries = client.unread_entries(None)?.entries;
+        // Newest first within each feed
+        entries.sort_by(|a, b| {
+            a.feed
+                .title
+                .cmp(&b.feed.title)
+                .then(b.published_at.cmp(&a.published_at))
+        });
+        let name =
+            (servers.len() > 1).then(|| settings.name.clone().unwrap_or(settings.server.clone()));
+        items.extend(entries.into_iter().map(|entry| Item {
+            server,
+            group: match &name {
+                Some(name) => format!("{}: {}", name, entry.feed.title),
+                None => entry.feed.title.clone(),
+            },
+            entry,
+            starred: false,
+        }));
+        clients.push(client);
+    }
+    if items.is_empty() {
+        println!("No unread entries");
+        return Ok(());
+    }
+
+    let _terminal = RawTerminal::enter()?;
+    let mut browser = Browser {
+        clients,
+        items,
+        selected: 0,
+        top: 0,
+        status: HELP.to_string(),
+    };
+    let mut stdin = io::stdin().lock();
+    let mut buf = [0; 8];
+    loop {
+        browser.draw()?;
+        let len = stdin.read(&mut buf)?;
+        match &buf[..len] {
+            b"q" | b"\x1b" | b"\x03" => break,
+            b"j" | b"\x1b[B" => browser.select_next(),
+            b"k" | b"\x1b[A" => browser.select_previous(),
+            b"o" => browser.open(),
+            b"r" => browser.mark_read(),
+            b"s" => browser.star(),
+            _ => {}
+        }
+        if browser.items.is_empty() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct Item {
+    /// The index of the server the entry is from
+    server: usize,
+    /// The heading the entry is listed under
+    group: String,
+    entry: Entry,
+    starred: bool,
+}
+
+struct Browser {
+    clients: Vec<Arc<dyn FeedSource>>,
+    items: Vec<Item>,
+    selected: usize,
+    /// The first line of the list that is in view
+    top: usize,
+    /// Shown at the bottom, either the keys or how the last action went
+    status: String,
+}
+
+impl Browser {
+    fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.items.len() - 1);
+    }
+
+    fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    fn client(&self) -> &dyn FeedSource {
+        self.clients[self.items[self.selected].server].as_ref()
+    }
+
+    fn open(&mut self) {
+        let url = &self.items[self.selected].entry.url;
+        self.status = match open::that_detached(url) {
+            Ok(()) => format!("Opened {}", url),
+            Err(e) => format!("Failed to open the entry: {}", e),
+        };
+    }
+
+    fn mark_read(&mut self) {
+        let id = self.items[self.selected].entry.id;
+        self.status = match self.client().mark_read(&[id]) {
+            Ok(()) => {
+                let item = self.items.remove(self.selected);
+                self.selected = self.selected.min(self.items.len().saturating_sub(1));
+                format!("Marked \"{}\" as read", item.entry.title)
+            }
+            Err(e) => format!("Failed to mark the entry as read: {}", e),
+        };
+    }
+
+    fn star(&mut self) {
+        let id = self.items[self.selected].entry.id;
+        self.status = match self.client().toggle_bookmark(id) {
+            Ok(()) => {
+                let item = &mut self.items[self.selected];
+                item.starred = !item.starred;
+                match item.starred {
+                    true => format!("Starred \"{}\"", item.entry.title),
+                    false => format!("Unstarred \"{}\"", item.entry.title),
+                }
+            }
+            Err(e) => format!("Failed to star the entry: {}", e),
+        };
+    }
+
+    /// The lines of the list along with whether they are headings, and the index of the line
+    /// of the selected entry
+    fn lines(&self) -> (Vec<(String, bool)>, usize) {
+        let mut lines = vec![];
+        let mut selected_line = 0;
+        for (i, item) in self.items.iter().enumerate() {
+            if i == 0 || self.items[i - 1].group != item.group {
+                if i > 0 {
+                    lines.push((String::new(), false));
+                }
+                lines.push((item.group.clone(), true));
+            }
+            if i == self.selected {
+                selected_line = lines.len();
+            }
+            let star = if item.starred { "*" } else { " " };
+            lines.push((format!(" {} {}", star, item.entry.title), false));
+        }
+        (lines, selected_line)
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        let (rows, columns) = terminal_size().unwrap_or((24, 80));
+        let height = rows.saturating_sub(1).max(1);
+        let (lines, selected_line) = self.lines();
+        // Scrolls just enough to keep the selected entry in view, along with the heading above
+        // it when moving up
+        if selected_line < self.top + 1 {
+            self.top = selected_line.saturating_sub(1);
+        }
+        if selected_line >= self.top + height {
+            self.top = selected_line + 1 - height;
+        }
+
+        let mut out = String::from("\x1b[H\x1b[2J");
+        for (i, (line, heading)) in lines.iter().enumerate().skip(self.top).take(height) {
+            let line = truncate(line, columns);
+            if i == selected_line {
+                out.push_str(&format!("\x1b[7m{}\x1b[0m\r\n", line));
+            } else if *heading {
+                out.push_str(&format!("\x1b[1m{}\x1b[0m\r\n", line));
+            } else {
+                out.push_str(&format!("{}\r\n", line));
+            }
+        }
+        out.push_str(&format!(
+            "\x1b[{};1H\x1b[2m{}\x1b[0m",
+            rows,
+            truncate(&self.status, columns)
+        ));
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(out.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Cuts the line down to the width of the terminal, leaving out control characters so that
+/// titles from feeds can't mess with the terminal
+fn truncate(line: &str, columns: usize) -> String {
+    let line: String = line.chars().filter(|c| !c.is_control()).collect();
+    match line.chars().count() > columns {
+        true => line
+            .chars()
+            .take(columns.saturating_sub(1))
+            .chain(['…'])
+            .collect(),
+        false => line,
+    }
+}
+
+/// The number of rows and columns of the terminal
+fn terminal_size() -> Option<(usize, usize)> {
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    let size = String::from_utf8(output.stdout).ok()?;
+    let (rows, columns) = size.trim().split_once(' ')?;
+    let size = (rows.parse().ok()?, columns.parse().ok()?);
+    // Terminals that don't know their size say 0 0
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+/// Puts the terminal into raw mode on the alternate screen, restoring it when dropped
+struct RawTerminal {
+    /// The settings of the terminal before, as printed by `stty -g`
+    saved: String,
+}
+
+impl RawTerminal {
+    fn enter() -> Result<Self> {
+        let output = Command::new("stty")
+            .arg("-g")
+            // `stty` looks at the terminal on its stdin, which isn't inherited by default when
+            // capturing the output
+            .stdin(Stdio::inherit())
+            .output()
+            .context("Failed to run stty")?;
+        if !output.status.success() {
+            return Err(anyhow!("The TUI needs to be run in a terminal"));
+        }
+        let saved = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Command::new("stty").args(["raw", "-echo"]).status()?;
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Self { saved })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = Command::new("stty").arg(&self.saved).status();
+    }
+}
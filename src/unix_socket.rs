@@ -0,0 +1,231 @@
+//! Reaching a Miniflux server listening on a Unix socket, for servers on the same computer
+//! that don't listen on a port
+//!
+//! The HTTP client only speaks TCP, so requests to the socket are written out by hand as
+//! HTTP/1.1, one connection each, and the answers are read back into the client's responses.
+//! Nothing listens on a port, so the permissions of the socket still decide who can use it.
+
+use anyhow::Result;
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::HeaderMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::source::Error;
+
+/// The Unix socket of a server, along with what every request to it is sent with
+#[derive(Debug, Clone)]
+pub struct UnixSocket {
+    path: PathBuf,
+    /// The headers the HTTP client would otherwise add, such as the credentials
+    headers: HeaderMap,
+    timeout: Duration,
+}
+
+impl UnixSocket {
+    #[cfg(unix)]
+    pub fn new(path: &Path, headers: HeaderMap, timeout: Duration) -> Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            headers,
+            timeout,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(_path: &Path, _headers: HeaderMap, _timeout: Duration) -> Result<Self> {
+        Err(anyhow::anyhow!("Unix sockets are only supported on Unix"))
+    }
+
+    /// Sends the request to the socket rather than to the host in its URL
+    #[cfg(unix)]
+    pub fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixStream;
+
+        let request = request.build()?;
+        let mut stream = UnixStream::connect(&self.path).map_err(Error::Socket)?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|()| stream.set_write_timeout(Some(self.timeout)))
+            .map_err(Error::Socket)?;
+
+        let mut headers = self.headers.clone();
+        headers.extend(request.headers().clone());
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .unwrap_or(&[]);
+        let url = request.url();
+        let target = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            request.method(),
+            target,
+            url.host_str().unwrap_or("localhost"),
+            body.len()
+        )
+        .into_bytes();
+        for (name, value) in &headers {
+            head.extend(format!("{}: ", name).as_bytes());
+            head.extend(value.as_bytes());
+            head.extend(b"\r\n");
+        }
+        head.extend(b"\r\n");
+        stream
+            .write_all(&head)
+            .and_then(|()| stream.write_all(body))
+            .map_err(Error::Socket)?;
+
+        // The server closes the connection after answering, as asked to
+        let mut answer = vec![];
+        stream.read_to_end(&mut answer).map_err(Error::Socket)?;
+        parse_response(&answer)
+            .map(Response::from)
+            .map_err(Error::Socket)
+    }
+
+    #[cfg(not(unix))]
+    pub fn send(&self, _request: RequestBuilder) -> Result<Response, Error> {
+        unreachable!("Unix sockets can't be set up on this platform")
+    }
+}
+
+/// Reads an HTTP/1.1 response, whose body is either chunked or runs until the end
+fn parse_response(answer: &[u8]) -> std::io::Result<http::Response<Vec<u8>>> {
+    let malformed = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "the server sent a malformed response",
+        )
+    };
+    let head_len = answer
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(malformed)?;
+    let head = std::str::from_utf8(&answer[..head_len]).map_err(|_| malformed())?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(malformed)?;
+
+    let mut response = http::Response::builder().status(status);
+    let mut chunked = false;
+    let mut content_length = None;
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or_else(malformed)?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+            continue;
+        }
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(value.parse::<usize>().map_err(|_| malformed())?);
+        }
+        response = response.header(name, value);
+    }
+
+    let body = &answer[head_len + 4..];
+    let body = match (chunked, content_length) {
+        (true, _) => dechunk(body).ok_or_else(malformed)?,
+        (false, Some(len)) => body.get(..len).ok_or_else(malformed)?.to_vec(),
+        (false, None) => body.to_vec(),
+    };
+    response.body(body).map_err(|_| malformed())
+}
+
+/// The body sent in chunks, `None` if it ends before the last chunk
+fn dechunk(mut chunks: &[u8]) -> Option<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let line_len = chunks.windows(2).position(|window| window == b"\r\n")?;
+        let size = std::str::from_utf8(&chunks[..line_len]).ok()?;
+        // Chunk extensions follow the size after a semicolon
+        let size = size.split(';').next()?.trim();
+        let size = usize::from_str_radix(size, 16).ok()?;
+        chunks = &chunks[line_len + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend(chunks.get(..size)?);
+        chunks = chunks.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_with_a_length() {
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}\r\n",
+        )
+        .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "application/json");
+        assert_eq!(response.body(), b"{}");
+    }
+
+    #[test]
+    fn chunked_responses() {
+        let response = parse_response(
+            b"HTTP/1.1 304 Not Modified\r\nTransfer-Encoding: chunked\r\n\r\n\
+              3\r\n{\"a\r\n4;ext=1\r\n\": 1\r\n1\r\n}\r\n0\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(response.status(), 304);
+        assert!(response.headers().get("transfer-encoding").is_none());
+        assert_eq!(response.body(), b"{\"a\": 1}");
+    }
+
+    #[test]
+    fn cut_off_responses_are_rejected() {
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 2").is_err());
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\n{}").is_err());
+        assert!(
+            parse_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nab")
+                .is_err()
+        );
+        assert!(parse_response(b"garbage\r\n\r\n").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn requests_go_over_the_socket() {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixListener;
+
+        let dir = std::env::temp_dir().join(format!("miniflux-notifier-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("miniflux.sock");
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![0; 4096];
+            let len = stream.read(&mut request).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ntrue")
+                .unwrap();
+            String::from_utf8_lossy(&request[..len]).to_string()
+        });
+
+        let headers =
+            HeaderMap::from_iter([("x-auth-token".parse().unwrap(), "secret".parse().unwrap())]);
+        let socket = UnixSocket::new(&path, headers, Duration::from_secs(5)).unwrap();
+        let request = reqwest::blocking::Client::new().get("http://localhost/v1/me?a=1");
+        let response = socket.send(request).unwrap();
+        assert!(response.json::<bool>().unwrap());
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET /v1/me?a=1 HTTP/1.1\r\n"));
+        assert!(request.contains("x-auth-token: secret\r\n"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+//! Receiving new entries from Miniflux webhooks instead of polling for them
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, KeyInit, Mac};
+use log::{debug, error, info, warn};
+use sha2::Sha256;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tiny_http::{Request, Response, Server};
+
+use crate::models::{Entry, WebhookNewEntries};
+
+/// The largest request body that is accepted
+const MAX_BODY_LEN: u64 = 16 * 1024 * 1024;
+
+/// Listens on `addr` for Miniflux webhook requests and calls `on_entries` with the entries of
+/// every `new_entries` event, until a shutdown is requested.
+///
+/// `on_entries` is also called with no entries whenever no request arrived for a short while,
+/// so that it gets a chance to do any work that is due.
+///
+/// Requests whose `X-Miniflux-Signature` header isn't a valid HMAC-SHA256 of the body using
+/// `secret` are rejected.
+pub fn serve(
+    addr: &str,
+    secret: &str,
+    shutdown: &AtomicBool,
+    mut on_entries: impl FnMut(Vec<Entry>),
+) -> Result<()> {
+    let server = Server::http(addr).map_err(|e| anyhow!("Failed to listen on {}: {}", addr, e))?;
+    info!("Listening for webhook events on {}", addr);
+
+    while !shutdown.load(Ordering::Relaxed) {
+        let Some(mut request) = server.recv_timeout(Duration::from_millis(250))? else {
+            on_entries(vec![]);
+            continue;
+        };
+
+        let (status, entries) = match handle(&mut request, secret) {
+            Ok(entries) => (204, entries),
+            Err((status, e)) => {
+                warn!("Rejected webhook request: {}", e);
+                (status, vec![])
+            }
+        };
+        if let Err(e) = request.respond(Response::empty(status)) {
+            error!("{:?}", e);
+        }
+        on_entries(entries);
+    }
+
+    Ok(())
+}
+
+/// Verifies and parses a webhook request, returning the HTTP status to reject it with on
+/// failure
+fn handle(request: &mut Request, secret: &str) -> Result<Vec<Entry>, (u16, String)> {
+    let mut body = vec![];
+    request
+        .as_reader()
+        .take(MAX_BODY_LEN)
+        .read_to_end(&mut body)
+        .map_err(|e| (400, e.to_string()))?;
+
+    let signature =
+        header(request, "X-Miniflux-Signature").ok_or((401, "missing signature".to_string()))?;
+    if !verify_signature(secret, &body, &signature) {
+        return Err((401, "invalid signature".to_string()));
+    }
+
+    let event_type = header(request, "X-Miniflux-Event-Type").unwrap_or_default();
+    if event_type != "new_entries" {
+        debug!("Ignoring webhook event {:?}", event_type);
+        return Ok(vec![]);
+    }
+
+    let event: WebhookNewEntries =
+        serde_json::from_slice(&body).map_err(|e| (400, e.to_string()))?;
+    Ok(event.into_entries())
+}
+
+fn header(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.to_string())
+}
+
+/// Checks that `signature` is the hex encoded HMAC-SHA256 of `body`
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature.trim()) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}